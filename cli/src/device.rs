@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::*;
+use core::result::Result::Ok;
+use log::*;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{Instant, timeout};
+use pod_core::context::Ctx;
+use pod_core::controller::*;
+use pod_core::dispatch::*;
+use pod_core::dump::ProgramsDump;
+use pod_core::edit::EditBuffer;
+use pod_core::event::*;
+use pod_core::midi::MidiMessage;
+use pod_core::midi_io::{BoxedMidiIn, BoxedMidiOut};
+use pod_core::model::{Config, Control, VirtualSelect};
+use pod_modules::handler_for_config;
+
+const APP_EVENT_CHANNEL_CAPACITY: usize = 512;
+
+/// How long to wait for the device to start responding to a request
+const RESPONSE_TIMEOUT: Duration = Duration::from_millis(3000);
+/// A request is considered complete when there was no activity on the
+/// app event bus for this long
+const QUIET_TIMEOUT: Duration = Duration::from_millis(1000);
+/// Upper limit for any request, e.g. a slow "all programs" dump
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A connected device driven by the same app event bus and handler
+/// dispatch as the GUI, but without a user interface
+pub struct Device {
+    pub config: &'static Config,
+    pub controller: Arc<Mutex<Controller>>,
+    pub edit: Arc<Mutex<EditBuffer>>,
+    pub dump: Arc<Mutex<ProgramsDump>>,
+    app_event_tx: broadcast::Sender<AppEvent>,
+}
+
+fn ui_controls() -> HashMap<String, Control> {
+    ["midi_channel", "program", "program:prev"].iter()
+        .map(|name| (name.to_string(), VirtualSelect::default().into()))
+        .collect()
+}
+
+impl Device {
+    pub fn start(midi_in: BoxedMidiIn, midi_out: BoxedMidiOut, midi_channel: u8,
+                 config: &'static Config) -> Result<Self> {
+        let handler = handler_for_config(config)
+            .with_context(|| format!("No handler for config {:?}", config.name))?;
+
+        let (app_event_tx, app_event_rx) =
+            broadcast::channel::<AppEvent>(APP_EVENT_CHANNEL_CAPACITY);
+
+        let edit = Arc::new(Mutex::new(EditBuffer::new(config)));
+        let dump = Arc::new(Mutex::new(ProgramsDump::new(config)));
+        let controller = edit.lock().unwrap().controller();
+
+        let ui_controller = Arc::new(Mutex::new(Controller::new(ui_controls())));
+        ui_controller.set("program", Program::ManualMode.into(), StoreOrigin::NONE);
+        ui_controller.set("program:prev", Program::ManualMode.into(), StoreOrigin::NONE);
+
+        let ctx = Ctx {
+            config,
+            handler,
+            controller: controller.clone(),
+            edit: edit.clone(),
+            dump: dump.clone(),
+            reroute: BufferReroute::new(),
+            ui_controller,
+            app_event_tx: app_event_tx.clone()
+        };
+        ctx.set_midi_channel(midi_channel);
+
+        start_controller_rx(controller.clone(), app_event_tx.clone());
+        start_midi_in(midi_in, app_event_tx.clone());
+        start_midi_out(midi_out, app_event_tx.subscribe());
+        start_app_events(ctx, app_event_tx.clone(), app_event_rx);
+
+        Ok(Self { config, controller, edit, dump, app_event_tx })
+    }
+
+    pub fn send(&self, event: AppEvent) {
+        self.app_event_tx.send_or_warn(event);
+    }
+
+    /// Run `f`, which is expected to send requests to the device, and wait
+    /// until the app event bus settles. If `reply` is set, the device must
+    /// respond with at least one MIDI message. Returns all app events seen
+    /// while waiting.
+    pub async fn run<F>(&self, f: F, reply: bool) -> Result<Vec<AppEvent>>
+        where F: FnOnce(&Self)
+    {
+        let mut rx = self.app_event_tx.subscribe();
+        f(self);
+
+        let start = Instant::now();
+        let mut events = vec![];
+        let mut replied = false;
+        loop {
+            let wait = if reply && !replied { RESPONSE_TIMEOUT } else { QUIET_TIMEOUT };
+            match timeout(wait, rx.recv()).await {
+                Ok(Ok(event)) => {
+                    replied |= matches!(event, AppEvent::MidiIn(_));
+                    events.push(event);
+                }
+                Ok(Err(RecvError::Lagged(n))) => {
+                    warn!("App event bus lagged: {}", n);
+                }
+                Ok(Err(RecvError::Closed)) => {
+                    bail!("App event bus closed");
+                }
+                Err(_) if reply && !replied => {
+                    bail!("No response from the device");
+                }
+                Err(_) => {
+                    break;
+                }
+            }
+            if start.elapsed() > REQUEST_TIMEOUT {
+                bail!("Request timed out");
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+/// Forward edit buffer controller changes to the app event bus
+fn start_controller_rx(controller: Arc<Mutex<Controller>>, app_event_tx: broadcast::Sender<AppEvent>) {
+    let (tx, mut rx) = broadcast::channel::<Event<String,u16>>(APP_EVENT_CHANNEL_CAPACITY);
+    controller.broadcast(Some(tx));
+
+    tokio::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(Event { key, value, origin, .. }) => {
+                    let e = ControlChangeEvent { name: key, value, origin };
+                    app_event_tx.send_or_warn(AppEvent::ControlChange(e));
+                }
+                Err(RecvError::Closed) => { break; }
+                Err(RecvError::Lagged(_)) => {}
+            }
+        }
+    });
+}
+
+fn start_midi_in(mut midi_in: BoxedMidiIn, app_event_tx: broadcast::Sender<AppEvent>) {
+    tokio::spawn(async move {
+        info!("MIDI in thread start");
+        while let Some(bytes) = midi_in.recv().await {
+            app_event_tx.send_or_warn(AppEvent::MidiIn(bytes));
+        }
+        midi_in.close();
+        info!("MIDI in thread finish");
+    });
+}
+
+fn start_midi_out(mut midi_out: BoxedMidiOut, mut app_event_rx: broadcast::Receiver<AppEvent>) {
+    tokio::spawn(async move {
+        info!("MIDI out thread start");
+        loop {
+            match app_event_rx.recv().await {
+                Ok(AppEvent::MidiOut(bytes)) => {
+                    midi_out.send(&bytes)
+                        .unwrap_or_else(|e| error!("MIDI OUT thread tx error: {}", e));
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(n)) => {
+                    error!("MIDI OUT thread rx lagged: {}", n);
+                }
+                Err(RecvError::Closed) => { break; }
+            }
+        }
+        midi_out.close();
+        info!("MIDI out thread finish");
+    });
+}
+
+fn start_app_events(ctx: Ctx, app_event_tx: broadcast::Sender<AppEvent>,
+                    mut app_event_rx: broadcast::Receiver<AppEvent>) {
+    tokio::spawn(async move {
+        loop {
+            let msg = match app_event_rx.recv().await {
+                Ok(msg) => { msg }
+                Err(RecvError::Closed) => {
+                    info!("App event bus closed");
+                    return;
+                }
+                Err(RecvError::Lagged(n)) => {
+                    error!("App event bus lagged: {}", n);
+                    continue;
+                }
+            };
+            debug!("== {:?}", msg);
+
+            // execute device-specific handlers
+            dispatch(&ctx, &msg);
+
+            // message conversion
+            match &msg {
+                AppEvent::MidiIn(bytes) => {
+                    match MidiMessage::from_bytes(bytes.clone()) {
+                        Ok(msg) => app_event_tx.send_or_warn(AppEvent::MidiMsgIn(msg)),
+                        Err(e) => error!("{}", e)
+                    }
+                }
+                AppEvent::MidiMsgOut(msg) => {
+                    match MidiMessage::to_bytes(msg) {
+                        Ok(bytes) => app_event_tx.send_or_warn(AppEvent::MidiOut(bytes)),
+                        Err(e) => error!("{}", e)
+                    }
+                }
+                AppEvent::Notification(event) => {
+                    info!("{}", event.msg);
+                }
+                _ => {}
+            }
+        }
+    });
+}
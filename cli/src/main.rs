@@ -0,0 +1,342 @@
+mod device;
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use anyhow::*;
+use clap::{Parser, Subcommand};
+use core::result::Result::Ok;
+use log::*;
+use pod_core::bank::Bank;
+use pod_core::config::{config_for_str, configs};
+use pod_core::definition::register_user_definitions;
+use pod_core::controller::*;
+use pod_core::diff::format_control_value;
+use pod_core::event::*;
+use pod_core::midi::{channel_from_arg, Channel, MidiMessage};
+use pod_core::midi_io::*;
+use pod_core::model::{AbstractControl, Config};
+use pod_core::program_id_string;
+use pod_modules::{handler_for_config, register_modules};
+use pod_modules::usb;
+use pod_modules::usb::{autodetect_supported, start_usb, usb_list_devices, usb_open_addr};
+use crate::device::Device;
+
+#[derive(Parser)]
+#[clap(name = "pod-cli")]
+/// Command-line access to Line6 POD devices
+pub struct Opts {
+    #[clap(short, long)]
+    /// Select the MIDI port to be connected as input. <INPUT> must be an
+    /// integer index of a MIDI input port present on this system. On Linux,
+    /// this can also be an ALSA <client>:<port> pair, such as "20:0".
+    /// If both `-i` and `-o` are provided, port autodetect will be skipped.
+    pub input: Option<String>,
+
+    #[clap(short, long)]
+    /// Select the MIDI port to be connected as output. <OUTPUT> must be an
+    /// integer index of a MIDI output port present on this system. On Linux,
+    /// this can also be an ALSA <client>:<port> pair, such as "20:0".
+    /// If both `-i` and `-o` are provided, port autodetect will be skipped.
+    pub output: Option<String>,
+
+    #[clap(short, long)]
+    /// Select the MIDI channel the POD is configured on. 0 means "omni" mode,
+    /// values 1 - 16 configure specific channel.
+    pub channel: Option<u8>,
+
+    /// Select the USB device to be connected as MIDI input/output. <USB> must be
+    /// an integer index of a recognized USB device present on this system. This
+    /// can also be an <bus>:<address> pair, such as "5:8".
+    #[cfg(feature = "usb")]
+    #[clap(short, long)]
+    pub usb: Option<String>,
+
+    #[cfg(not(feature = "usb"))]
+    #[clap(skip)]
+    pub usb: Option<String>,
+
+    #[clap(short, long)]
+    /// Select the model of the device. <MODEL> must be either an
+    /// integer index of a supported device model or a string name
+    /// of the model in question. If omitted, the device model will
+    /// be detected.
+    pub model: Option<String>,
+
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// List device models, MIDI ports and USB devices
+    ListPorts,
+    /// Detect the connected device
+    Detect,
+    /// Save all programs of the device to a bank file
+    DumpBank {
+        /// Bank file to write, standard output if omitted
+        file: Option<PathBuf>,
+    },
+    /// Restore all programs of the device from a bank file
+    RestoreBank {
+        /// Bank file to read
+        file: PathBuf,
+    },
+    /// Print the value of an edit buffer control, or all controls
+    Get {
+        /// Control name
+        control: Option<String>,
+    },
+    /// Set the value of an edit buffer control
+    Set {
+        /// Control name
+        control: String,
+        /// Raw control value
+        value: u16,
+    },
+    /// Switch the device to a program, such as "1A" or "5"
+    Program {
+        /// Program id as shown in the UI or a program number starting from 1
+        program: String,
+    },
+}
+
+fn list_ports() {
+    let tab = "    ";
+
+    println!("Device models (-m):");
+    for (i, c) in configs().iter().enumerate() {
+        println!("{}[{}] {}", tab, i, &c.name);
+    }
+    println!();
+    println!("MIDI input ports (-i):");
+    for (i, n) in MidiInPort::ports().ok().unwrap_or_default().iter().enumerate() {
+        println!("{}[{}] {}", tab, i, n);
+    }
+    println!();
+    println!("MIDI output ports (-o):");
+    for (i, n) in MidiOutPort::ports().ok().unwrap_or_default().iter().enumerate() {
+        println!("{}[{}] {}", tab, i, n);
+    }
+
+    if autodetect_supported() {
+        println!();
+        println!("USB devices (-u):");
+        for (i, (n, is_ok)) in usb_list_devices().iter().enumerate() {
+            let prefix = if *is_ok { "" } else { "ERROR: " };
+            println!("{}[{}] {}{}", tab, i, prefix, n);
+        }
+    }
+}
+
+/**
+ * Open the device as selected on the command line, running MIDI and,
+ * if that failed, USB autodetect when needed.
+ */
+async fn connect(opts: &Opts) -> Result<(BoxedMidiIn, BoxedMidiOut, u8, &'static Config)> {
+    let midi_channel = opts.channel
+        .map(channel_from_arg)
+        .transpose()?;
+    let config = opts.model.as_ref()
+        .map(|m| config_for_str(m))
+        .transpose()?;
+
+    let ports: Option<(BoxedMidiIn, BoxedMidiOut)> = match (&opts.input, &opts.output, &opts.usb) {
+        (None, None, None) => None,
+        (Some(_), None, None) | (None, Some(_), None) => {
+            bail!("Both input and output port need to be set on command line to skip autodetect!")
+        }
+        (Some(_), _, Some(_)) | (_, Some(_), Some(_)) => {
+            bail!("MIDI and USB inputs cannot be set on command line together, use either MIDI or USB!")
+        }
+        (Some(i), Some(o), None) => {
+            let midi_in = MidiInPort::new_for_address(i)?;
+            let midi_out = MidiOutPort::new_for_address(o)?;
+            Some((box_midi_in(midi_in), box_midi_out(midi_out)))
+        }
+        (None, None, Some(u)) => {
+            let (midi_in, midi_out) = usb_open_addr(u)?;
+            Some((box_midi_in(midi_in), box_midi_out(midi_out)))
+        }
+    };
+    // USB devices don't care about the MIDI channel
+    let midi_channel = midi_channel.or_else(|| opts.usb.as_ref().map(|_| Channel::num(0)));
+
+    match (ports, config) {
+        (Some((midi_in, midi_out)), Some(config)) => {
+            let (midi_in, midi_out, channel) = test_with_ports(
+                midi_in, midi_out, midi_channel.unwrap_or(Channel::all()), config
+            ).await?;
+            Ok((midi_in, midi_out, channel, config))
+        }
+        (Some((midi_in, midi_out)), None) => {
+            let res = autodetect_with_ports(vec![midi_in], vec![midi_out], midi_channel).await?;
+            Ok((res.in_port, res.out_port, res.channel, res.config))
+        }
+        (None, _) => {
+            if config.is_some() {
+                warn!("Model set on command line, but not input/output ports. \
+                       The model parameter will be ignored!");
+            }
+            let res = match pod_core::midi_io::autodetect(midi_channel).await {
+                Ok(res) => res,
+                Err(e) if autodetect_supported() => {
+                    usb::autodetect().await
+                        .map_err(|e1| anyhow!("MIDI: {}\nUSB: {}", e, e1))?
+                }
+                Err(e) => return Err(e)
+            };
+            Ok((res.in_port, res.out_port, res.channel, res.config))
+        }
+    }
+}
+
+fn program_for_str(config: &Config, program_str: &str) -> Result<usize> {
+    let program = match usize::from_str(program_str) {
+        Ok(n) if n >= 1 => Some(n - 1),
+        Ok(_) => None,
+        Err(_) => (0 .. config.program_num)
+            .find(|i| program_id_string(*i).eq_ignore_ascii_case(program_str))
+    };
+    program
+        .filter(|p| *p < config.program_num)
+        .with_context(|| format!("Program {:?} not found!", program_str))
+}
+
+fn device_version(events: &[AppEvent]) -> String {
+    events.iter()
+        .find_map(|e| match e {
+            AppEvent::DeviceDetected(DeviceDetectedEvent { version, .. }) => Some(version.clone()),
+            _ => None
+        })
+        .unwrap_or_default()
+}
+
+fn print_control(device: &Device, name: &str) -> Result<()> {
+    let controller = device.controller.lock().unwrap();
+    let control = controller.get_config(name)
+        .with_context(|| format!("Control {:?} not found!", name))?;
+    let value = controller.get(name).unwrap_or_default();
+    let handler = handler_for_config(device.config)
+        .with_context(|| format!("No handler for device {:?}", device.config.name))?;
+    println!("{} = {} ({})", name, value,
+             format_control_value(device.config, handler.as_ref(), &controller, name, control, value));
+    Ok(())
+}
+
+async fn run(opts: Opts) -> Result<()> {
+    if let Command::ListPorts = opts.command {
+        list_ports();
+        return Ok(());
+    }
+
+    let (midi_in, midi_out, midi_channel, config) = connect(&opts).await?;
+    info!("Connected to {:?}: {} / {}, channel {}",
+        config.name, midi_in.name(), midi_out.name(), midi_channel);
+
+    if let Command::Detect = opts.command {
+        println!("{}", config.name);
+        println!("in: {}", midi_in.name());
+        println!("out: {}", midi_out.name());
+        println!("channel: {}", midi_channel);
+        return Ok(());
+    }
+
+    let device = Device::start(midi_in, midi_out, midi_channel, config)?;
+    match opts.command {
+        Command::ListPorts | Command::Detect => unreachable!(),
+        Command::DumpBank { file } => {
+            let events = device.run(|device| {
+                let msg = MidiMessage::UniversalDeviceInquiry { channel: midi_channel };
+                device.send(AppEvent::MidiMsgOut(msg));
+                let e = BufferLoadEvent { buffer: Buffer::All, origin: Origin::UI };
+                device.send(AppEvent::Load(e));
+            }, true).await?;
+
+            let bank = Bank::from_dump(config, &device.dump.lock().unwrap(), &device_version(&events));
+            match file {
+                Some(path) => {
+                    std::fs::write(&path, bank.to_string())
+                        .with_context(|| format!("Failed to write {:?}", path))?;
+                    info!("Bank saved to {:?}", path);
+                }
+                None => print!("{}", bank)
+            }
+        }
+        Command::RestoreBank { file } => {
+            let str = std::fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read {:?}", file))?;
+            let bank = Bank::from_str(&str)?;
+            bank.restore(config, &mut device.dump.lock().unwrap())?;
+
+            device.run(|device| {
+                let e = BufferStoreEvent { buffer: Buffer::All, origin: Origin::UI };
+                device.send(AppEvent::Store(e));
+            }, false).await?;
+            info!("Bank restored from {:?}", file);
+        }
+        Command::Get { control } => {
+            device.run(|device| {
+                let e = BufferLoadEvent { buffer: Buffer::EditBuffer, origin: Origin::UI };
+                device.send(AppEvent::Load(e));
+            }, true).await?;
+
+            match control {
+                Some(name) => print_control(&device, &name)?,
+                None => {
+                    let mut names = config.controls.iter()
+                        .filter(|(_, c)| c.get_addr().is_some())
+                        .map(|(name, _)| name.clone())
+                        .collect::<Vec<_>>();
+                    names.sort();
+                    println!("name = {}", device.edit.lock().unwrap().name().trim());
+                    for name in names {
+                        print_control(&device, &name)?;
+                    }
+                }
+            }
+        }
+        Command::Set { control, value } => {
+            let Some(c) = config.control_by_name(&control) else {
+                bail!("Control {:?} not found!", control);
+            };
+            if c.get_cc().is_none() {
+                bail!("Control {:?} cannot be set over MIDI!", control);
+            }
+            let (min, max) = config.control_bounds(&control)
+                .with_context(|| format!("Control {:?} has no value!", control))?;
+            if value < min || value > max {
+                bail!("Value {} for control {:?} out of range {}..{}!", value, control, min, max);
+            }
+            device.run(|device| {
+                device.controller.set(&control, value, StoreOrigin::UI);
+            }, false).await?;
+        }
+        Command::Program { program } => {
+            let program = program_for_str(config, &program)?;
+            device.run(|device| {
+                let e = ProgramChangeEvent {
+                    program: Program::Program(program as u16), origin: Origin::UI
+                };
+                device.send(AppEvent::ProgramChange(e));
+            }, false).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    simple_logger::SimpleLogger::new()
+        .with_level(LevelFilter::Warn)
+        .env()
+        .init()?;
+
+    register_modules();
+    register_user_definitions();
+    start_usb();
+    let opts: Opts = Opts::parse();
+
+    run(opts).await
+}
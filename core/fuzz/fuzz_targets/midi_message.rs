@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pod_core::midi::MidiMessage;
+
+// Any input may be rejected by the parser, but must not crash it.
+// Parsed messages must encode back into bytes that parse the same way.
+fuzz_target!(|data: &[u8]| {
+    let Ok(msg) = MidiMessage::from_bytes(data.to_vec()) else {
+        return;
+    };
+    let bytes = msg.to_bytes()
+        .unwrap_or_else(|e| panic!("{:?} cannot be encoded: {}", msg, e));
+    let parsed = MidiMessage::from_bytes(bytes)
+        .unwrap_or_else(|e| panic!("{:?} cannot be parsed back: {}", msg, e));
+    assert_eq!(parsed, msg);
+});
@@ -0,0 +1,722 @@
+//! Local JSON API over WebSocket and HTTP for remote panels and
+//! third-party editors.
+//!
+//! Every message is a JSON object. Upon connection and whenever the
+//! device changes, a client is sent a `config` message describing the
+//! current device, followed by a `state` message with the current values
+//! of all controls and the current program:
+//!
+//! * `{"type": "config", "name": ..., "programs": [...], "controls": [...],
+//!   "amp_models": [...], "cab_models": [...], "effects": [...]}`;
+//! * `{"type": "state", "controls": {<name>: <value>, ...}, "program": ...}`.
+//!
+//! After that, the client receives `control_change`, `program_change` and
+//! `modified` messages as they happen on the app event bus. A program is
+//! a program number starting from 0, "manual" or "tuner". A buffer is
+//! a program number, "edit", "current" or "all".
+//!
+//! The client can send the following commands:
+//!
+//! * `{"cmd": "set", "name": <name>, "value": <value>}` -- set a control;
+//! * `{"cmd": "program", "program": <program>}` -- switch to a program,
+//!   which can also be given as a program id such as "1A";
+//! * `{"cmd": "load", "buffer": <buffer>}`, `{"cmd": "store", "buffer": <buffer>}`
+//!   -- load a buffer from or store it to the device;
+//! * `{"cmd": "config"}`, `{"cmd": "state"}` -- re-send the respective message.
+//!
+//! A command that cannot be executed is answered with
+//! `{"type": "error", "message": ...}`.
+//!
+//! Clients that cannot keep a WebSocket open can use plain HTTP requests
+//! on the same port instead: `GET /config` and `GET /state` return the
+//! respective message, and `POST /command` executes the command given as
+//! the request body, returning the reply or `{"type": "ok"}`. Commands
+//! that cannot be executed return status 400 with the error message.
+//!
+//! Controls are described with their kind, CC, program address and the
+//! `min` and `max` values they accept, and labels where the values have
+//! them, such as select controls. A `set` command with a value outside of
+//! `min..max` is rejected.
+//!
+//! Web pages open WebSocket connections and send requests to any address,
+//! so connections and requests sent by a browser with an `Origin` header
+//! are only accepted from the allowed origins given to `ApiServer::start`. Clients that send no
+//! `Origin` header, such as native applications, are always accepted.
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use anyhow::*;
+use futures_util::{SinkExt, StreamExt};
+use log::*;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use crate::context::Ctx;
+use crate::controller::*;
+use crate::event::*;
+use crate::model::*;
+use crate::program_id_string;
+use crate::remote::*;
+
+const MAX_HEAD_SIZE: usize = 8192;
+const MAX_BODY_SIZE: usize = 65536;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+enum Command {
+    Config,
+    State,
+    Set { name: String, value: u16 },
+    Program { program: Value },
+    Load { buffer: Value },
+    Store { buffer: Value },
+}
+
+fn origin_str(origin: StoreOrigin) -> &'static str {
+    match origin {
+        StoreOrigin::NONE => "none",
+        StoreOrigin::MIDI => "midi",
+        StoreOrigin::UI => "ui"
+    }
+}
+
+fn program_to_json(program: &Program) -> Value {
+    match program {
+        Program::ManualMode => json!("manual"),
+        Program::Tuner => json!("tuner"),
+        Program::Program(p) => json!(p)
+    }
+}
+
+fn program_from_json(config: &Config, value: &Value) -> Option<Program> {
+    match value {
+        Value::String(s) => program_from_str(config, s),
+        value => program_from_num(config, value.as_i64()?)
+    }
+}
+
+fn buffer_to_json(buffer: &Buffer) -> Value {
+    match buffer {
+        Buffer::EditBuffer => json!("edit"),
+        Buffer::Current => json!("current"),
+        Buffer::All => json!("all"),
+        Buffer::Program(p) => json!(p)
+    }
+}
+
+fn buffer_from_json(config: &Config, value: &Value) -> Option<Buffer> {
+    match value.as_str() {
+        Some("edit") => Some(Buffer::EditBuffer),
+        Some("current") => Some(Buffer::Current),
+        Some("all") => Some(Buffer::All),
+        _ => match program_from_json(config, value)? {
+            Program::Program(p) => Some(Buffer::Program(p as usize)),
+            _ => None
+        }
+    }
+}
+
+fn control_to_json(config: &Config, name: &str, control: &Control) -> Value {
+    let (kind, labels) = match control {
+        Control::SwitchControl(_) | Control::MidiSwitchControl(_) =>
+            ("switch", None),
+        Control::RangeControl(RangeControl { format, .. }) |
+        Control::AddrRangeControl(AddrRangeControl { format, .. }) |
+        Control::VirtualRangeControl(VirtualRangeControl { format, .. }) => {
+            let labels = match format {
+                Format::Labels(labels) => Some(labels.clone()),
+                _ => None
+            };
+            ("range", labels)
+        }
+        Control::Select(_) | Control::MidiSelect(_) | Control::VirtualSelect(_) =>
+            ("select", config.select_labels(name)),
+        Control::Button(_) =>
+            ("button", None)
+    };
+    let mut value = json!({
+        "name": name,
+        "kind": kind,
+        "cc": control.get_cc(),
+        "addr": control.get_addr().map(|(addr, _)| addr),
+    });
+    if let Some((min, max)) = config.control_bounds(name) {
+        value["min"] = json!(min);
+        value["max"] = json!(max);
+    }
+    if let Some(labels) = labels {
+        value["labels"] = json!(labels);
+    }
+    value
+}
+
+fn config_message(config: &Config) -> Value {
+    let controls = config.controls.iter()
+        .collect::<BTreeMap<_, _>>()
+        .into_iter()
+        .map(|(name, control)| control_to_json(config, name, control))
+        .collect::<Vec<_>>();
+    let programs = (0 .. config.program_num)
+        .map(program_id_string)
+        .collect::<Vec<_>>();
+    let amp_models = config.amp_models.iter()
+        .map(|amp| json!({
+            "name": amp.name,
+            "bright_switch": amp.bright_switch,
+            "presence": amp.presence,
+            "drive2": amp.drive2
+        }))
+        .collect::<Vec<_>>();
+    let effects = config.effects.iter()
+        .map(|effect| &effect.name)
+        .collect::<Vec<_>>();
+
+    json!({
+        "type": "config",
+        "name": config.name,
+        "programs": programs,
+        "manual_mode": config.pc_manual_mode.is_some(),
+        "tuner": config.pc_tuner.is_some(),
+        "controls": controls,
+        "amp_models": amp_models,
+        "cab_models": config.cab_models,
+        "effects": effects
+    })
+}
+
+fn state_message(target: &RemoteTarget) -> Value {
+    let controls = {
+        let controller = target.controller.lock().unwrap();
+        controller.controls.keys()
+            .flat_map(|name| controller.get(name).map(|v| (name.clone(), v)))
+            .collect::<BTreeMap<_, _>>()
+    };
+    json!({
+        "type": "state",
+        "controls": controls,
+        "program": program_to_json(&target.program())
+    })
+}
+
+fn error_message(message: &str) -> Value {
+    json!({ "type": "error", "message": message })
+}
+
+/// Convert an app event into a message for connected clients
+fn publish(event: &AppEvent) -> Option<Value> {
+    match event {
+        AppEvent::ControlChange(ControlChangeEvent { name, value, origin }) =>
+            Some(json!({
+                "type": "control_change",
+                "name": name,
+                "value": value,
+                "origin": origin_str(*origin)
+            })),
+        AppEvent::ProgramChange(ProgramChangeEvent { program, origin }) =>
+            Some(json!({
+                "type": "program_change",
+                "program": program_to_json(program),
+                "origin": origin_str((*origin).into())
+            })),
+        AppEvent::Modified(ModifiedEvent { buffer, origin, modified }) =>
+            Some(json!({
+                "type": "modified",
+                "buffer": buffer_to_json(buffer),
+                "modified": modified,
+                "origin": origin_str((*origin).into())
+            })),
+        _ => None
+    }
+}
+
+/// Execute a command, returning replies to the client
+fn handle(target: Option<&RemoteTarget>, app_event_tx: &broadcast::Sender<AppEvent>,
+          text: &str) -> Vec<Value> {
+    let command = match serde_json::from_str::<Command>(text) {
+        Result::Ok(command) => command,
+        Err(e) => return vec![error_message(&format!("Invalid command: {}", e))]
+    };
+    let Some(target) = target else {
+        return vec![error_message("No device")];
+    };
+    let config = target.config;
+
+    match command {
+        Command::Config => {
+            return vec![config_message(config)];
+        }
+        Command::State => {
+            return vec![state_message(target)];
+        }
+        Command::Set { name, value } => {
+            if let Err(e) = target.set_control(&name, value as i64) {
+                return vec![error_message(&e.to_string())];
+            }
+        }
+        Command::Program { program } => {
+            let Some(program) = program_from_json(config, &program) else {
+                return vec![error_message(&format!("Program {} not found", program))];
+            };
+            target.set_program(program);
+        }
+        Command::Load { buffer } => {
+            let Some(buffer) = buffer_from_json(config, &buffer) else {
+                return vec![error_message(&format!("Buffer {} not found", buffer))];
+            };
+            let e = AppEvent::Load(BufferLoadEvent { buffer, origin: Origin::UI });
+            app_event_tx.send(e).ok();
+        }
+        Command::Store { buffer } => {
+            let Some(buffer) = buffer_from_json(config, &buffer) else {
+                return vec![error_message(&format!("Buffer {} not found", buffer))];
+            };
+            let e = AppEvent::Store(BufferStoreEvent { buffer, origin: Origin::UI });
+            app_event_tx.send(e).ok();
+        }
+    }
+    vec![]
+}
+
+/// A WebSocket server mapping JSON commands to the controllers of the
+/// current device and app events, and publishing control, program and
+/// modified state changes from the app event bus to all clients
+#[derive(Clone)]
+pub struct ApiServer {
+    target: Arc<Mutex<Option<RemoteTarget>>>,
+    ctx_tx: broadcast::Sender<()>,
+    allowed_origins: Arc<Vec<String>>,
+}
+
+/// Check the `Origin` header of a WebSocket handshake request. Requests
+/// without an origin are not sent by browsers and are always allowed.
+fn origin_allowed(origin: Option<&str>, allowed_origins: &[String]) -> bool {
+    let Some(origin) = origin else {
+        return true;
+    };
+    let origin = origin.trim_end_matches('/');
+    allowed_origins.iter()
+        .any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(origin))
+}
+
+impl ApiServer {
+    /// Start an API server listening on TCP `addr`, accepting browser
+    /// connections from `allowed_origins` only. Must be called from
+    /// within a tokio runtime.
+    pub fn start(addr: SocketAddr, allowed_origins: &[String],
+                 app_event_tx: &broadcast::Sender<AppEvent>) -> Result<Self> {
+        let listener = std::net::TcpListener::bind(addr)
+            .with_context(|| format!("Failed to bind API server to {}", addr))?;
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        info!("API server listening on ws://{0} and http://{0}", addr);
+
+        let (ctx_tx, _) = broadcast::channel(4);
+        let server = Self {
+            target: Arc::new(Mutex::new(None)),
+            ctx_tx,
+            allowed_origins: Arc::new(allowed_origins.to_vec())
+        };
+        tokio::spawn(accept_task(listener, server.clone(), app_event_tx.clone()));
+
+        Ok(server)
+    }
+
+    /// Set the device context to control, `None` if there is no device
+    pub fn set_ctx(&self, ctx: Option<&Ctx>) {
+        *self.target.lock().unwrap() = ctx.map(RemoteTarget::new);
+        self.ctx_tx.send(()).ok();
+    }
+
+    fn target(&self) -> Option<RemoteTarget> {
+        self.target.lock().unwrap().clone()
+    }
+}
+
+async fn accept_task(listener: TcpListener, server: ApiServer,
+                     app_event_tx: broadcast::Sender<AppEvent>) {
+    loop {
+        match listener.accept().await {
+            Result::Ok((stream, from)) => {
+                tokio::spawn(client_task(stream, from, server.clone(), app_event_tx.clone()));
+            }
+            Err(e) => {
+                error!("API server accept failed: {}", e);
+            }
+        }
+    }
+}
+
+/// Head of an HTTP request, either a WebSocket upgrade or a plain
+/// HTTP request
+#[derive(Debug)]
+struct HttpHead {
+    method: String,
+    path: String,
+    origin: Option<String>,
+    websocket: bool,
+    content_length: usize,
+    /// Length of the head in bytes
+    len: usize,
+}
+
+/// Parse the head of an HTTP request, `None` if it is not complete yet
+fn parse_head(data: &[u8]) -> Result<Option<HttpHead>> {
+    let mut headers = [httparse::EMPTY_HEADER; 32];
+    let mut request = httparse::Request::new(&mut headers);
+    let httparse::Status::Complete(len) = request.parse(data)? else {
+        return Ok(None);
+    };
+    let header = |name: &str| request.headers.iter()
+        .find(|h| h.name.eq_ignore_ascii_case(name))
+        .map(|h| String::from_utf8_lossy(h.value).into_owned());
+    let content_length = match header("content-length") {
+        Some(v) => v.trim().parse::<usize>().context("Invalid Content-Length")?,
+        None => 0
+    };
+    let path = request.path.unwrap_or_default();
+    Ok(Some(HttpHead {
+        method: request.method.unwrap_or_default().to_string(),
+        path: path.split('?').next().unwrap_or_default().to_string(),
+        origin: header("origin"),
+        websocket: header("upgrade").is_some_and(|v| v.eq_ignore_ascii_case("websocket")),
+        content_length,
+        len
+    }))
+}
+
+/// Wait for the head of an HTTP request without consuming it, so that
+/// a WebSocket upgrade request can still be passed to the handshake
+async fn peek_head(stream: &TcpStream) -> Result<HttpHead> {
+    let mut buf = vec![0u8; MAX_HEAD_SIZE];
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+    loop {
+        let len = stream.peek(&mut buf).await?;
+        if len == 0 {
+            bail!("Connection closed");
+        }
+        if let Some(head) = parse_head(&buf[.. len])? {
+            return Ok(head);
+        }
+        if len == buf.len() {
+            bail!("Request head too large");
+        }
+        if Instant::now() > deadline {
+            bail!("Request timed out");
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+/// Execute a plain HTTP request, returning the response status and body
+fn http_reply(target: Option<&RemoteTarget>, app_event_tx: &broadcast::Sender<AppEvent>,
+              method: &str, path: &str, body: &[u8]) -> (StatusCode, Option<Value>) {
+    let command = match (method, path) {
+        ("OPTIONS", _) => return (StatusCode::NO_CONTENT, None),
+        ("GET", "/config") => r#"{"cmd": "config"}"#.to_string(),
+        ("GET", "/state") => r#"{"cmd": "state"}"#.to_string(),
+        ("POST", "/command") => String::from_utf8_lossy(body).into_owned(),
+        (_, "/config" | "/state" | "/command") =>
+            return (StatusCode::METHOD_NOT_ALLOWED, Some(error_message("Method not allowed"))),
+        _ =>
+            return (StatusCode::NOT_FOUND, Some(error_message("Not found")))
+    };
+    if target.is_none() {
+        return (StatusCode::SERVICE_UNAVAILABLE, Some(error_message("No device")));
+    }
+    match handle(target, app_event_tx, &command).into_iter().next() {
+        Some(reply) if reply["type"] == "error" => (StatusCode::BAD_REQUEST, Some(reply)),
+        Some(reply) => (StatusCode::OK, Some(reply)),
+        None => (StatusCode::OK, Some(json!({ "type": "ok" })))
+    }
+}
+
+fn http_response(status: StatusCode, allow_origin: Option<&str>, body: Option<&Value>) -> Vec<u8> {
+    let body = body.map(|v| v.to_string()).unwrap_or_default();
+    let mut response = format!("HTTP/1.1 {} {}\r\n", status.as_u16(), status.canonical_reason().unwrap_or_default());
+    if !body.is_empty() {
+        response += "Content-Type: application/json\r\n";
+    }
+    if let Some(origin) = allow_origin {
+        response += &format!("Access-Control-Allow-Origin: {}\r\n", origin);
+        response += "Access-Control-Allow-Methods: GET, POST\r\n";
+        response += "Access-Control-Allow-Headers: Content-Type\r\n";
+        response += "Vary: Origin\r\n";
+    }
+    response += &format!("Content-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+    response.into_bytes()
+}
+
+async fn http_task(mut stream: TcpStream, from: SocketAddr, head: HttpHead, server: ApiServer,
+                   app_event_tx: broadcast::Sender<AppEvent>) {
+    let origin = head.origin.as_deref();
+    let (status, body) = if !origin_allowed(origin, &server.allowed_origins) {
+        warn!("API client {} rejected: origin {:?} not allowed", from, origin.unwrap_or_default());
+        (StatusCode::FORBIDDEN, Some(error_message("Origin not allowed")))
+    } else if head.content_length > MAX_BODY_SIZE {
+        (StatusCode::PAYLOAD_TOO_LARGE, Some(error_message("Request too large")))
+    } else {
+        let mut data = vec![0u8; head.len + head.content_length];
+        match tokio::time::timeout(REQUEST_TIMEOUT, stream.read_exact(&mut data)).await {
+            Result::Ok(Result::Ok(_)) => {}
+            Result::Ok(Err(e)) => {
+                warn!("API client {} request failed: {}", from, e);
+                return;
+            }
+            Err(_) => {
+                warn!("API client {} request timed out", from);
+                return;
+            }
+        }
+        trace!("API << {} {} {}", from, head.method, head.path);
+        http_reply(server.target().as_ref(), &app_event_tx, &head.method, &head.path, &data[head.len ..])
+    };
+    let allow_origin = origin.filter(|_| status != StatusCode::FORBIDDEN);
+    let response = http_response(status, allow_origin, body.as_ref());
+    if let Err(e) = stream.write_all(&response).await {
+        warn!("Failed to send API response to {}: {}", from, e);
+    }
+    stream.shutdown().await.ok();
+}
+
+async fn client_task(stream: TcpStream, from: SocketAddr, server: ApiServer,
+                     app_event_tx: broadcast::Sender<AppEvent>) {
+    let head = match peek_head(&stream).await {
+        Result::Ok(head) => head,
+        Err(e) => {
+            warn!("API client {} request failed: {}", from, e);
+            return;
+        }
+    };
+    if !head.websocket {
+        http_task(stream, from, head, server, app_event_tx).await;
+        return;
+    }
+
+    let allowed_origins = server.allowed_origins.clone();
+    // the error response type is given by tungstenite
+    #[allow(clippy::result_large_err)]
+    let check_origin = move |request: &Request, response: Response| {
+        let origin = request.headers().get("origin")
+            .map(|v| v.to_str().unwrap_or("<invalid>"));
+        if origin_allowed(origin, &allowed_origins) {
+            return Result::Ok(response);
+        }
+        warn!("API client {} rejected: origin {:?} not allowed", from, origin.unwrap_or_default());
+        let mut response = ErrorResponse::new(Some("Origin not allowed".into()));
+        *response.status_mut() = StatusCode::FORBIDDEN;
+        Err(response)
+    };
+    let ws = match tokio_tungstenite::accept_hdr_async(stream, check_origin).await {
+        Result::Ok(ws) => ws,
+        Err(e) => {
+            warn!("API client {} handshake failed: {}", from, e);
+            return;
+        }
+    };
+    info!("API client {} connected", from);
+    let (mut sink, mut stream) = ws.split();
+    let mut app_event_rx = app_event_tx.subscribe();
+    let mut ctx_rx = server.ctx_tx.subscribe();
+
+    let device = |target: Option<&RemoteTarget>| match target {
+        Some(target) => vec![config_message(target.config), state_message(target)],
+        None => vec![]
+    };
+    let mut replies = device(server.target().as_ref());
+
+    loop {
+        for reply in replies.drain(..) {
+            trace!("API >> {} {}", from, reply);
+            if let Err(e) = sink.send(Message::text(reply.to_string())).await {
+                warn!("Failed to send API message to {}: {}", from, e);
+                return;
+            }
+        }
+
+        tokio::select! {
+            msg = stream.next() => {
+                let text = match msg {
+                    Some(Result::Ok(Message::Text(text))) => text,
+                    Some(Result::Ok(Message::Close(_))) | None => break,
+                    Some(Result::Ok(_)) => continue,
+                    Some(Err(e)) => {
+                        warn!("API client {} error: {}", from, e);
+                        break;
+                    }
+                };
+                trace!("API << {} {}", from, text);
+                replies = handle(server.target().as_ref(), &app_event_tx, &text);
+            }
+            event = app_event_rx.recv() => {
+                match event {
+                    Result::Ok(event) => replies.extend(publish(&event)),
+                    Err(RecvError::Closed) => break,
+                    Err(RecvError::Lagged(n)) => warn!("API client {} lagged: {}", from, n)
+                }
+            }
+            result = ctx_rx.recv() => {
+                if let Err(RecvError::Closed) = result {
+                    break;
+                }
+                replies = device(server.target().as_ref());
+            }
+        }
+    }
+    info!("API client {} disconnected", from);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    fn config() -> Config {
+        let mut config = test_util::config("API POD");
+        config.program_num = 8;
+        config.pc_manual_mode = Some(0);
+        config.controls.insert("mode".into(), RangeControl {
+            cc: 20, addr: 10, config: RangeConfig::Short { from: 0, to: 2, edge: true },
+            format: Format::Labels(vec!["A".into(), "B".into(), "C".into()])
+        }.into());
+        config.cab_models = vec!["1x12".into(), "2x12".into()];
+        config.controls.insert("cab_select".into(), Select { cc: 71, addr: 11 }.into());
+        config.controls.insert("name_change".into(), Button {}.into());
+        config
+    }
+
+    #[test]
+    fn program_and_buffer_values() {
+        let config = config();
+        assert_eq!(program_from_json(&config, &json!(3)), Some(Program::Program(3)));
+        assert_eq!(program_from_json(&config, &json!("1B")), Some(Program::Program(1)));
+        assert_eq!(program_from_json(&config, &json!("manual")), Some(Program::ManualMode));
+        assert_eq!(program_from_json(&config, &json!("tuner")), None);
+        assert_eq!(program_from_json(&config, &json!(8)), None);
+        assert_eq!(program_from_json(&config, &json!(-1)), None);
+
+        assert_eq!(buffer_from_json(&config, &json!("edit")), Some(Buffer::EditBuffer));
+        assert_eq!(buffer_from_json(&config, &json!("all")), Some(Buffer::All));
+        assert_eq!(buffer_from_json(&config, &json!(7)), Some(Buffer::Program(7)));
+        assert_eq!(buffer_from_json(&config, &json!("foo")), None);
+
+        for buffer in [Buffer::EditBuffer, Buffer::Current, Buffer::All, Buffer::Program(5)] {
+            assert_eq!(buffer_from_json(&config, &buffer_to_json(&buffer)), Some(buffer));
+        }
+    }
+
+    #[test]
+    fn config_lists_controls() {
+        let value = config_message(&config());
+        let controls = value["controls"].as_array().unwrap();
+        assert_eq!(controls.len(), 4);
+        assert_eq!(controls[0]["name"], "cab_select");
+        assert_eq!(controls[0]["kind"], "select");
+        assert_eq!(controls[0]["min"], 0);
+        assert_eq!(controls[0]["max"], 1);
+        assert_eq!(controls[0]["labels"], json!(["1x12", "2x12"]));
+        assert_eq!(controls[1]["name"], "drive");
+        assert_eq!(controls[1]["max"], 127);
+        assert!(controls[1].get("labels").is_none());
+        assert_eq!(controls[2]["name"], "mode");
+        assert_eq!(controls[2]["max"], 2);
+        assert_eq!(controls[2]["labels"], json!(["A", "B", "C"]));
+        assert_eq!(controls[3]["name"], "name_change");
+        assert!(controls[3].get("max").is_none());
+        assert_eq!(value["programs"][1], "1B");
+    }
+
+    #[test]
+    fn commands() {
+        let (tx, mut rx) = broadcast::channel(4);
+        let config = test_util::leak(config());
+        let target = RemoteTarget {
+            config,
+            controller: Arc::new(Mutex::new(Controller::new(config.controls.clone()))),
+            ui_controller: Arc::new(Mutex::new(Controller::new(Default::default())))
+        };
+
+        assert!(handle(Some(&target), &tx, r#"{"cmd": "set", "name": "drive", "value": 64}"#).is_empty());
+        assert_eq!(target.controller.get("drive"), Some(64));
+
+        assert!(handle(Some(&target), &tx, r#"{"cmd": "load", "buffer": 2}"#).is_empty());
+        assert!(matches!(rx.try_recv(),
+            Result::Ok(AppEvent::Load(BufferLoadEvent { buffer: Buffer::Program(2), origin: Origin::UI }))));
+
+        for text in [r#"{"cmd": "set", "name": "foo", "value": 1}"#, r#"{"cmd": "store", "buffer": 99}"#,
+                     r#"{"cmd": "set", "name": "drive", "value": 128}"#,
+                     r#"{"cmd": "set", "name": "mode", "value": 3}"#,
+                     r#"{"cmd": "set", "name": "cab_select", "value": 2}"#,
+                     r#"{"cmd": "set", "name": "name_change", "value": 1}"#,
+                     r#"{"cmd": "set", "name": "drive", "value": -1}"#,
+                     r#"{"cmd": "bar"}"#, "garbage"] {
+            let replies = handle(Some(&target), &tx, text);
+            assert_eq!(replies.len(), 1);
+            assert_eq!(replies[0]["type"], "error");
+        }
+        assert_eq!(handle(None, &tx, r#"{"cmd": "config"}"#)[0]["type"], "error");
+        assert_eq!(target.controller.get("drive"), Some(64));
+        assert_eq!(target.controller.get("mode"), Some(0));
+    }
+
+    #[test]
+    fn http_requests() {
+        let (tx, _rx) = broadcast::channel(4);
+        let config = test_util::leak(config());
+        let target = RemoteTarget {
+            config,
+            controller: Arc::new(Mutex::new(Controller::new(config.controls.clone()))),
+            ui_controller: Arc::new(Mutex::new(Controller::new(Default::default())))
+        };
+        let reply = |method, path, body: &str| http_reply(Some(&target), &tx, method, path, body.as_bytes());
+
+        let (status, value) = reply("GET", "/config", "");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(value.unwrap()["type"], "config");
+        let (status, value) = reply("POST", "/command", r#"{"cmd": "set", "name": "drive", "value": 5}"#);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(value.unwrap()["type"], "ok");
+        assert_eq!(target.controller.get("drive"), Some(5));
+        assert_eq!(reply("POST", "/command", r#"{"cmd": "set", "name": "drive", "value": 500}"#).0,
+                   StatusCode::BAD_REQUEST);
+        assert_eq!(reply("POST", "/config", "").0, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(reply("GET", "/", "").0, StatusCode::NOT_FOUND);
+        assert_eq!(reply("OPTIONS", "/command", "").0, StatusCode::NO_CONTENT);
+        assert_eq!(http_reply(None, &tx, "GET", "/state", &[]).0, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn http_heads() {
+        let head = parse_head(b"POST /command?x=1 HTTP/1.1\r\nOrigin: null\r\nContent-Length: 12\r\n\r\n{}")
+            .unwrap().unwrap();
+        assert_eq!((head.method.as_str(), head.path.as_str()), ("POST", "/command"));
+        assert_eq!(head.origin.as_deref(), Some("null"));
+        assert_eq!((head.content_length, head.len, head.websocket), (12, 64, false));
+
+        let head = parse_head(b"GET / HTTP/1.1\r\nUpgrade: WebSocket\r\n\r\n").unwrap().unwrap();
+        assert!(head.websocket);
+        assert!(parse_head(b"GET / HTTP/1.1\r\nHost: x").unwrap().is_none());
+        assert!(parse_head(b"GET / HTTP/1.1\r\nContent-Length: x\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn origins() {
+        let allowed = vec!["http://localhost:8000".to_string(), "null".to_string()];
+
+        assert!(origin_allowed(None, &allowed));
+        assert!(origin_allowed(None, &[]));
+        assert!(origin_allowed(Some("http://localhost:8000"), &allowed));
+        assert!(origin_allowed(Some("http://LOCALHOST:8000/"), &allowed));
+        assert!(origin_allowed(Some("null"), &allowed));
+        assert!(!origin_allowed(Some("http://localhost:8001"), &allowed));
+        assert!(!origin_allowed(Some("https://evil.example.com"), &allowed));
+        assert!(!origin_allowed(Some("http://localhost:8000"), &[]));
+    }
+}
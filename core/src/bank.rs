@@ -0,0 +1,294 @@
+use std::fmt;
+use std::str::FromStr;
+use anyhow::*;
+use crate::dump::ProgramsDump;
+use crate::event::Origin;
+use crate::model::Config;
+use crate::program;
+use crate::syx::check_patch_source;
+
+const BANK_HEADER: &str = "# POD UI program bank";
+const BANK_FORMAT_VERSION: u32 = 1;
+
+/// A backup of all programs of a device, as stored in a bank file.
+///
+/// A bank file is a line-based text file: a header of `key: value`
+/// pairs describing the device, followed by `name.N` and `data.N`
+/// entries for every program, where program data is hex-encoded.
+#[derive(Clone, Debug)]
+pub struct Bank {
+    pub device: String,
+    pub family: u16,
+    pub member: u16,
+    pub version: String,
+    pub program_size: usize,
+    pub names: Vec<String>,
+    pub data: Vec<u8>,
+}
+
+impl Bank {
+    /// Create a bank from all programs in the programs dump.
+    /// `version` is the firmware version as reported by the device.
+    pub fn from_dump(config: &Config, dump: &ProgramsDump, version: &str) -> Self {
+        let names = (0 .. dump.program_num())
+            .map(|i| dump.name(i).unwrap_or_default())
+            .collect();
+
+        Self {
+            device: config.name.clone(),
+            family: config.family,
+            member: config.member,
+            version: version.to_string(),
+            program_size: dump.program_size(),
+            names,
+            data: program::store_all_dump(dump)
+        }
+    }
+
+    pub fn program_num(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Check that this bank can be restored into a device with the given
+    /// `config` and return the data suitable for `program::load_all_dump`.
+    pub fn data_for(&self, config: &Config) -> Result<&[u8]> {
+        check_patch_source(self.family, self.member, config)
+            .with_context(|| format!("Bank for {:?} cannot be restored into {:?}",
+                                     self.device, config.name))?;
+        if self.program_size != config.program_size {
+            bail!("Program size mismatch: expected {}, got {}",
+                config.program_size, self.program_size);
+        }
+        if self.program_num() != config.program_num {
+            bail!("Program count mismatch: expected {}, got {}",
+                config.program_num, self.program_num());
+        }
+
+        Ok(self.data.as_slice())
+    }
+
+    /// Restore this bank into the programs dump, marking every restored
+    /// program as modified.
+    pub fn restore(&self, config: &Config, dump: &mut ProgramsDump) -> Result<()> {
+        let data = self.data_for(config)?;
+        program::load_all_dump(dump, data, Origin::UI);
+        dump.set_all_modified(true);
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Bank {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", BANK_HEADER)?;
+        writeln!(f, "format: {}", BANK_FORMAT_VERSION)?;
+        writeln!(f, "device: {}", self.device)?;
+        writeln!(f, "family: {:04x}", self.family)?;
+        writeln!(f, "member: {:04x}", self.member)?;
+        writeln!(f, "version: {}", self.version)?;
+        writeln!(f, "programs: {}", self.program_num())?;
+        writeln!(f, "program_size: {}", self.program_size)?;
+
+        for (i, data) in self.data.chunks(self.program_size).enumerate() {
+            writeln!(f)?;
+            writeln!(f, "name.{}: {}", i, self.names[i])?;
+            writeln!(f, "data.{}: {}", i, to_hex(data))?;
+        }
+
+        fmt::Result::Ok(())
+    }
+}
+
+impl FromStr for Bank {
+    type Err = Error;
+
+    fn from_str(str: &str) -> Result<Self> {
+        let mut lines = str.lines();
+        if lines.next().map(|l| l.trim()) != Some(BANK_HEADER) {
+            bail!("Not a bank file");
+        }
+
+        let mut get = |key: &str| -> Result<String> {
+            for line in lines.by_ref() {
+                let line = line.trim_end();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let Some((k, v)) = line.split_once(": ").or_else(|| line.split_once(':')) else {
+                    bail!("Malformed line: {:?}", line);
+                };
+                if k != key {
+                    bail!("Expected {:?}, got {:?}", key, k);
+                }
+                return Ok(v.to_string());
+            }
+            bail!("Unexpected end of file, expected {:?}", key);
+        };
+
+        let format = get("format")?.parse::<u32>()
+            .context("Failed to parse bank format")?;
+        if format != BANK_FORMAT_VERSION {
+            bail!("Bank format version not supported: {}", format);
+        }
+        let device = get("device")?;
+        let family = u16::from_str_radix(&get("family")?, 16)
+            .context("Failed to parse device family")?;
+        let member = u16::from_str_radix(&get("member")?, 16)
+            .context("Failed to parse device member")?;
+        let version = get("version")?;
+        let program_num = get("programs")?.parse::<usize>()
+            .context("Failed to parse program count")?;
+        let program_size = get("program_size")?.parse::<usize>()
+            .context("Failed to parse program size")?;
+
+        let mut names = vec![];
+        let mut data = vec![];
+        for i in 0 .. program_num {
+            names.push(get(&format!("name.{}", i))?);
+            let bytes = from_hex(&get(&format!("data.{}", i))?)
+                .with_context(|| format!("Failed to parse data for program {}", i))?;
+            if bytes.len() != program_size {
+                bail!("Program {} size mismatch: expected {}, got {}",
+                    i, program_size, bytes.len());
+            }
+            data.extend(bytes);
+        }
+
+        Ok(Self { device, family, member, version, program_size, names, data })
+    }
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(str: &str) -> Result<Vec<u8>> {
+    let str = str.trim();
+    if !str.is_ascii() {
+        bail!("Invalid hex data");
+    }
+    if str.len() % 2 != 0 {
+        bail!("Odd number of hex digits");
+    }
+    (0 .. str.len()).step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&str[i .. i+2], 16)
+                .map_err(|e| anyhow!("Invalid hex data: {}", e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{config_for_name, register_config};
+    use crate::model::RangeControl;
+    use crate::test_util;
+    use super::*;
+
+    fn config(name: &str, member: u16, program_num: usize) -> Config {
+        let mut config = test_util::config(name);
+        config.family = 0x0020;
+        config.member = member;
+        config.program_num = program_num;
+        config
+    }
+
+    fn get(name: &str) -> &'static Config {
+        register_config(&config("Bank POD", 1, 4));
+        register_config(&config("Bank POD 8", 2, 8));
+        let mut other = config("Bank Other", 3, 4);
+        other.controls.insert("bass".into(), RangeControl { cc: 14, addr: 1, ..Default::default() }.into());
+        register_config(&other);
+        config_for_name(name).unwrap()
+    }
+
+    fn bank(config: &Config) -> Bank {
+        let mut dump = ProgramsDump::new(config);
+        for i in 0 .. config.program_num {
+            let data = dump.data_mut(i).unwrap();
+            data.iter_mut().enumerate().for_each(|(j, b)| *b = (i * 16 + j) as u8);
+            data[4 .. 12].copy_from_slice(format!("Tone {:<3}", i).as_bytes());
+            dump.update_name_from_data(i, Origin::MIDI);
+        }
+        Bank::from_dump(config, &dump, "0200")
+    }
+
+    /// Replace the line starting with `key` in the bank file text
+    fn edit(str: &str, key: &str, line: &str) -> String {
+        str.lines()
+            .map(|l| if l.starts_with(key) { line } else { l })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn round_trip() {
+        let config = get("Bank POD");
+        let bank = bank(config);
+        let parsed = bank.to_string().parse::<Bank>().unwrap();
+
+        assert_eq!(parsed.device, "Bank POD");
+        assert_eq!(parsed.version, "0200");
+        assert_eq!(parsed.names, bank.names);
+        assert_eq!(parsed.names[3], "Tone 3");
+        assert_eq!(parsed.data, bank.data);
+
+        let mut dump = ProgramsDump::new(config);
+        parsed.restore(config, &mut dump).unwrap();
+        assert_eq!(dump.data(2).unwrap(), &bank.data[24 .. 36]);
+        assert!(dump.modified(2));
+    }
+
+    #[test]
+    fn truncated_or_oversized_data() {
+        let str = bank(get("Bank POD")).to_string();
+        let data = str.lines().find(|l| l.starts_with("data.1:")).unwrap();
+
+        let truncated = edit(&str, "data.1:", &data[.. data.len() - 2]);
+        assert!(truncated.parse::<Bank>().is_err());
+        let odd = edit(&str, "data.1:", &data[.. data.len() - 1]);
+        assert!(odd.parse::<Bank>().is_err());
+        let oversized = edit(&str, "data.1:", &format!("{}00", data));
+        assert!(oversized.parse::<Bank>().is_err());
+        let invalid = edit(&str, "data.1:", &data.replace(": 1", ": x"));
+        assert!(invalid.parse::<Bank>().is_err());
+
+        let cut = str.lines().take_while(|l| !l.starts_with("data.3:")).collect::<Vec<_>>().join("\n");
+        assert!(cut.parse::<Bank>().is_err());
+    }
+
+    #[test]
+    fn malformed_header() {
+        let str = bank(get("Bank POD")).to_string();
+        assert!(str.replacen(BANK_HEADER, "# something else", 1).parse::<Bank>().is_err());
+        assert!(edit(&str, "format:", "format: 2").parse::<Bank>().is_err());
+        assert!(edit(&str, "family:", "family: xyz").parse::<Bank>().is_err());
+        assert!(edit(&str, "programs:", "nonsense").parse::<Bank>().is_err());
+    }
+
+    #[test]
+    fn wrong_model() {
+        let bank = bank(get("Bank Other"));
+        let parsed = bank.to_string().parse::<Bank>().unwrap();
+        let mut dump = ProgramsDump::new(get("Bank POD"));
+        assert!(parsed.restore(get("Bank POD"), &mut dump).is_err());
+        assert!(!dump.modified(0));
+
+        let mut unknown = bank.clone();
+        unknown.member = 0x7e;
+        assert!(unknown.data_for(get("Bank POD")).is_err());
+    }
+
+    #[test]
+    fn wrong_program_count() {
+        let str = bank(get("Bank POD")).to_string();
+        // the file itself has fewer programs than it claims
+        assert!(edit(&str, "programs:", "programs: 8").parse::<Bank>().is_err());
+
+        // the file is consistent, but for a different program count
+        let parsed = str.parse::<Bank>().unwrap();
+        let mut program_8 = parsed.clone();
+        program_8.member = 2;
+        assert!(program_8.data_for(get("Bank POD 8")).is_err());
+    }
+}
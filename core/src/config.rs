@@ -1,22 +1,77 @@
+use std::sync::RwLock;
+use anyhow::*;
+use core::result::Result::Ok;
+use log::info;
 use crate::model::Config;
 
-// TODO: remove the "static mut" hack!
-pub static mut PODS: Vec<Config> = Vec::new();
+struct RegisteredConfig {
+    config: &'static Config,
+    base: Option<&'static Config>,
+}
 
-pub fn register_config(config: &Config) {
-    unsafe {
-        PODS.push(config.clone());
+/// Registry of device configs. Registered configs are never freed, so that
+/// references to them stay valid even after a config is replaced.
+static CONFIGS: RwLock<Vec<RegisteredConfig>> = RwLock::new(Vec::new());
+
+fn register(config: Config, base: Option<&'static Config>) -> &'static Config {
+    let config: &'static Config = Box::leak(Box::new(config));
+    let entry = RegisteredConfig { config, base };
+
+    let mut configs = CONFIGS.write().unwrap();
+    match configs.iter_mut().find(|e| e.config.name == config.name) {
+        Some(e) => {
+            info!("Replacing config {:?}", config.name);
+            *e = entry;
+        }
+        None => {
+            configs.push(entry);
+        }
     }
+    config
 }
 
-pub fn configs() -> &'static Vec<Config> {
-    unsafe {
-        return &PODS;
-    }
+/// Register a device config. A config with the same name as an already
+/// registered config replaces it.
+pub fn register_config(config: &Config) {
+    register(config.clone(), None);
+}
+
+/// Register a device config that is handled by the same device module
+/// as a registered `base` config
+pub fn register_config_with_base(config: Config, base: &'static Config) -> &'static Config {
+    register(config, Some(base))
+}
+
+/// All registered device configs, in registration order
+pub fn configs() -> Vec<&'static Config> {
+    CONFIGS.read().unwrap().iter().map(|e| e.config).collect()
 }
 
 pub fn config_for_id(family: u16, member: u16) -> Option<&'static Config> {
-    configs().iter().find(|config| {
+    configs().into_iter().find(|config| {
         family == config.family && member == config.member
     })
-}
\ No newline at end of file
+}
+
+pub fn config_for_name(name: &str) -> Option<&'static Config> {
+    configs().into_iter().find(|config| config.name == name)
+}
+
+/// Find a config by its index in `configs()` or, ignoring case, by name,
+/// as given on the command line
+pub fn config_for_str(config_str: &str) -> Result<&'static Config> {
+    if let Ok(index) = config_str.parse::<usize>() {
+        return configs().get(index).copied()
+            .with_context(|| format!("Config with index {} not found!", index));
+    }
+    configs().into_iter()
+        .find(|c| c.name.eq_ignore_ascii_case(config_str))
+        .with_context(|| format!("Config with name {:?} not found!", config_str))
+}
+
+/// The config that a registered `config` was based on, if any
+pub fn config_base(config: &Config) -> Option<&'static Config> {
+    CONFIGS.read().unwrap().iter()
+        .find(|e| std::ptr::eq(e.config, config))
+        .and_then(|e| e.base)
+}
@@ -1,6 +1,7 @@
 use std::fmt::{Debug, Formatter};
 use std::sync::{Arc, Mutex};
 use crate::controller::*;
+use crate::dispatch::BufferReroute;
 use crate::dump::ProgramsDump;
 use crate::edit::EditBuffer;
 use crate::event::{EventSender, Origin, Program};
@@ -14,6 +15,7 @@ pub struct Ctx {
     pub controller: Arc<Mutex<Controller>>,
     pub edit: Arc<Mutex<EditBuffer>>,
     pub dump: Arc<Mutex<ProgramsDump>>,
+    pub reroute: BufferReroute,
 
     pub ui_controller: Arc<Mutex<Controller>>,
 
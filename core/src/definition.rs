@@ -0,0 +1,380 @@
+//! Device config definitions loaded from TOML or JSON files, so that a
+//! tweaked firmware or a sibling model can be supported without writing
+//! a device module.
+//!
+//! A definition is based on a registered config, which provides the
+//! device handler and the user interface. All fields of the definition
+//! except `name` and `base` are optional and replace the corresponding
+//! fields of the base config. Controls are added to the base controls,
+//! replacing base controls of the same name, and `remove_controls` are
+//! removed. `select_labels` are added to the base select labels in the
+//! same way. A definition with the same name as its base replaces the
+//! base config.
+//!
+//! ```toml
+//! name = "POD 2.0 (tweaked)"
+//! base = "POD 2.0"
+//! member = 0x0301
+//! flags = ["MANUAL_MODE", "ALL_PROGRAMS_DUMP"]
+//!
+//! [controls.drive]
+//! type = "range_control"
+//! cc = 13
+//! addr = 9
+//! format = { interpolate = { points = [[0, 0.0], [127, 10.0]], format = "{val:1.1f}" } }
+//!
+//! [controls.amp_select]
+//! type = "select"
+//! cc = 12
+//! addr = 8
+//! ```
+//!
+//! Range configs and formats that are implemented in code, such as
+//! `RangeConfig::Function` or `Format::Callback`, cannot be defined in
+//! files.
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use anyhow::*;
+use bitflags::Flags;
+use log::*;
+use serde::Deserialize;
+use crate::config::{config_for_name, configs, register_config_with_base};
+use crate::model::*;
+use crate::user_config_path;
+
+/// Name of the device definitions directory in the user configuration directory
+pub const DEFINITIONS_DIR: &str = "devices";
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigDefinition {
+    pub name: String,
+    /// Name of the registered config this definition is based on
+    pub base: String,
+    pub family: Option<u16>,
+    pub member: Option<u16>,
+
+    pub program_size: Option<usize>,
+    pub program_num: Option<usize>,
+
+    pub pc_manual_mode: Option<usize>,
+    pub pc_tuner: Option<usize>,
+    pub pc_offset: Option<usize>,
+
+    pub toggles: Option<Vec<Toggle>>,
+    pub amp_models: Option<Vec<Amp>>,
+    pub cab_models: Option<Vec<String>>,
+    pub effects: Option<Vec<Effect>>,
+    pub select_labels: HashMap<String, Vec<String>>,
+    pub controls: HashMap<String, Control>,
+    pub remove_controls: Vec<String>,
+    pub init_controls: Option<Vec<String>>,
+
+    pub out_cc_edit_buffer_dump_req: Option<Vec<u8>>,
+    pub in_cc_edit_buffer_dump_req: Option<Vec<u8>>,
+
+    pub program_name_addr: Option<usize>,
+    pub program_name_length: Option<usize>,
+    /// Names of `DeviceFlags`
+    pub flags: Option<Vec<String>>,
+    /// Names of `MidiQuirks`
+    pub midi_quirks: Option<Vec<String>>,
+}
+
+macro_rules! replace_fields {
+    ($config:ident, $def:ident, $($field:ident),*) => {
+        $( if let Some(v) = $def.$field { $config.$field = v; } )*
+    };
+}
+
+fn parse_flags<F: Flags>(names: &[String]) -> Result<F> {
+    names.iter().try_fold(F::empty(), |flags, name| {
+        let flag = F::from_name(name)
+            .with_context(|| format!("Unknown flag {:?}", name))?;
+        Ok(flags.union(flag))
+    })
+}
+
+impl ConfigDefinition {
+    /// Load a definition from a `.toml` or `.json` file
+    pub fn load(path: &Path) -> Result<Self> {
+        let str = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {:?}", path))?;
+        let ext = path.extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match ext.as_str() {
+            "toml" => Self::from_toml(&str),
+            "json" => serde_json::from_str(&str).map_err(|e| e.into()),
+            _ => bail!("Unsupported definition file type {:?}", ext)
+        }.with_context(|| format!("Failed to parse {:?}", path))
+    }
+
+    pub fn from_toml(str: &str) -> Result<Self> {
+        toml::from_str(str).map_err(|e| e.into())
+    }
+
+    /// Build a config from this definition on top of a `base` config
+    pub fn into_config(self, base: &Config) -> Result<Config> {
+        if self.name.is_empty() {
+            bail!("Definition has no name");
+        }
+        let mut config = base.clone();
+        config.name = self.name.clone();
+
+        let def = self;
+        replace_fields!(config, def,
+            family, member, program_size, program_num,
+            toggles, amp_models, cab_models, effects, init_controls,
+            out_cc_edit_buffer_dump_req, in_cc_edit_buffer_dump_req,
+            program_name_addr, program_name_length);
+        if def.pc_manual_mode.is_some() { config.pc_manual_mode = def.pc_manual_mode; }
+        if def.pc_tuner.is_some() { config.pc_tuner = def.pc_tuner; }
+        if def.pc_offset.is_some() { config.pc_offset = def.pc_offset; }
+        if let Some(flags) = def.flags.as_ref() {
+            config.flags = parse_flags(flags)?;
+        }
+        if let Some(quirks) = def.midi_quirks.as_ref() {
+            config.midi_quirks = parse_flags(quirks)?;
+        }
+
+        for name in def.remove_controls.iter() {
+            if config.controls.remove(name).is_none() {
+                bail!("Control {:?} to remove not found", name);
+            }
+        }
+        config.controls.extend(def.controls);
+        config.select_labels.extend(def.select_labels);
+
+        Ok(config)
+    }
+}
+
+fn validate_control(config: &Config, name: &str, control: &Control) -> Result<()> {
+    if let Some((addr, len)) = control.get_addr() {
+        if addr as usize + len as usize > config.program_size {
+            bail!("Control {:?} address {} is outside of the program ({} bytes)",
+                name, addr, config.program_size);
+        }
+    }
+
+    let (range, format) = match control {
+        Control::RangeControl(RangeControl { config, format, .. }) |
+        Control::AddrRangeControl(AddrRangeControl { config, format, .. }) |
+        Control::VirtualRangeControl(VirtualRangeControl { config, format }) => (config, format),
+        _ => return Ok(())
+    };
+    match range {
+        RangeConfig::Short { from, to, .. } if from > to =>
+            bail!("Control {:?} range {}..{} is empty", name, from, to),
+        RangeConfig::Long { from, to } if from > to =>
+            bail!("Control {:?} range {}..{} is empty", name, from, to),
+        RangeConfig::Steps { steps } if steps.is_empty() =>
+            bail!("Control {:?} has no steps", name),
+        _ => {}
+    }
+    match format {
+        Format::Labels(labels) if labels.is_empty() =>
+            bail!("Control {:?} has no labels", name),
+        Format::Interpolate(FormatInterpolate { points, .. }) if points.len() < 2 =>
+            bail!("Control {:?} needs at least two points to interpolate", name),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Check that a config is consistent before registering it
+pub fn validate_config(config: &Config) -> Result<()> {
+    if config.name.is_empty() {
+        bail!("Config has no name");
+    }
+    if config.program_num == 0 || config.program_size == 0 {
+        bail!("Config {:?} has no programs", config.name);
+    }
+    if config.program_name_addr + config.program_name_length > config.program_size {
+        bail!("Config {:?} program name is outside of the program ({} bytes)",
+            config.name, config.program_size);
+    }
+
+    let mut seen_cc = HashMap::new();
+    for (name, control) in config.controls.iter() {
+        validate_control(config, name, control)
+            .with_context(|| format!("Config {:?} failed validation", config.name))?;
+        if let Some(cc) = control.get_cc() {
+            if let Some(other) = seen_cc.insert(cc, name) {
+                bail!("Config {:?} contains multiple controls for CC={}: {:?} and {:?}",
+                    config.name, cc, other, name);
+            }
+        }
+    }
+
+    // effect entry controls name UI widgets, which need not be config controls
+    // an empty position control is a non-moving toggle
+    let control_names = config.toggles.iter()
+        .map(|t| &t.position_control)
+        .filter(|name| !name.is_empty())
+        .chain(config.init_controls.iter());
+    for name in control_names {
+        if !config.controls.contains_key(name) {
+            bail!("Config {:?} refers to an unknown control {:?}", config.name, name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Build, validate and register a config from a definition
+pub fn register_definition(def: ConfigDefinition) -> Result<&'static Config> {
+    let base = config_for_name(&def.base)
+        .with_context(|| format!("Base config {:?} not found", def.base))?;
+    let config = def.into_config(base)?;
+    validate_config(&config)?;
+
+    let same_id = configs().into_iter().find(|c| {
+        c.family == config.family && c.member == config.member && c.name != config.name
+    });
+    if let Some(other) = same_id {
+        bail!("Config {:?} has the same family/member id as {:?}", config.name, other.name);
+    }
+
+    Ok(register_config_with_base(config, base))
+}
+
+/// Load and register all definitions in `dir`. Definitions that fail
+/// to load or validate are skipped.
+pub fn register_definitions(dir: &Path) -> Vec<&'static Config> {
+    let Result::Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut paths = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|path| {
+            matches!(path.extension().and_then(|ext| ext.to_str()), Some("toml") | Some("json"))
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let mut registered = vec![];
+    for path in paths {
+        match ConfigDefinition::load(&path).and_then(register_definition) {
+            Result::Ok(config) => {
+                info!("Registered config {:?} from {:?}", config.name, path);
+                registered.push(config);
+            }
+            Err(e) => {
+                error!("Failed to load device definition {:?}: {:#}", path, e);
+            }
+        }
+    }
+    registered
+}
+
+/// Load and register all definitions in the user configuration directory
+pub fn register_user_definitions() -> Vec<&'static Config> {
+    user_config_path(DEFINITIONS_DIR)
+        .map(|dir| register_definitions(&dir))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    fn base() -> Config {
+        let mut config = test_util::config("Base");
+        config.family = 1;
+        config.member = 1;
+        config.controls.insert("reverb_enable".into(), SwitchControl { cc: 36, addr: 1, ..Default::default() }.into());
+        config
+    }
+
+    #[test]
+    fn definition_from_toml() {
+        let def = ConfigDefinition::from_toml(r#"
+            name = "Sibling"
+            base = "Base"
+            member = 2
+            flags = ["MANUAL_MODE"]
+            remove_controls = ["reverb_enable"]
+            init_controls = ["mode"]
+
+            [controls.drive]
+            type = "range_control"
+            cc = 14
+            addr = 2
+            format = { labels = ["low", "high"] }
+            config = { short = { from = 0, to = 1, edge = true } }
+
+            [controls.mode]
+            type = "select"
+            cc = 15
+            addr = 3
+        "#).unwrap();
+        let config = def.into_config(&base()).unwrap();
+        validate_config(&config).unwrap();
+
+        assert_eq!(config.name, "Sibling");
+        assert_eq!((config.family, config.member), (1, 2));
+        assert_eq!(config.program_size, 12);
+        assert_eq!(config.flags, DeviceFlags::MANUAL_MODE);
+        assert!(config.controls.get("reverb_enable").is_none());
+        assert_eq!(config.init_controls, vec!["mode".to_string()]);
+        match config.controls.get("drive") {
+            Some(Control::RangeControl(RangeControl {
+                cc: 14, addr: 2, config: RangeConfig::Short { from: 0, to: 1, .. }, format: Format::Labels(labels)
+            })) => assert_eq!(labels.len(), 2),
+            c => panic!("Unexpected control: {:?}", c)
+        }
+    }
+
+    #[test]
+    fn invalid_definitions() {
+        let check = |str: &str| {
+            ConfigDefinition::from_toml(str)
+                .and_then(|def| def.into_config(&base()))
+                .and_then(|config| validate_config(&config))
+        };
+
+        // duplicate CC
+        assert!(check(r#"
+            name = "Bad"
+            [controls.gain]
+            type = "range_control"
+            cc = 13
+        "#).is_err());
+        // address outside of the program
+        assert!(check(r#"
+            name = "Bad"
+            [controls.gain]
+            type = "switch_control"
+            cc = 20
+            addr = 12
+        "#).is_err());
+        // unknown control
+        assert!(check(r#"
+            name = "Bad"
+            init_controls = ["gain"]
+        "#).is_err());
+        // unknown flag
+        assert!(check(r#"
+            name = "Bad"
+            flags = ["NO_SUCH_FLAG"]
+        "#).is_err());
+        // unknown field
+        assert!(check(r#"
+            name = "Bad"
+            programs = 10
+        "#).is_err());
+        // empty range
+        assert!(check(r#"
+            name = "Bad"
+            [controls.gain]
+            type = "virtual_range_control"
+            config = { long = { from = 100, to = 10 } }
+        "#).is_err());
+
+        assert!(check(r#"name = "Good""#).is_ok());
+    }
+}
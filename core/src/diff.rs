@@ -0,0 +1,213 @@
+use crate::controller::*;
+use crate::edit::EditBuffer;
+use crate::handler::Handler;
+use crate::model::*;
+use crate::program;
+
+/// A control that has different values in two compared programs,
+/// with both values rendered through the control's format
+#[derive(Clone, Debug)]
+pub struct ControlDiff {
+    pub name: String,
+    pub a: String,
+    pub b: String,
+}
+
+fn format_range(config: &RangeConfig, format: &Format<RangeConfig>, value: u16) -> String {
+    let v = value as f64;
+    match format {
+        Format::Callback(f) => f(config, v),
+        Format::Data(data) => data.format(v),
+        Format::Interpolate(data) => data.format(v),
+        Format::Labels(labels) => labels.get(value as usize).cloned()
+            .unwrap_or_else(|| value.to_string()),
+        Format::None => value.to_string()
+    }
+}
+
+/// Render a control value the same way it is shown in the UI. The labels
+/// of select controls are looked up through `handler`, as they can depend
+/// on the other control values in `controller`.
+pub fn format_control_value(config: &Config, handler: &dyn Handler, controller: &Controller,
+                            name: &str, control: &Control, value: u16) -> String {
+    match control {
+        Control::RangeControl(RangeControl { config: c, format, .. }) |
+        Control::AddrRangeControl(AddrRangeControl { config: c, format, .. }) |
+        Control::VirtualRangeControl(VirtualRangeControl { config: c, format, .. }) => {
+            format_range(c, format, value)
+        }
+        Control::SwitchControl(_) | Control::MidiSwitchControl(_) => {
+            (if value != 0 { "on" } else { "off" }).to_string()
+        }
+        Control::Select(_) | Control::MidiSelect(_) | Control::VirtualSelect(_) => {
+            handler.select_labels(config, name, controller)
+                .and_then(|labels| labels.get(value as usize).cloned())
+                .filter(|label| !label.is_empty())
+                .unwrap_or_else(|| value.to_string())
+        }
+        _ => value.to_string()
+    }
+}
+
+fn decode(config: &Config, handler: &dyn Handler, data: &[u8]) -> EditBuffer {
+    let mut edit = EditBuffer::new(config);
+    program::load_patch_dump_ctrl(&mut edit, data, |controller, name, buffer| {
+        handler.control_value_from_buffer(controller, name, buffer)
+    });
+    edit
+}
+
+/// Compare two program buffers of a device with the given `config`,
+/// decoding them with the module's `handler`. Returns the program name
+/// (as control "name") and all controls that differ between the two.
+pub fn diff_programs(config: &Config, handler: &dyn Handler, a: &[u8], b: &[u8]) -> Vec<ControlDiff> {
+    let a = decode(config, handler, a);
+    let b = decode(config, handler, b);
+
+    let mut diffs = vec![];
+    let (name_a, name_b) = (a.name(), b.name());
+    if name_a.trim() != name_b.trim() {
+        diffs.push(ControlDiff { name: "name".into(), a: name_a, b: name_b });
+    }
+
+    let ca = a.controller_locked();
+    let cb = b.controller_locked();
+    let mut controls = ca.ordered_controls();
+    controls.reverse(); // in buffer order
+    for (name, control) in controls.iter() {
+        let (Some(va), Some(vb)) = (ca.get(name), cb.get(name)) else {
+            continue;
+        };
+        if va == vb {
+            continue;
+        }
+        diffs.push(ControlDiff {
+            name: name.clone(),
+            a: format_control_value(config, handler, &ca, name, control, va),
+            b: format_control_value(config, handler, &cb, name, control, vb),
+        });
+    }
+
+    diffs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, TestHandler};
+
+    fn config() -> Config {
+        let mut config = test_util::config("Diff POD");
+        config.amp_models = ["Clean", "Crunch"].iter()
+            .map(|name| Amp { name: name.to_string(), ..Default::default() })
+            .collect();
+        config.select_labels.insert("stomp_select".into(), vec!["Fuzz".into(), "Comp".into()]);
+        config.controls.insert("drive".into(), RangeControl {
+            cc: 13, addr: 0, format: Format::Labels(vec!["low".into(), "high".into()]),
+            ..Default::default()
+        }.into());
+        config.controls.insert("amp_select".into(), Select { cc: 12, addr: 1 }.into());
+        config.controls.insert("stomp_select".into(), Select { cc: 75, addr: 2 }.into());
+        config.controls.insert("reverb_enable".into(), SwitchControl { cc: 36, addr: 3, ..Default::default() }.into());
+        config
+    }
+
+    fn program(values: [u8; 4], name: &str) -> Vec<u8> {
+        let mut data = values.to_vec();
+        data.extend(format!("{:8}", name).bytes());
+        data
+    }
+
+    #[test]
+    fn same_programs_have_no_diffs() {
+        let config = config();
+        let a = program([0, 1, 1, 1], "Tone");
+
+        assert!(diff_programs(&config, &TestHandler, &a, &a).is_empty());
+    }
+
+    #[test]
+    fn diffs_are_labeled() {
+        let config = config();
+        let a = program([0, 0, 0, 0], "Tone");
+        let b = program([1, 1, 1, 1], "Other");
+
+        let diffs = diff_programs(&config, &TestHandler, &a, &b).into_iter()
+            .map(|d| (d.name, d.a, d.b))
+            .collect::<Vec<_>>();
+
+        let expected = [
+            ("name", "Tone", "Other"),
+            ("drive", "low", "high"),
+            ("amp_select", "Clean", "Crunch"),
+            ("stomp_select", "Fuzz", "Comp"),
+            ("reverb_enable", "off", "on"),
+        ].map(|(n, a, b)| (n.to_string(), a.to_string(), b.to_string()));
+        assert_eq!(diffs, expected);
+    }
+
+    #[test]
+    fn unknown_select_values_are_numbers() {
+        let config = config();
+        let control = config.control_by_name("stomp_select").unwrap();
+
+        let controller = Controller::new(config.controls.clone());
+        let label = |name, control, v| format_control_value(&config, &TestHandler, &controller, name, control, v);
+
+        assert_eq!(label("stomp_select", control, 1), "Comp");
+        assert_eq!(label("stomp_select", control, 5), "5");
+        let control = Control::from(Select { cc: 19, addr: 5 });
+        assert_eq!(label("mystery_select", &control, 3), "3");
+    }
+
+    #[test]
+    fn effect_entries_are_labeled() {
+        let mut config = config();
+        let entry = |id| Some(EffectEntry { id, ..Default::default() });
+        config.effects = vec![
+            Effect { name: "Chorus".into(), clean: entry(2), delay: entry(0) },
+            Effect { name: "Swell".into(), clean: None, delay: entry(1) },
+        ];
+        let control = Control::from(Select { cc: 19, addr: 5 });
+        let controller = Controller::new(config.controls.clone());
+        let label = |v| format_control_value(&config, &TestHandler, &controller, "effect_select:raw", &control, v);
+
+        assert_eq!(label(0), "Chorus + Delay");
+        assert_eq!(label(1), "Swell + Delay");
+        assert_eq!(label(2), "Chorus");
+        assert_eq!(label(3), "3");
+    }
+
+    /// Labels the stomp models by the amp model
+    struct AmpHandler;
+
+    impl Handler for AmpHandler {
+        fn control_value_from_buffer(&self, controller: &mut Controller, name: &str, buffer: &[u8]) {
+            TestHandler.control_value_from_buffer(controller, name, buffer)
+        }
+
+        fn select_labels(&self, config: &Config, name: &str, controller: &Controller) -> Option<Vec<String>> {
+            match (name, controller.get("amp_select")) {
+                ("stomp_select", Some(1)) => Some(vec!["Crunch Fuzz".into(), "Crunch Comp".into()]),
+                _ => config.select_labels(name)
+            }
+        }
+    }
+
+    #[test]
+    fn labels_depend_on_other_controls() {
+        let config = config();
+        let a = program([0, 0, 0, 0], "Tone");
+        let b = program([0, 1, 1, 0], "Tone");
+
+        let diffs = diff_programs(&config, &AmpHandler, &a, &b).into_iter()
+            .map(|d| (d.name, d.a, d.b))
+            .collect::<Vec<_>>();
+
+        let expected = [
+            ("amp_select", "Clean", "Crunch"),
+            ("stomp_select", "Fuzz", "Crunch Comp"),
+        ].map(|(n, a, b)| (n.to_string(), a.to_string(), b.to_string()));
+        assert_eq!(diffs, expected);
+    }
+}
@@ -1,29 +1,39 @@
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use log::*;
-use once_cell::sync::Lazy;
 use crate::context::Ctx;
 use crate::event::*;
 use crate::midi::{Channel, MidiMessage};
 
-/// DISPATCH_BUFFER_REROUTE is a hash map of Buffer -> Buffer routing,
-/// used when an unmodified program (load from device) is requested into
-/// the edit buffer (possibly, into a different program) from a program
+/// BufferReroute is a hash map of Buffer -> Buffer routing of a device
+/// context, used when an unmodified program (load from device) is requested
+/// into the edit buffer (possibly, into a different program) from a program
 /// that is not the current program.
-static DISPATCH_BUFFER_REROUTE: Lazy<Mutex<HashMap<Buffer, Buffer>>> = Lazy::new(|| {
-    Mutex::new(HashMap::new())
-});
+#[derive(Clone, Default)]
+pub struct BufferReroute(Arc<Mutex<HashMap<Buffer, Buffer>>>);
 
-pub fn dispatch_buffer_set(from: Buffer, to: Buffer) {
-    DISPATCH_BUFFER_REROUTE.lock().unwrap().insert(from, to);
-}
+impl BufferReroute {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-pub fn dispatch_buffer_get(from: &Buffer) -> Option<Buffer> {
-    DISPATCH_BUFFER_REROUTE.lock().unwrap().remove(from)
-}
+    pub fn set(&self, from: Buffer, to: Buffer) {
+        self.0.lock().unwrap().insert(from, to);
+    }
 
-pub fn dispatch_buffer_clear() {
-    DISPATCH_BUFFER_REROUTE.lock().unwrap().clear()
+    pub fn get(&self, from: &Buffer) -> Option<Buffer> {
+        self.0.lock().unwrap().remove(from)
+    }
+
+    /// Buffer that the data of buffer `from` will be rerouted to, without
+    /// removing the reroute
+    pub fn peek(&self, from: &Buffer) -> Option<Buffer> {
+        self.0.lock().unwrap().get(from).cloned()
+    }
+
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear()
+    }
 }
 
 // -------------------------------------------------------------
@@ -81,7 +91,7 @@ pub fn midi_pc_out_handler(ctx: &Ctx, midi_message: &MidiMessage) {
 }
 
 pub fn pc_handler(ctx: &Ctx, event: &ProgramChangeEvent) {
-    dispatch_buffer_clear();
+    ctx.reroute.clear();
     ctx.handler.pc_handler(ctx, event);
 }
 
@@ -119,7 +129,7 @@ pub fn copy_handler(ctx: &Ctx, event: &BufferCopyEvent) {
 }
 
 pub fn buffer_handler(ctx: &Ctx, event: &BufferDataEvent) {
-    match dispatch_buffer_get(&event.buffer) {
+    match ctx.reroute.get(&event.buffer) {
         Some(buffer) => {
             // As part of a copy, we must also rewrite the origin so that the
             // data looks like it is coming from the MIDI side, so that the
@@ -171,4 +181,72 @@ pub fn new_device_handler(ctx: &Ctx) {
 
 pub fn marker_handler(ctx: &Ctx, marker: u32) {
     ctx.handler.marker_handler(ctx, marker)
-}
\ No newline at end of file
+}
+
+// -------------------------------------------------------------
+
+/// Run the device-specific handler of an app event. This is the same for
+/// all front-ends, which only add their own handling of the events on
+/// top. Returns `false` if the event is not handled by the device.
+pub fn dispatch(ctx: &Ctx, event: &AppEvent) -> bool {
+    match event {
+        // device inquiry
+        AppEvent::MidiMsgIn(msg @ MidiMessage::UniversalDeviceInquiry { .. }) |
+        AppEvent::MidiMsgIn(msg @ MidiMessage::UniversalDeviceInquiryResponse { .. }) => {
+            midi_udi_handler(ctx, msg);
+        }
+
+        // control change
+        AppEvent::MidiMsgIn(msg @ MidiMessage::ControlChange { .. }) => {
+            midi_cc_in_handler(ctx, msg);
+        }
+        AppEvent::MidiMsgOut(msg @ MidiMessage::ControlChange { .. }) => {
+            midi_cc_out_handler(ctx, msg);
+        }
+        AppEvent::ControlChange(cc) => {
+            cc_handler(ctx, cc);
+        }
+
+        // program change
+        AppEvent::MidiMsgIn(msg @ MidiMessage::ProgramChange { .. }) => {
+            midi_pc_in_handler(ctx, msg);
+        }
+        AppEvent::MidiMsgOut(msg @ MidiMessage::ProgramChange { .. }) => {
+            midi_pc_out_handler(ctx, msg);
+        }
+        AppEvent::ProgramChange(pc) => {
+            pc_handler(ctx, pc);
+        }
+
+        // store & load
+        AppEvent::Load(event) => {
+            load_handler(ctx, event)
+        }
+        AppEvent::Store(event) => {
+            store_handler(ctx, event)
+        }
+        AppEvent::Copy(event) => {
+            copy_handler(ctx, event)
+        }
+        AppEvent::BufferData(event) => {
+            buffer_handler(ctx, event)
+        }
+        AppEvent::Modified(event) => {
+            modified_handler(ctx, event);
+        }
+
+        // other
+        AppEvent::MidiMsgIn(msg) => {
+            midi_in_handler(ctx, msg);
+        }
+        AppEvent::MidiMsgOut(msg) => {
+            midi_out_handler(ctx, msg);
+        }
+        AppEvent::Marker(marker) => {
+            marker_handler(ctx, *marker);
+        }
+
+        _ => return false
+    }
+    true
+}
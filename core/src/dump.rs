@@ -9,7 +9,8 @@ pub struct ProgramsDump {
     program_size: usize,
     data: Box<[u8]>,
     modified: Box<[bool]>,
-    names: ProgramNames
+    names: ProgramNames,
+    restored: bool
 }
 
 impl ProgramsDump {
@@ -20,7 +21,7 @@ impl ProgramsDump {
         let modified = vec![false; program_num * program_size].into_boxed_slice();
         let names = ProgramNames::new(config);
 
-        Self { program_num, program_size, data, modified, names }
+        Self { program_num, program_size, data, modified, names, restored: false }
     }
 
     pub fn broadcast_names(&mut self, tx: Option<broadcast::Sender<Event<usize,String>>>) {
@@ -71,6 +72,16 @@ impl ProgramsDump {
     pub fn set_all_modified(&mut self, modified: bool) {
         self.modified.iter_mut().for_each(|m| *m = modified);
     }
+
+    /// Programs were restored from a local copy, so they do not need
+    /// to be loaded from the device when it is connected
+    pub fn restored(&self) -> bool {
+        self.restored
+    }
+
+    pub fn set_restored(&mut self, restored: bool) {
+        self.restored = restored;
+    }
 }
 fn nth_chunk(data: &[u8], page: usize, page_size: usize) -> Option<&[u8]> {
     data.chunks(page_size).nth(page)
@@ -0,0 +1,314 @@
+use std::sync::{Arc, Mutex};
+use anyhow::*;
+use async_trait::async_trait;
+use log::*;
+use once_cell::sync::Lazy;
+use tokio::sync::mpsc;
+use crate::controller::*;
+use crate::handler::BoxedHandler;
+use crate::midi::{Channel, MidiMessage};
+use crate::midi_io::{MidiIn, MidiOut};
+use crate::model::{AbstractControl, Config, DeviceFlags};
+use crate::program_id_string;
+use crate::str_encoder::StrEncoder;
+
+/// An in-process software device that can be connected to instead of
+/// a real device through a pair of emulator MIDI ports
+pub trait EmulatedDevice: Send {
+    fn config(&self) -> &'static Config;
+
+    /// Process a MIDI message sent to the device, returning the messages
+    /// the device sends back in response
+    fn message(&mut self, msg: MidiMessage) -> Vec<MidiMessage>;
+}
+
+impl<T: EmulatedDevice + ?Sized> EmulatedDevice for Box<T> {
+    fn config(&self) -> &'static Config {
+        (**self).config()
+    }
+
+    fn message(&mut self, msg: MidiMessage) -> Vec<MidiMessage> {
+        (**self).message(msg)
+    }
+}
+
+/// An emulated device shared between its MIDI ports and, possibly,
+/// the code that set it up
+pub type SharedEmulatedDevice = Arc<Mutex<dyn EmulatedDevice>>;
+
+/// Program number selected by a MIDI PC message `program`. Returns `None`
+/// for manual mode, tuner or programs out of range.
+pub fn pc_program(config: &Config, program: u8) -> Option<usize> {
+    let program = program as usize;
+    if config.pc_manual_mode == Some(program) || config.pc_tuner == Some(program) {
+        return None;
+    }
+    program.checked_sub(config.pc_offset.unwrap_or_default())
+        .filter(|p| *p < config.program_num)
+}
+
+fn check_size(buffer: &str, data: Vec<u8>, expected: usize) -> Option<Vec<u8>> {
+    if data.len() != expected {
+        error!("Emulator: {} size mismatch: expected {}, got {}", buffer, expected, data.len());
+        return None;
+    }
+    Some(data)
+}
+
+/// Emulator of devices speaking the POD 2.0 sysex protocol: POD 2.0,
+/// POD Pro, Pocket POD, etc.
+pub struct Pod2Emulator {
+    config: &'static Config,
+    handler: BoxedHandler,
+    controller: Controller,
+    channel: u8,
+    edit: Vec<u8>,
+    programs: Vec<u8>,
+}
+
+impl Pod2Emulator {
+    pub fn new(config: &'static Config, handler: BoxedHandler) -> Self {
+        let controller = Controller::new(config.controls.clone());
+        let edit = vec![0u8; config.program_size];
+
+        // start with empty programs named after their program ids
+        let encoder = StrEncoder::new(config);
+        let mut programs = vec![0u8; config.program_size * config.program_num];
+        for (i, program) in programs.chunks_mut(config.program_size).enumerate() {
+            encoder.str_to_buffer(&format!("Program {}", program_id_string(i)), program);
+        }
+
+        Self { config, handler, controller, channel: Channel::num(0), edit, programs }
+    }
+
+    fn program(&self, patch: usize) -> Option<&[u8]> {
+        let size = self.config.program_size;
+        self.programs.get(patch * size .. (patch + 1) * size)
+    }
+
+    fn program_mut(&mut self, patch: usize) -> Option<&mut [u8]> {
+        let size = self.config.program_size;
+        self.programs.get_mut(patch * size .. (patch + 1) * size)
+    }
+
+    fn patch_dump(&self, patch: u8) -> Option<MidiMessage> {
+        let Some(data) = self.program(patch as usize) else {
+            warn!("Emulator: program {} out of bounds", patch);
+            return None;
+        };
+        Some(MidiMessage::ProgramPatchDump { patch, ver: 0, data: data.to_vec() })
+    }
+
+    fn is_our_channel(&self, channel: u8) -> bool {
+        channel == self.channel || channel == Channel::all()
+    }
+
+    fn control_change(&mut self, cc: u8, value: u8) {
+        let Some((name, control)) = self.config.cc_to_control(cc) else {
+            warn!("Emulator: control for CC={} not defined!", cc);
+            return;
+        };
+        let value = control.value_from_midi(value);
+        self.controller.set(name, value, StoreOrigin::NONE);
+        self.handler.control_value_to_buffer(&self.controller, name, &mut self.edit);
+    }
+
+    fn program_change(&mut self, program: u8) {
+        // manual mode and tuner keep the current edit buffer
+        if let Some(data) = pc_program(self.config, program).and_then(|p| self.program(p)) {
+            self.edit = data.to_vec();
+        }
+    }
+
+}
+
+impl EmulatedDevice for Pod2Emulator {
+    fn config(&self) -> &'static Config {
+        self.config
+    }
+
+    fn message(&mut self, msg: MidiMessage) -> Vec<MidiMessage> {
+        let size = self.config.program_size;
+        match msg {
+            MidiMessage::UniversalDeviceInquiry { channel } if self.is_our_channel(channel) => {
+                vec![MidiMessage::UniversalDeviceInquiryResponse {
+                    channel: self.channel,
+                    family: self.config.family,
+                    member: self.config.member,
+                    ver: "0303".to_string()
+                }]
+            }
+            MidiMessage::ControlChange { channel, control, value } if self.is_our_channel(channel) => {
+                self.control_change(control, value);
+                vec![]
+            }
+            MidiMessage::ProgramChange { channel, program } if self.is_our_channel(channel) => {
+                self.program_change(program);
+                vec![]
+            }
+            MidiMessage::ProgramEditBufferDumpRequest => {
+                vec![MidiMessage::ProgramEditBufferDump { ver: 0, data: self.edit.clone() }]
+            }
+            MidiMessage::ProgramPatchDumpRequest { patch } => {
+                self.patch_dump(patch).into_iter().collect()
+            }
+            MidiMessage::AllProgramsDumpRequest => {
+                if self.config.flags.contains(DeviceFlags::ALL_PROGRAMS_DUMP) {
+                    vec![MidiMessage::AllProgramsDump { ver: 0, data: self.programs.clone() }]
+                } else {
+                    (0 .. self.config.program_num)
+                        .flat_map(|patch| self.patch_dump(patch as u8))
+                        .collect()
+                }
+            }
+            MidiMessage::ProgramEditBufferDump { data, .. } => {
+                if let Some(data) = check_size("edit buffer", data, size) {
+                    self.edit = data;
+                }
+                vec![]
+            }
+            MidiMessage::ProgramPatchDump { patch, data, .. } => {
+                if let Some(data) = check_size("program", data, size) {
+                    match self.program_mut(patch as usize) {
+                        Some(program) => program.copy_from_slice(&data),
+                        None => warn!("Emulator: program {} out of bounds", patch)
+                    }
+                }
+                vec![]
+            }
+            MidiMessage::AllProgramsDump { data, .. } => {
+                if let Some(data) = check_size("all programs", data, size * self.config.program_num) {
+                    self.programs = data;
+                }
+                vec![]
+            }
+            msg => {
+                debug!("Emulator: ignoring {:?}", msg);
+                vec![]
+            }
+        }
+    }
+}
+
+struct EmulatorPort {
+    name: String,
+    device: SharedEmulatedDevice,
+    /// Sender of the currently open emulator MIDI input
+    tx: Arc<Mutex<Option<mpsc::UnboundedSender<Vec<u8>>>>>,
+}
+
+static EMULATORS: Lazy<Mutex<Vec<EmulatorPort>>> = Lazy::new(|| Mutex::new(vec![]));
+
+fn port_name(config: &Config) -> String {
+    format!("{} (emulated)", config.name)
+}
+
+/// Make an emulated device available as an emulator port. The device
+/// keeps its state across port re-opens for the lifetime of the application.
+pub fn register_emulator(device: Box<dyn EmulatedDevice>) {
+    let name = port_name(device.config());
+    let port = EmulatorPort {
+        name,
+        device: Arc::new(Mutex::new(device)) as SharedEmulatedDevice,
+        tx: Arc::new(Mutex::new(None))
+    };
+    EMULATORS.lock().unwrap().push(port);
+}
+
+/// Names of all registered emulator ports
+pub fn emulator_ports() -> Vec<String> {
+    EMULATORS.lock().unwrap().iter().map(|p| p.name.clone()).collect()
+}
+
+pub fn is_emulator_port(name: &str) -> bool {
+    EMULATORS.lock().unwrap().iter().any(|p| p.name == name)
+}
+
+/// Open the emulator port `name` as a MIDI in/out pair. Re-opening the
+/// port closes the previously opened emulator MIDI input.
+pub fn emulator_open(name: &str) -> Result<(EmulatorMidiIn, EmulatorMidiOut)> {
+    let emulators = EMULATORS.lock().unwrap();
+    let port = emulators.iter().find(|p| p.name == name)
+        .with_context(|| format!("Emulator port {:?} not found", name))?;
+
+    Ok(open_ports(&port.name, port.device.clone(), port.tx.clone()))
+}
+
+/// Connect to an emulated `device` that is not registered as an emulator
+/// port, e.g. to drive a device handler against it
+pub fn emulator_connect(device: SharedEmulatedDevice) -> (EmulatorMidiIn, EmulatorMidiOut) {
+    let name = port_name(device.lock().unwrap().config());
+    open_ports(&name, device, Arc::new(Mutex::new(None)))
+}
+
+fn open_ports(name: &str, device: SharedEmulatedDevice,
+              tx_slot: Arc<Mutex<Option<mpsc::UnboundedSender<Vec<u8>>>>>) -> (EmulatorMidiIn, EmulatorMidiOut) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    tx_slot.lock().unwrap().replace(tx);
+
+    let midi_in = EmulatorMidiIn { name: name.to_string(), rx };
+    let midi_out = EmulatorMidiOut { name: name.to_string(), device, tx: tx_slot };
+    (midi_in, midi_out)
+}
+
+/// MIDI input receiving messages sent by an emulated device
+pub struct EmulatorMidiIn {
+    name: String,
+    rx: mpsc::UnboundedReceiver<Vec<u8>>,
+}
+
+/// MIDI output sending messages to an emulated device
+pub struct EmulatorMidiOut {
+    name: String,
+    device: SharedEmulatedDevice,
+    tx: Arc<Mutex<Option<mpsc::UnboundedSender<Vec<u8>>>>>,
+}
+
+#[async_trait]
+impl MidiIn for EmulatorMidiIn {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn recv(&mut self) -> Option<Vec<u8>> {
+        self.rx.recv().await
+    }
+
+    fn close(&mut self) {
+        self.rx.close();
+    }
+
+    fn no_reply_retry(&self) -> usize {
+        0
+    }
+}
+
+#[async_trait]
+impl MidiOut for EmulatorMidiOut {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn send(&mut self, bytes: &[u8]) -> Result<()> {
+        trace!(">> {:02x?} len={}", bytes, bytes.len());
+        let msg = MidiMessage::from_bytes(bytes.to_vec())?;
+        let replies = self.device.lock().unwrap().message(msg);
+
+        let tx = self.tx.lock().unwrap();
+        let Some(tx) = tx.as_ref() else {
+            return Ok(());
+        };
+        for reply in replies {
+            let bytes = reply.to_bytes()?;
+            trace!("<< {:02x?} len={}", bytes, bytes.len());
+            if tx.send(bytes).is_err() {
+                // emulator MIDI input closed, nobody is listening
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) {
+    }
+}
@@ -155,6 +155,9 @@ pub enum AppEvent {
     Copy(BufferCopyEvent),
     BufferData(BufferDataEvent),
     Modified(ModifiedEvent),
+    Undo,
+    Redo,
+    SelectSnapshot(usize),
 
     DeviceDetected(DeviceDetectedEvent),
     NewConfig(NewConfigEvent),
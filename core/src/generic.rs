@@ -7,7 +7,6 @@ use crate::midi::{Channel, MidiMessage};
 use crate::model::{AbstractControl, DeviceFlags};
 use crate::{config, program};
 use crate::cc_values::*;
-use crate::dispatch::dispatch_buffer_set;
 
 fn update_edit_buffer(ctx: &Ctx, event: &ControlChangeEvent) {
     let controller = &ctx.controller.lock().unwrap();
@@ -356,7 +355,7 @@ pub fn store_handler(ctx: &Ctx, event: &BufferStoreEvent) -> bool {
 }
 
 pub fn copy_handler(ctx: &Ctx, event: &BufferCopyEvent) {
-    dispatch_buffer_set(event.from.clone(), event.to.clone());
+    ctx.reroute.set(event.from.clone(), event.to.clone());
     let e = BufferStoreEvent {
         buffer: event.from.clone(),
         origin: UI
@@ -563,8 +562,11 @@ pub fn new_device_handler(ctx: &Ctx) {
     let msg = MidiMessage::UniversalDeviceInquiry { channel: ctx.midi_channel() };
     ctx.app_event_tx.send_or_warn(AppEvent::MidiMsgOut(msg));
 
-    // Request all buffers load
-    let e = BufferLoadEvent { buffer: Buffer::All, origin: UI };
+    // Request all buffers load, unless the programs were restored from
+    // a local mirror: a full dump would overwrite the programs changed
+    // offline, so only the edit buffer is loaded
+    let buffer = if ctx.dump.lock().unwrap().restored() { Buffer::EditBuffer } else { Buffer::All };
+    let e = BufferLoadEvent { buffer, origin: UI };
     ctx.app_event_tx.send_or_warn(AppEvent::Load(e));
 }
 
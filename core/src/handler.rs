@@ -4,6 +4,7 @@ use crate::controller::Controller;
 use crate::event::*;
 use crate::generic;
 use crate::midi::MidiMessage;
+use crate::model::Config;
 
 /// The `Handler` trait is to be implemented by all device modules.
 pub trait Handler {
@@ -91,6 +92,13 @@ pub trait Handler {
 
     fn control_value_from_buffer(&self, controller: &mut Controller, name: &str, buffer: &[u8]) {}
     fn control_value_to_buffer(&self, controller: &Controller, name: &str, buffer: &mut [u8]) {}
+
+    /// Labels of the values of select control `name`. Modules where the
+    /// labels depend on the values of other controls, kept in `controller`,
+    /// override this.
+    fn select_labels(&self, config: &Config, name: &str, controller: &Controller) -> Option<Vec<String>> {
+        config.select_labels(name)
+    }
 }
 
 pub type BoxedHandler = Box<dyn Handler + 'static + Send>;
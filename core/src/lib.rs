@@ -1,6 +1,6 @@
 pub mod midi;
 mod util;
-pub use util::{def, is_valid_char, program_id_string};
+pub use util::{def, is_valid_char, program_id_string, user_config_path};
 
 pub mod store;
 pub mod model;
@@ -20,4 +20,21 @@ pub mod generic;
 pub mod context;
 pub mod handler;
 pub mod dispatch;
-pub mod cc_values;
\ No newline at end of file
+pub mod cc_values;
+pub mod syx;
+pub mod bank;
+pub mod text_patch;
+pub mod diff;
+pub mod mirror;
+pub mod undo;
+pub mod snapshots;
+pub mod session;
+pub mod emulator;
+pub mod remote;
+pub mod osc;
+pub mod api;
+pub mod midi_learn;
+pub mod virtual_port;
+pub mod definition;
+#[cfg(test)]
+mod test_util;
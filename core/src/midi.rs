@@ -9,6 +9,22 @@ impl Channel {
     pub const fn all() -> u8 { 0x7f }
 }
 
+/// Convert a MIDI channel as given on the command line or in the
+/// settings, where 0 means "omni" and 1 - 16 a specific channel, into
+/// a channel number
+pub fn channel_from_arg(channel: u8) -> Result<u8> {
+    match channel {
+        0 => Ok(Channel::all()),
+        x if (1u8 ..= 16).contains(&x) => Ok(x - 1),
+        x => bail!("Midi channel {} out of bounds (0, 1..16)", x)
+    }
+}
+
+/// Convert a channel number back into its command line form
+pub fn channel_to_arg(channel: u8) -> u8 {
+    if channel == Channel::all() { 0 } else { channel + 1 }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum MidiMessage {
     UniversalDeviceInquiry { channel: u8 },
@@ -45,21 +61,22 @@ pub enum MidiMessage {
 
 pub struct PodXtPatch;
 impl PodXtPatch {
-    pub fn to_midi(value: u16) -> u16 {
+    pub fn to_midi(value: u16) -> Result<u16> {
         let bank = (value >> 8) & 0xff;
         let patch = value & 0xff;
-        match (bank, patch) {
+        let value = match (bank, patch) {
             (0, 0 ..= 63) => patch,
             (0, 64 ..= 127) => patch + 128,
             (1, 0 ..= 63) => patch + 64,
             (1, 64 ..= 127) => patch + 192,
             (2, 0 ..= 63) => patch + 128,
             (2, 64 ..= 127) => patch + 256,
-            _ => panic!("unsupported patch_to_midi value: {}", value)
-        }
+            _ => bail!("Unsupported patch_to_midi value: {:#06x}", value)
+        };
+        Ok(value)
     }
 
-    pub fn from_midi(value: u16) -> u16 {
+    pub fn from_midi(value: u16) -> Result<u16> {
         let (bank, patch) = match value {
             0 ..= 63 => (0, value),
             192 ..= 255 => (0, value - 128),
@@ -67,39 +84,41 @@ impl PodXtPatch {
             256 ..= 319 => (1, value - 192),
             128 ..= 191 => (2, value - 128),
             320 ..= 383 => (2, value - 256),
-            _ => panic!("unsupported patch_from_midi value: {}", value)
+            _ => bail!("Unsupported patch_from_midi value: {}", value)
         };
-        (bank << 8) | patch
+        Ok((bank << 8) | patch)
     }
 }
 
 pub struct PodXtSaved;
 impl PodXtSaved {
-    pub fn to_midi(value: u16) -> u16 {
+    pub fn to_midi(value: u16) -> Result<u16> {
         let bank = ((value >> 8) & 0xff) as u8;
         let patch = (value & 0xff) as u8;
 
-        if bank > 2 {
-            panic!("unsupported saved_to_midi value: {}", value);
+        if bank > 2 || patch > 0x7f {
+            bail!("Unsupported saved_to_midi value: {:#06x}", value);
         }
 
-        u16_from_2_u7(bank + 1, patch)
+        Ok(u16_from_2_u7(bank + 1, patch))
     }
 
-    pub fn from_midi(value: u16) -> u16 {
-        let (bank, patch) = u16_to_2_u7(value);
-
-        if bank == 0 || bank > 3 {
-            panic!("unsupported saved_from_midi value: {}", value)
+    pub fn from_midi(value: u16) -> Result<u16> {
+        // banks 1..=3
+        if !(0x80 .. 0x200).contains(&value) {
+            bail!("Unsupported saved_from_midi value: {}", value)
         }
+        let (bank, patch) = u16_to_2_u7(value);
 
-        ((bank as u16 - 1) << 8) | patch as u16
+        Ok(((bank as u16 - 1) << 8) | patch as u16)
     }
 }
 
 impl MidiMessage {
-    pub fn to_bytes(&self) -> Vec<u8> {
-        match self {
+    /// Encode the message as raw MIDI bytes. Fails if a field value
+    /// cannot be represented in the message.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let bytes = match self {
             MidiMessage::UniversalDeviceInquiry { channel } =>
                 [0xf0, 0x7e, *channel, 0x06, 0x01, 0xf7].to_vec(),
             MidiMessage::UniversalDeviceInquiryResponse { channel, family, member, ver } => {
@@ -150,12 +169,12 @@ impl MidiMessage {
                 msg
             }
             MidiMessage::XtPatchDumpRequest { patch } => {
-                let patch = PodXtPatch::to_midi(*patch);
+                let patch = PodXtPatch::to_midi(*patch)?;
                 let (p1, p2) = u16_to_2_u7(patch);
                 [0xf0, 0x00, 0x01, 0x0c, 0x03, 0x73, p1, p2, 0x00, 0x00, 0xf7].to_vec()
             }
             MidiMessage::XtPatchDump { patch, id, data } => {
-                let patch = PodXtPatch::to_midi(*patch);
+                let patch = PodXtPatch::to_midi(*patch)?;
                 let (p1, p2) = u16_to_2_u7(patch);
                 let mut msg = vec![0xf0, 0x00, 0x01, 0x0c, 0x03, 0x71, *id, p1, p2];
                 msg.extend(data);
@@ -165,7 +184,7 @@ impl MidiMessage {
             MidiMessage::XtPatchDumpEnd =>
                 [0xf0, 0x00, 0x01, 0x0c, 0x03, 0x72, 0xf7].to_vec(),
             MidiMessage::XtSaved { patch} => {
-                let patch = PodXtSaved::to_midi(*patch);
+                let patch = PodXtSaved::to_midi(*patch)?;
                 let (p1, p2) = u16_to_2_u7(patch);
                 [0xf0, 0x00, 0x01, 0x0c, 0x03, 0x24, p1, p2, 0xf7].to_vec()
             },
@@ -207,7 +226,8 @@ impl MidiMessage {
                 [0xb0 | *channel & 0x0f, *control, *value].to_vec(),
             MidiMessage::ProgramChange { channel, program } =>
                 [0xc0 | *channel & 0x0f, *program].to_vec(),
-        }
+        };
+        Ok(bytes)
     }
 
     // This is technically not needed since midir 0.9.0 fixed sysex size
@@ -301,13 +321,13 @@ impl MidiMessage {
                                 }),
                             [0x03, 0x73, p1, p2, 0x00, 0x00] => {
                                 let patch = u16_from_2_u7(*p1, *p2);
-                                let patch = PodXtPatch::from_midi(patch);
+                                let patch = PodXtPatch::from_midi(patch)?;
                                 Ok(MidiMessage::XtPatchDumpRequest { patch })
                             }
                             [0x03, 0x72] => Ok(MidiMessage::XtPatchDumpEnd),
                             [0x03, 0x71, i, p1, p2, data @ ..] => {
                                 let patch = u16_from_2_u7(*p1, *p2);
-                                let patch = PodXtPatch::from_midi(patch);
+                                let patch = PodXtPatch::from_midi(patch)?;
                                 Ok(MidiMessage::XtPatchDump {
                                     id: *i,
                                     patch,
@@ -316,7 +336,7 @@ impl MidiMessage {
                             }
                             [0x03, 0x24, p1, p2] => {
                                 let patch = u16_from_2_u7(*p1, *p2);
-                                let patch = PodXtSaved::from_midi(patch);
+                                let patch = PodXtSaved::from_midi(patch)?;
                                 Ok(MidiMessage::XtSaved { patch })
                             }
                             [0x03, 0x50] => Ok(MidiMessage::XtStoreStatus { success: true }),
@@ -364,7 +384,19 @@ impl MidiMessage {
 
 #[cfg(test)]
 mod tests {
-    use crate::midi::MidiMessage;
+    use proptest::prelude::*;
+    use crate::midi::{channel_from_arg, channel_to_arg, Channel, MidiMessage, PodXtPatch, PodXtSaved};
+
+    #[test]
+    fn channel_args() {
+        assert_eq!(channel_from_arg(0).unwrap(), Channel::all());
+        assert_eq!(channel_from_arg(1).unwrap(), 0);
+        assert_eq!(channel_from_arg(16).unwrap(), 15);
+        assert!(channel_from_arg(17).is_err());
+        for arg in 0 ..= 16 {
+            assert_eq!(channel_to_arg(channel_from_arg(arg).unwrap()), arg);
+        }
+    }
 
     #[test]
     fn message_parsing_should_not_crash() {
@@ -382,7 +414,7 @@ mod tests {
         ];
 
         for msg in messages.iter() {
-            let bytes = msg.to_bytes();
+            let bytes = msg.to_bytes().unwrap();
             println!("{:?}", msg);
             println!("{:x?} len={}", bytes, bytes.len());
 
@@ -409,5 +441,126 @@ mod tests {
             }
         }
     }
-}
 
+    #[test]
+    fn xt_patch_conversions_should_not_crash() {
+        for value in 0 ..= u16::MAX {
+            if let Ok(midi) = PodXtPatch::to_midi(value) {
+                assert_eq!(PodXtPatch::from_midi(midi).ok(), Some(value));
+            }
+            if let Ok(patch) = PodXtPatch::from_midi(value) {
+                assert_eq!(PodXtPatch::to_midi(patch).ok(), Some(value));
+            }
+            if let Ok(midi) = PodXtSaved::to_midi(value) {
+                assert_eq!(PodXtSaved::from_midi(midi).ok(), Some(value));
+            }
+            if let Ok(patch) = PodXtSaved::from_midi(value) {
+                assert_eq!(PodXtSaved::to_midi(patch).ok(), Some(value));
+            }
+        }
+        assert!(PodXtPatch::to_midi(0x0300).is_err());
+        assert!(PodXtPatch::from_midi(384).is_err());
+        assert!(PodXtSaved::to_midi(0x0300).is_err());
+        assert!(PodXtSaved::from_midi(0).is_err());
+    }
+
+    fn u7() -> impl Strategy<Value = u8> {
+        0 .. 0x80u8
+    }
+
+    /// A 16-bit value that is sent as two 7-bit bytes
+    fn u16_u7() -> impl Strategy<Value = u16> {
+        (u7(), u7()).prop_map(|(lo, hi)| (hi as u16) << 8 | lo as u16)
+    }
+
+    fn xt_patch() -> impl Strategy<Value = u16> {
+        (0 .. 3u16, 0 .. 0x80u16).prop_map(|(bank, patch)| bank << 8 | patch)
+    }
+
+    fn nibble_data() -> impl Strategy<Value = Vec<u8>> {
+        prop::collection::vec(any::<u8>(), 1 .. 256)
+    }
+
+    fn u7_data() -> impl Strategy<Value = Vec<u8>> {
+        prop::collection::vec(u7(), 0 .. 256)
+    }
+
+    fn message() -> impl Strategy<Value = MidiMessage> {
+        prop_oneof![
+            u7().prop_map(|channel| MidiMessage::UniversalDeviceInquiry { channel }),
+            (u7(), u16_u7(), u16_u7(), "[ -~]{4}").prop_map(|(channel, family, member, ver)|
+                MidiMessage::UniversalDeviceInquiryResponse { channel, family, member, ver }),
+            u7().prop_map(|patch| MidiMessage::ProgramPatchDumpRequest { patch }),
+            (u7(), u7(), nibble_data()).prop_map(|(patch, ver, data)|
+                MidiMessage::ProgramPatchDump { patch, ver, data }),
+            Just(MidiMessage::ProgramEditBufferDumpRequest),
+            (u7(), nibble_data()).prop_map(|(ver, data)|
+                MidiMessage::ProgramEditBufferDump { ver, data }),
+            Just(MidiMessage::AllProgramsDumpRequest),
+            (u7(), nibble_data()).prop_map(|(ver, data)|
+                MidiMessage::AllProgramsDump { ver, data }),
+            Just(MidiMessage::XtInstalledPacksRequest),
+            u7().prop_map(|packs| MidiMessage::XtInstalledPacks { packs }),
+            Just(MidiMessage::XtEditBufferDumpRequest),
+            (u7(), u7_data()).prop_map(|(id, data)| MidiMessage::XtBufferDump { id, data }),
+            xt_patch().prop_map(|patch| MidiMessage::XtPatchDumpRequest { patch }),
+            (xt_patch(), u7(), u7_data()).prop_map(|(patch, id, data)|
+                MidiMessage::XtPatchDump { patch, id, data }),
+            Just(MidiMessage::XtPatchDumpEnd),
+            xt_patch().prop_map(|patch| MidiMessage::XtSaved { patch }),
+            any::<bool>().prop_map(|success| MidiMessage::XtStoreStatus { success }),
+            Just(MidiMessage::XtTunerNoteRequest),
+            any::<u16>().prop_map(|note| MidiMessage::XtTunerNote { note }),
+            Just(MidiMessage::XtTunerOffsetRequest),
+            any::<u16>().prop_map(|offset| MidiMessage::XtTunerOffset { offset }),
+            Just(MidiMessage::XtProgramNumberRequest),
+            any::<u16>().prop_map(|program| MidiMessage::XtProgramNumber { program }),
+            Just(MidiMessage::XtProgramEditStateRequest),
+            any::<bool>().prop_map(|edited| MidiMessage::XtProgramEditState { edited }),
+            (0 .. 16u8, u7(), u7()).prop_map(|(channel, control, value)|
+                MidiMessage::ControlChange { channel, control, value }),
+            (0 .. 16u8, u7()).prop_map(|(channel, program)|
+                MidiMessage::ProgramChange { channel, program }),
+        ]
+    }
+
+    /// Line6 sysex messages with an arbitrary 7-bit payload
+    fn line6_sysex() -> impl Strategy<Value = Vec<u8>> {
+        prop::collection::vec(u7(), 0 .. 16).prop_map(|payload| {
+            let mut bytes = vec![0xf0, 0x00, 0x01, 0x0c];
+            bytes.extend(payload);
+            bytes.push(0xf7);
+            bytes
+        })
+    }
+
+    /// Messages that parse must encode back into a message that
+    /// parses the same way
+    fn check_reencode(bytes: Vec<u8>) -> Result<(), TestCaseError> {
+        if let Ok(msg) = MidiMessage::from_bytes(bytes) {
+            let bytes = msg.to_bytes();
+            prop_assert!(bytes.is_ok(), "{:?} cannot be encoded", msg);
+            let parsed = MidiMessage::from_bytes(bytes.unwrap());
+            prop_assert_eq!(parsed.ok(), Some(msg));
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn message_round_trip(msg in message()) {
+            let bytes = msg.to_bytes().unwrap();
+            prop_assert_eq!(MidiMessage::from_bytes(bytes).ok(), Some(msg));
+        }
+
+        #[test]
+        fn arbitrary_bytes_should_not_crash(bytes in prop::collection::vec(any::<u8>(), 0 .. 64)) {
+            check_reencode(bytes)?;
+        }
+
+        #[test]
+        fn arbitrary_sysex_should_not_crash(bytes in line6_sysex()) {
+            check_reencode(bytes)?;
+        }
+    }
+}
@@ -196,6 +196,13 @@ pub trait  MidiOpen {
         let port_n_re = Regex::new(r"\d+").unwrap();
         let port_id_re = Regex::new(r"\d+:\d+").unwrap();
 
+        // a full port name, such as one listed by `MidiPorts::all_ports`
+        let port = class.ports().into_iter()
+            .find(|port| class.port_name(port).ok().as_deref() == Some(port_addr));
+        if let Some(port) = port {
+            return Self::_new_for_port(class, port);
+        }
+
         let mut found = None;
         if port_id_re.is_match(&port_addr) {
             for port in class.ports().into_iter() {
@@ -283,6 +290,7 @@ impl MidiPorts for MidiInPort {
         Self::all_ports()
             .map(|v| v.into_iter()
                 .filter(|name| !name.starts_with("pod midi out:"))
+                .filter(|name| !name.starts_with("pod virtual out:"))
                 .collect()
             )
     }
@@ -298,6 +306,7 @@ impl MidiPorts for MidiOutPort {
         Self::all_ports()
             .map(|v| v.into_iter()
                 .filter(|name| !name.starts_with("pod midi in:"))
+                .filter(|name| !name.starts_with("pod virtual in:"))
                 .collect()
             )
     }
@@ -340,7 +349,7 @@ async fn detect(in_ports: &mut [BoxedMidiIn], out_ports: &mut [BoxedMidiOut]) ->
 async fn detect_with_channel(in_ports: &mut [BoxedMidiIn], out_ports: &mut [BoxedMidiOut], channel: u8) -> Result<(Vec<(usize, &'static Config)>, Option<String>)> {
 
     let in_names = in_ports.iter().map(|p| p.name()).collect::<Vec<_>>();
-    let udi = MidiMessage::UniversalDeviceInquiry { channel }.to_bytes();
+    let udi = MidiMessage::UniversalDeviceInquiry { channel }.to_bytes()?;
 
     let mut streams = IndexedStreamsUnordered::new();
     for p in in_ports.iter_mut() {
@@ -398,7 +407,7 @@ async fn detect_channel(in_port: &mut BoxedMidiIn, out_port: &mut BoxedMidiOut)
         MidiMessage::UniversalDeviceInquiry { channel: Channel::num(n) }.to_bytes()
     }).chain(std::iter::once(
         MidiMessage::UniversalDeviceInquiry { channel: Channel::all() }.to_bytes()
-    ));
+    )).collect::<Result<Vec<_>>>()?;
 
     let input = stream! {
       while let Some(data) = in_port.recv().await {
@@ -497,6 +506,42 @@ pub async fn autodetect(channel: Option<u8>) -> Result<AutodetectResult> {
     autodetect_with_ports(in_ports, out_ports, channel).await
 }
 
+/// Detect all devices connected to the MIDI ports of this system. Every
+/// input port is probed on its own, skipping the output ports of the
+/// devices already found, and the ports of all found devices are left
+/// open.
+pub async fn autodetect_all(channel: Option<u8>) -> Result<Vec<AutodetectResult>> {
+    let mut devices: Vec<AutodetectResult> = vec![];
+    for (i, in_name) in MidiInPort::ports()?.iter().enumerate() {
+        let in_port = match MidiInPort::new(Some(i)) {
+            Ok(port) => box_midi_in(port),
+            Err(e) => {
+                warn!("Failed to open MIDI in port {:?}: {}", in_name, e);
+                continue;
+            }
+        };
+        let out_ports = MidiOutPort::ports()?.iter().enumerate()
+            .filter(|(_, name)| !devices.iter().any(|d| d.out_port.name() == **name))
+            .flat_map(|(i, name)| {
+                MidiOutPort::new(Some(i))
+                    .map_err(|e| warn!("Failed to open MIDI out port {:?}: {}", name, e))
+                    .ok()
+            })
+            .map(box_midi_out)
+            .collect::<Vec<_>>();
+        if out_ports.is_empty() {
+            break;
+        }
+
+        match autodetect_with_ports(vec![in_port], out_ports, channel).await {
+            Ok(res) => devices.push(res),
+            Err(e) => debug!("No device found on MIDI in port {:?}: {}", in_name, e)
+        }
+    }
+
+    Ok(devices)
+}
+
 pub async fn autodetect_with_ports(in_ports: Vec<BoxedMidiIn>, out_ports: Vec<BoxedMidiOut>,
                                    channel: Option<u8>) -> Result<AutodetectResult> {
     let config: Option<&Config>;
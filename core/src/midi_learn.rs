@@ -0,0 +1,318 @@
+//! MIDI learn: translate CC messages from an external MIDI controller,
+//! connected to its own MIDI input, into device control changes.
+//!
+//! In learn mode, the next CC message received from the controller is
+//! mapped to the control being learned. Mappings are kept per device
+//! model and persisted in the user configuration directory.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use anyhow::*;
+use log::*;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use crate::context::Ctx;
+use crate::controller::*;
+use crate::event::*;
+use crate::midi::MidiMessage;
+use crate::midi_io::BoxedMidiIn;
+use crate::model::*;
+
+/// Name of the MIDI learn mappings file in the user configuration directory
+pub const MAPPINGS_FILE: &str = "midi-learn.json";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MidiMapping {
+    pub channel: u8,
+    pub cc: u8,
+    pub control: String,
+}
+
+/// MIDI learn mappings of all device models, keyed by config name
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MidiMappings(BTreeMap<String, Vec<MidiMapping>>);
+
+impl MidiMappings {
+    /// Load mappings from `path`. A missing file has no mappings.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let str = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {:?}", path))?;
+        serde_json::from_str(&str)
+            .with_context(|| format!("Failed to parse {:?}", path))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {:?}", dir))?;
+        }
+        let str = serde_json::to_string_pretty(self)?;
+        fs::write(path, str)
+            .with_context(|| format!("Failed to write {:?}", path))
+    }
+
+    pub fn find(&self, model: &str, channel: u8, cc: u8) -> Option<&MidiMapping> {
+        self.0.get(model)?.iter()
+            .find(|m| m.channel == channel && m.cc == cc)
+    }
+
+    pub fn find_control(&self, model: &str, control: &str) -> Option<&MidiMapping> {
+        self.0.get(model)?.iter()
+            .find(|m| m.control == control)
+    }
+
+    /// Add a mapping, replacing any mappings of the same CC or control
+    pub fn set(&mut self, model: &str, mapping: MidiMapping) {
+        let mappings = self.0.entry(model.to_string()).or_default();
+        mappings.retain(|m| {
+            !(m.channel == mapping.channel && m.cc == mapping.cc) && m.control != mapping.control
+        });
+        mappings.push(mapping);
+    }
+
+    /// Remove the mapping of a control, returning true if there was one
+    pub fn remove(&mut self, model: &str, control: &str) -> bool {
+        let Some(mappings) = self.0.get_mut(model) else {
+            return false;
+        };
+        let len = mappings.len();
+        mappings.retain(|m| m.control != control);
+        mappings.len() != len
+    }
+}
+
+/// Scale a 7-bit CC value into the value range of a control
+pub fn scale_value(control: &Control, value: u8) -> u16 {
+    match control {
+        Control::SwitchControl(_) | Control::MidiSwitchControl(_) => {
+            (value > 63) as u16
+        }
+        Control::RangeControl(RangeControl { config, .. }) |
+        Control::AddrRangeControl(AddrRangeControl { config, .. }) |
+        Control::VirtualRangeControl(VirtualRangeControl { config, .. }) => {
+            let (from, to) = config.bounds();
+            (from + (to - from) * value as f64 / 127.0).round() as u16
+        }
+        _ => {
+            control.value_from_midi(value)
+        }
+    }
+}
+
+/// Controller of the current device that external controller messages
+/// are translated to
+struct LearnTarget {
+    config: &'static Config,
+    controller: Arc<Mutex<Controller>>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    Learned(MidiMapping),
+    Set(String, u16),
+}
+
+#[derive(Default)]
+struct MidiLearnState {
+    target: Option<LearnTarget>,
+    mappings: MidiMappings,
+    path: Option<PathBuf>,
+    learn: Option<String>,
+}
+
+impl MidiLearnState {
+    /// Handle a CC message from the external controller
+    fn handle(&mut self, channel: u8, cc: u8, value: u8) -> Option<Action> {
+        let target = self.target.as_ref()?;
+        let model = target.config.name.as_str();
+
+        if let Some(control) = self.learn.take() {
+            let mapping = MidiMapping { channel, cc, control };
+            self.mappings.set(model, mapping.clone());
+            self.save();
+            return Some(Action::Learned(mapping));
+        }
+
+        let mapping = self.mappings.find(model, channel, cc)?;
+        let Some(control) = target.config.controls.get(&mapping.control) else {
+            warn!("MIDI learn: control {:?} not found", mapping.control);
+            return None;
+        };
+        Some(Action::Set(mapping.control.clone(), scale_value(control, value)))
+    }
+
+    fn save(&self) {
+        let Some(path) = self.path.as_ref() else {
+            return;
+        };
+        if let Err(e) = self.mappings.save(path) {
+            error!("Failed to save MIDI learn mappings: {}", e);
+        }
+    }
+}
+
+/// MIDI learn translator between an external MIDI controller and the
+/// controller of the current device
+#[derive(Clone)]
+pub struct MidiLearn {
+    state: Arc<Mutex<MidiLearnState>>,
+}
+
+impl MidiLearn {
+    /// Create a MIDI learn translator with mappings persisted in `path`,
+    /// or not persisted at all if `path` is `None`
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let mappings = path.as_ref()
+            .map(|path| {
+                MidiMappings::load(path).unwrap_or_else(|e| {
+                    error!("Failed to load MIDI learn mappings: {}", e);
+                    MidiMappings::default()
+                })
+            })
+            .unwrap_or_default();
+        let state = MidiLearnState { mappings, path, ..Default::default() };
+        Self { state: Arc::new(Mutex::new(state)) }
+    }
+
+    /// Start translating messages from an external controller's MIDI
+    /// input. Must be called from within a tokio runtime.
+    pub fn start(&self, midi_in: BoxedMidiIn, app_event_tx: &broadcast::Sender<AppEvent>) {
+        info!("MIDI learn: listening on {:?}", midi_in.name());
+        tokio::spawn(recv_task(midi_in, self.state.clone(), app_event_tx.clone()));
+    }
+
+    /// Set the device context to control, `None` if there is no device
+    pub fn set_ctx(&self, ctx: Option<&Ctx>) {
+        let mut state = self.state.lock().unwrap();
+        state.target = ctx.map(|ctx| LearnTarget {
+            config: ctx.config,
+            controller: ctx.controller.clone()
+        });
+        state.learn = None;
+    }
+
+    /// Map the next CC message from the external controller to `control`
+    pub fn learn(&self, control: &str) {
+        self.state.lock().unwrap().learn = Some(control.to_string());
+    }
+
+    pub fn cancel(&self) {
+        self.state.lock().unwrap().learn = None;
+    }
+
+    /// Mapping of `control` of the current device
+    pub fn mapping(&self, control: &str) -> Option<MidiMapping> {
+        let state = self.state.lock().unwrap();
+        let model = state.target.as_ref()?.config.name.as_str();
+        state.mappings.find_control(model, control).cloned()
+    }
+
+    /// Remove the mapping of `control` of the current device
+    pub fn forget(&self, control: &str) {
+        let mut state = self.state.lock().unwrap();
+        let Some(target) = state.target.as_ref() else {
+            return;
+        };
+        let model = target.config.name.clone();
+        if state.mappings.remove(&model, control) {
+            state.save();
+        }
+    }
+}
+
+async fn recv_task(mut midi_in: BoxedMidiIn, state: Arc<Mutex<MidiLearnState>>,
+                   app_event_tx: broadcast::Sender<AppEvent>) {
+    while let Some(bytes) = midi_in.recv().await {
+        let (channel, cc, value) = match MidiMessage::from_bytes(bytes) {
+            Result::Ok(MidiMessage::ControlChange { channel, control, value }) =>
+                (channel, control, value),
+            Result::Ok(_) => continue,
+            Err(e) => {
+                warn!("MIDI learn: {}", e);
+                continue;
+            }
+        };
+        let mut state = state.lock().unwrap();
+        match state.handle(channel, cc, value) {
+            Some(Action::Learned(mapping)) => {
+                let msg = format!("MIDI CC {} on channel {} mapped to {:?}",
+                                  mapping.cc, mapping.channel + 1, mapping.control);
+                info!("MIDI learn: {}", msg);
+                app_event_tx.send(AppEvent::Notification(NotificationEvent::msg(msg))).ok();
+            }
+            Some(Action::Set(name, value)) => {
+                let controller = state.target.as_ref().unwrap().controller.clone();
+                drop(state);
+                controller.set(&name, value, StoreOrigin::UI);
+            }
+            None => {}
+        }
+    }
+    info!("MIDI learn: {:?} closed", midi_in.name());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn scale() {
+        let range = |config| Control::from(RangeControl { config, ..Default::default() });
+
+        let control = range(RangeConfig::Normal);
+        assert_eq!(scale_value(&control, 0), 0);
+        assert_eq!(scale_value(&control, 64), 64);
+        assert_eq!(scale_value(&control, 127), 127);
+
+        let control = range(RangeConfig::Short { from: 0, to: 2, edge: true });
+        assert_eq!(scale_value(&control, 0), 0);
+        assert_eq!(scale_value(&control, 64), 1);
+        assert_eq!(scale_value(&control, 127), 2);
+
+        let control = range(RangeConfig::Long { from: 100, to: 3000 });
+        assert_eq!(scale_value(&control, 0), 100);
+        assert_eq!(scale_value(&control, 127), 3000);
+
+        let control = Control::from(SwitchControl { inverted: true, ..Default::default() });
+        assert_eq!(scale_value(&control, 10), 0);
+        assert_eq!(scale_value(&control, 100), 1);
+    }
+
+    #[test]
+    fn learn_and_translate() {
+        let mut config = test_util::config("Test");
+        config.controls.insert("mode".into(), RangeControl {
+            cc: 14, config: RangeConfig::Steps { steps: vec![0, 32, 64, 96] }, ..Default::default()
+        }.into());
+        let config = test_util::leak(config);
+        let mut state = MidiLearnState {
+            target: Some(LearnTarget {
+                config,
+                controller: Arc::new(Mutex::new(Controller::new(config.controls.clone())))
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(state.handle(0, 7, 100), None);
+
+        state.learn = Some("drive".into());
+        let mapping = MidiMapping { channel: 0, cc: 7, control: "drive".into() };
+        assert_eq!(state.handle(0, 7, 100), Some(Action::Learned(mapping)));
+        assert_eq!(state.handle(0, 7, 100), Some(Action::Set("drive".into(), 100)));
+        assert_eq!(state.handle(1, 7, 100), None);
+
+        // re-learning a CC replaces its previous mapping
+        state.learn = Some("mode".into());
+        state.handle(0, 7, 0);
+        assert_eq!(state.handle(0, 7, 127), Some(Action::Set("mode".into(), 3)));
+        assert!(state.mappings.find_control("Test", "drive").is_none());
+
+        assert!(state.mappings.remove("Test", "mode"));
+        assert_eq!(state.handle(0, 7, 127), None);
+    }
+}
@@ -0,0 +1,157 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use anyhow::*;
+use crate::bank::Bank;
+use crate::dump::ProgramsDump;
+use crate::event::Origin;
+use crate::model::Config;
+use crate::program;
+
+pub const MIRROR_EXT: &str = "podbank";
+
+/// A local copy of all programs of a device, kept between sessions so
+/// that programs can be browsed and edited while the device is not
+/// connected.
+///
+/// A mirror file is a bank file followed by a `modified` entry listing
+/// the programs that were changed locally and not yet stored to the
+/// device.
+#[derive(Clone, Debug)]
+pub struct Mirror {
+    pub bank: Bank,
+    pub modified: Vec<usize>,
+}
+
+impl Mirror {
+    pub fn from_dump(config: &Config, dump: &ProgramsDump, version: &str) -> Self {
+        let modified = (0 .. dump.program_num())
+            .filter(|i| dump.modified(*i))
+            .collect();
+
+        Self {
+            bank: Bank::from_dump(config, dump, version),
+            modified
+        }
+    }
+
+    /// Restore this mirror into the programs dump, including the
+    /// program names and modified flags, and mark the dump as restored.
+    pub fn restore(&self, config: &Config, dump: &mut ProgramsDump) -> Result<()> {
+        let data = self.bank.data_for(config)?;
+        program::load_all_dump(dump, data, Origin::MIDI);
+        for i in 0 .. dump.program_num() {
+            dump.set_modified(i, self.modified.contains(&i));
+        }
+        dump.set_restored(true);
+
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let str = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {:?}", path))?;
+        Self::from_str(&str)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {:?}", dir))?;
+        }
+        // write to a temporary file first, so that a failed write
+        // does not destroy the previous copy
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, self.to_string())
+            .with_context(|| format!("Failed to write {:?}", tmp))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("Failed to write {:?}", path))?;
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Mirror {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.bank)?;
+        writeln!(f)?;
+        let modified = self.modified.iter()
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>();
+        writeln!(f, "modified: {}", modified.join(","))
+    }
+}
+
+impl FromStr for Mirror {
+    type Err = Error;
+
+    fn from_str(str: &str) -> Result<Self> {
+        let bank = Bank::from_str(str)?;
+        let modified = str.lines()
+            .filter_map(|line| line.strip_prefix("modified:"))
+            .next_back()
+            .unwrap_or_default()
+            .split(',')
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .map(|v| {
+                v.parse::<usize>()
+                    .with_context(|| format!("Failed to parse modified program {:?}", v))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { bank, modified })
+    }
+}
+
+fn sanitize(str: &str) -> String {
+    str.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == ' ' || c == '-' { c } else { '_' })
+        .collect()
+}
+
+/// Mirror file for a device with the given `config` in the `dir` directory.
+/// Devices that report a `serial` number get a mirror file of their own.
+pub fn mirror_path(dir: &Path, config: &Config, serial: Option<&str>) -> PathBuf {
+    let name = match serial {
+        Some(serial) => format!("{}@{}", sanitize(&config.name), sanitize(serial)),
+        None => sanitize(&config.name)
+    };
+    dir.join(name).with_extension(MIRROR_EXT)
+}
+
+/// Find the most recently saved mirror file for a device with the given
+/// `config` regardless of its serial number.
+pub fn find_mirror(dir: &Path, config: &Config) -> Option<PathBuf> {
+    let name = sanitize(&config.name);
+    mirror_files(dir).into_iter()
+        .filter(|(path, _)| {
+            let stem = path.file_stem().map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            stem == name || stem.starts_with(&format!("{}@", name))
+        })
+        .max_by_key(|(_, time)| *time)
+        .map(|(path, _)| path)
+}
+
+/// Find the device name of the most recently saved mirror file.
+pub fn last_mirror_device(dir: &Path) -> Option<String> {
+    mirror_files(dir).into_iter()
+        .max_by_key(|(_, time)| *time)
+        .and_then(|(path, _)| Mirror::load(&path).ok())
+        .map(|mirror| mirror.bank.device)
+}
+
+fn mirror_files(dir: &Path) -> Vec<(PathBuf, std::time::SystemTime)> {
+    let Result::Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    entries.flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().map(|ext| ext == MIRROR_EXT).unwrap_or(false))
+        .flat_map(|path| {
+            let time = path.metadata().and_then(|m| m.modified()).ok()?;
+            Some((path, time))
+        })
+        .collect()
+}
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::fmt;
 use bitflags::bitflags;
 use log::warn;
+use serde::Deserialize;
 
 bitflags! {
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -56,6 +57,8 @@ pub struct Config {
     pub amp_models: Vec<Amp>,
     pub cab_models: Vec<String>,
     pub effects: Vec<Effect>,
+    /// Value labels of select controls other than amp, cab and effect selects
+    pub select_labels: HashMap<String, Vec<String>>,
     pub controls: HashMap<String, Control>,
     pub init_controls: Vec<String>,
 
@@ -69,7 +72,8 @@ pub struct Config {
 }
 
 
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, Debug, Deserialize)]
+#[serde(default)]
 pub struct Amp {
     pub name: String,
     pub reverb: u16,
@@ -78,21 +82,24 @@ pub struct Amp {
     pub drive2: bool,
 }
 
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, Debug, Deserialize)]
+#[serde(default)]
 pub struct Effect {
     pub name: String,
     pub clean: Option<EffectEntry>,
     pub delay: Option<EffectEntry>,
 }
 
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, Debug, Deserialize)]
+#[serde(default)]
 pub struct EffectEntry {
     pub id: u8,
     pub effect_tweak: String,
     pub controls: Vec<String>
 }
 
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, Debug, Deserialize)]
+#[serde(default)]
 pub struct Toggle {
     pub name: String,
     pub position_control: String,
@@ -100,7 +107,8 @@ pub struct Toggle {
     pub off_position: usize,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum Control {
     SwitchControl(SwitchControl),
     MidiSwitchControl(MidiSwitchControl),
@@ -113,9 +121,11 @@ pub enum Control {
     Button(Button)
 }
 
-#[derive(Clone)]
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Format<T> {
     None,
+    #[serde(skip)]
     Callback(fn (&T, f64) -> String),
     Data(FormatData),
     Interpolate(FormatInterpolate),
@@ -135,7 +145,8 @@ impl<T> fmt::Debug for Format<T>  {
 }
 
 /// v = kx + b
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
 pub struct FormatData {
     pub k: f64,
     pub b: f64,
@@ -149,7 +160,8 @@ impl Default for FormatData {
 }
 
 /// Interpolate between a given set of points
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
 pub struct FormatInterpolate {
     pub points: Vec<(u8, f64)>,
     pub format: String
@@ -161,33 +173,45 @@ impl Default for FormatInterpolate {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
 pub struct SwitchControl { pub cc: u8, pub addr: u8, pub inverted: bool }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
 pub struct MidiSwitchControl { pub cc: u8 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
 pub struct RangeControl { pub cc: u8, pub addr: u8, pub config: RangeConfig, pub format: Format<RangeConfig> }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
 pub struct AddrRangeControl { pub addr: u8, pub config: RangeConfig, pub format: Format<RangeConfig> }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
 pub struct VirtualRangeControl { pub config: RangeConfig, pub format: Format<RangeConfig> }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RangeConfig {
     Normal,
     Short { from: u8, to: u8, edge: bool },
     Long { from: u16, to: u16 },
     Steps { steps: Vec<u8> },
+    #[serde(skip)]
     Function { from_midi: fn(u8) -> u16, to_midi: fn(u16) -> u8 },
+    #[serde(skip)]
     Multibyte { from: u16, to: u16, size: u8, from_buffer: fn(u32) -> u16, to_buffer: fn(u16) -> u32 },
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
 pub struct Select { pub cc: u8, pub addr: u8 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
 pub struct MidiSelect { pub cc: u8 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
 pub struct VirtualSelect {}
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
 pub struct Button {}
 
 
@@ -653,6 +677,7 @@ impl Config {
             amp_models: vec![],
             cab_models: vec![],
             effects: vec![],
+            select_labels: Default::default(),
             controls: Default::default(),
             init_controls: vec![],
             out_cc_edit_buffer_dump_req: vec![],
@@ -668,6 +693,56 @@ impl Config {
         self.controls.get(name)
     }
 
+    /// Labels of the values of a select control, indexed by value.
+    /// Amp, cab and effect selects are labeled with the config models,
+    /// other selects with `select_labels`.
+    pub fn select_labels(&self, name: &str) -> Option<Vec<String>> {
+        let labels = match name {
+            "amp_select" => self.amp_models.iter().map(|amp| amp.name.clone()).collect(),
+            "cab_select" => self.cab_models.clone(),
+            "effect_select" => self.effects.iter().map(|effect| effect.name.clone()).collect(),
+            "effect_select:raw" => {
+                // raw effect select values are effect entry ids
+                let mut labels = vec![];
+                for effect in self.effects.iter() {
+                    let entries = [(&effect.clean, ""), (&effect.delay, " + Delay")];
+                    for (entry, suffix) in entries {
+                        let Some(id) = entry.as_ref().map(|e| e.id as usize) else { continue };
+                        if labels.len() <= id {
+                            labels.resize(id + 1, String::new());
+                        }
+                        labels[id] = format!("{}{}", effect.name, suffix);
+                    }
+                }
+                labels
+            }
+            _ => self.select_labels.get(name).cloned().unwrap_or_default()
+        };
+        (!labels.is_empty()).then_some(labels)
+    }
+
+    /// Range of the valid values of a control. Selects without labels
+    /// accept any MIDI value.
+    pub fn control_bounds(&self, name: &str) -> Option<(u16, u16)> {
+        let bounds = match self.controls.get(name)? {
+            Control::RangeControl(RangeControl { config, .. }) |
+            Control::AddrRangeControl(AddrRangeControl { config, .. }) |
+            Control::VirtualRangeControl(VirtualRangeControl { config, .. }) => {
+                let (from, to) = config.bounds();
+                (from as u16, to as u16)
+            }
+            Control::SwitchControl(_) | Control::MidiSwitchControl(_) => (0, 1),
+            Control::Select(_) | Control::MidiSelect(_) | Control::VirtualSelect(_) => {
+                let max = self.select_labels(name)
+                    .map(|labels| labels.len() - 1)
+                    .unwrap_or(127);
+                (0, max as u16)
+            }
+            Control::Button(_) => return None
+        };
+        Some(bounds)
+    }
+
     pub fn cc_to_control(&self, cc: u8) -> Option<(&String, &Control)> {
         self.controls.iter()
             .find(|(_, control)| {
@@ -0,0 +1,509 @@
+//! OSC (Open Sound Control) server for remote control of device
+//! parameters over UDP.
+//!
+//! The server understands the following addresses:
+//!
+//! * `/pod/control/<name> [value]` -- set the control `<name>` to `value`,
+//!   or reply with the current value if no value is given;
+//! * `/pod/program [program]` -- switch to a program given as a number
+//!   starting from 1, a program id such as "1A", or "manual"/"tuner",
+//!   or reply with the current program if no program is given;
+//! * `/pod/subscribe`, `/pod/unsubscribe` -- start/stop receiving
+//!   control and program changes. A newly subscribed client is sent
+//!   the current values of all controls and the current program.
+//!   Subscriptions expire unless renewed by sending `/pod/subscribe`
+//!   again within a minute, and only a few clients can be subscribed
+//!   at a time.
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use anyhow::*;
+use log::*;
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use crate::context::Ctx;
+use crate::controller::*;
+use crate::event::*;
+use crate::model::Config;
+use crate::remote::*;
+
+const CONTROL_PREFIX: &str = "/pod/control/";
+const PROGRAM_ADDR: &str = "/pod/program";
+const SUBSCRIBE_ADDR: &str = "/pod/subscribe";
+const UNSUBSCRIBE_ADDR: &str = "/pod/unsubscribe";
+const BUNDLE_TAG: &[u8] = b"#bundle\0";
+const MAX_PACKET_SIZE: usize = 8192;
+const MAX_CLIENTS: usize = 8;
+const SUBSCRIPTION_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+    String(String),
+}
+
+impl OscArg {
+    fn as_int(&self) -> Option<i32> {
+        match self {
+            OscArg::Int(v) => Some(*v),
+            OscArg::Float(v) => Some(v.round() as i32),
+            OscArg::String(_) => None
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OscMessage {
+    pub addr: String,
+    pub args: Vec<OscArg>,
+}
+
+fn pad4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn write_str(bytes: &mut Vec<u8>, str: &str) {
+    bytes.extend_from_slice(str.as_bytes());
+    // at least one terminating zero, padded to 4 bytes
+    let len = pad4(str.len() + 1);
+    bytes.resize(bytes.len() + len - str.len(), 0);
+}
+
+fn read_str<'a>(bytes: &'a [u8], pos: &mut usize) -> Result<&'a str> {
+    let data = bytes.get(*pos ..).unwrap_or_default();
+    let len = data.iter().position(|b| *b == 0)
+        .context("Unterminated OSC string")?;
+    let str = std::str::from_utf8(&data[.. len])
+        .context("Error converting OSC string to UTF-8")?;
+    *pos += pad4(len + 1);
+    Ok(str)
+}
+
+fn read_u32(bytes: &[u8], pos: &mut usize) -> Result<u32> {
+    let data = bytes.get(*pos .. *pos + 4)
+        .context("Truncated OSC argument")?;
+    *pos += 4;
+    Ok(u32::from_be_bytes([data[0], data[1], data[2], data[3]]))
+}
+
+impl OscMessage {
+    pub fn new(addr: &str, args: Vec<OscArg>) -> Self {
+        Self { addr: addr.to_string(), args }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        write_str(&mut bytes, &self.addr);
+        let tags = self.args.iter()
+            .map(|arg| match arg {
+                OscArg::Int(_) => 'i',
+                OscArg::Float(_) => 'f',
+                OscArg::String(_) => 's'
+            })
+            .collect::<String>();
+        write_str(&mut bytes, &format!(",{}", tags));
+        for arg in self.args.iter() {
+            match arg {
+                OscArg::Int(v) => bytes.extend_from_slice(&v.to_be_bytes()),
+                OscArg::Float(v) => bytes.extend_from_slice(&v.to_be_bytes()),
+                OscArg::String(v) => write_str(&mut bytes, v)
+            }
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut pos = 0;
+        let addr = read_str(bytes, &mut pos)?;
+        if !addr.starts_with('/') {
+            bail!("Invalid OSC address {:?}", addr);
+        }
+        // Some old implementations omit the type tag string
+        let tags = if pos < bytes.len() { read_str(bytes, &mut pos)? } else { "," };
+        let Some(tags) = tags.strip_prefix(',') else {
+            bail!("Invalid OSC type tag string {:?}", tags);
+        };
+
+        let mut args = vec![];
+        for tag in tags.chars() {
+            let arg = match tag {
+                'i' => OscArg::Int(read_u32(bytes, &mut pos)? as i32),
+                'f' => OscArg::Float(f32::from_bits(read_u32(bytes, &mut pos)?)),
+                's' => OscArg::String(read_str(bytes, &mut pos)?.to_string()),
+                'T' => OscArg::Int(1),
+                'F' => OscArg::Int(0),
+                _ => bail!("Unsupported OSC argument type {:?}", tag)
+            };
+            args.push(arg);
+        }
+
+        Ok(Self { addr: addr.to_string(), args })
+    }
+
+    /// Parse an OSC packet, which is either a single message or a bundle
+    /// of messages. Bundle time tags are ignored and all messages are
+    /// returned for immediate execution.
+    pub fn from_packet(bytes: &[u8]) -> Result<Vec<Self>> {
+        let Some(mut data) = bytes.strip_prefix(BUNDLE_TAG) else {
+            return Ok(vec![Self::from_bytes(bytes)?]);
+        };
+        // skip the time tag
+        data = data.get(8 ..).context("Truncated OSC bundle")?;
+
+        let mut messages = vec![];
+        while !data.is_empty() {
+            let mut pos = 0;
+            let len = read_u32(data, &mut pos)? as usize;
+            let element = data.get(pos .. pos + len)
+                .context("Truncated OSC bundle element")?;
+            messages.extend(Self::from_packet(element)?);
+            data = &data[pos + len ..];
+        }
+        Ok(messages)
+    }
+}
+
+#[derive(Default)]
+struct OscState {
+    target: Option<RemoteTarget>,
+    /// Subscribed clients and the time of their last subscription
+    clients: Vec<(SocketAddr, Instant)>,
+}
+
+fn program_arg(program: &Program) -> OscArg {
+    match program {
+        Program::ManualMode => OscArg::String("manual".into()),
+        Program::Tuner => OscArg::String("tuner".into()),
+        Program::Program(p) => OscArg::Int(*p as i32 + 1)
+    }
+}
+
+/// Program from an OSC argument, given as a program number starting
+/// from 1 or as a string
+fn program_from_arg(config: &Config, arg: &OscArg) -> Option<Program> {
+    match arg {
+        OscArg::String(s) => program_from_str(config, s),
+        arg => program_from_num(config, arg.as_int()? as i64 - 1)
+    }
+}
+
+fn control_message(name: &str, value: u16) -> OscMessage {
+    OscMessage::new(&format!("{}{}", CONTROL_PREFIX, name), vec![OscArg::Int(value as i32)])
+}
+
+fn program_message(program: &Program) -> OscMessage {
+    OscMessage::new(PROGRAM_ADDR, vec![program_arg(program)])
+}
+
+impl OscState {
+    /// Handle an incoming message, returning replies to the sender
+    fn handle(&mut self, msg: &OscMessage, from: SocketAddr) -> Vec<OscMessage> {
+        match msg.addr.as_str() {
+            SUBSCRIBE_ADDR => {
+                if self.subscribe(from, Instant::now()) {
+                    self.snapshot()
+                } else {
+                    vec![]
+                }
+            }
+            UNSUBSCRIBE_ADDR => {
+                info!("OSC client {} unsubscribed", from);
+                self.clients.retain(|(c, _)| *c != from);
+                vec![]
+            }
+            _ => {
+                let Some(target) = self.target.as_ref() else {
+                    warn!("OSC message {:?} without device", msg.addr);
+                    return vec![];
+                };
+                if msg.addr == PROGRAM_ADDR {
+                    Self::program(target, msg)
+                } else if let Some(name) = msg.addr.strip_prefix(CONTROL_PREFIX) {
+                    Self::control(target, name, msg)
+                } else {
+                    warn!("Unknown OSC address {:?}", msg.addr);
+                    vec![]
+                }
+            }
+        }
+    }
+
+    /// Subscribe or renew the subscription of client `from`. Returns `true`
+    /// for a new subscription, which is to be answered with a snapshot.
+    fn subscribe(&mut self, from: SocketAddr, now: Instant) -> bool {
+        self.expire(now);
+        if let Some((_, time)) = self.clients.iter_mut().find(|(c, _)| *c == from) {
+            *time = now;
+            return false;
+        }
+        if self.clients.len() >= MAX_CLIENTS {
+            warn!("OSC client {} not subscribed: too many clients", from);
+            return false;
+        }
+        info!("OSC client {} subscribed", from);
+        self.clients.push((from, now));
+        true
+    }
+
+    /// Drop the subscriptions that were not renewed in time
+    fn expire(&mut self, now: Instant) {
+        self.clients.retain(|(c, time)| {
+            let active = now.duration_since(*time) < SUBSCRIPTION_TIMEOUT;
+            if !active {
+                info!("OSC client {} subscription expired", c);
+            }
+            active
+        });
+    }
+
+    fn control(target: &RemoteTarget, name: &str, msg: &OscMessage) -> Vec<OscMessage> {
+        if !target.config.controls.contains_key(name) {
+            warn!("OSC: control {:?} not found", name);
+            return vec![];
+        }
+        let Some(arg) = msg.args.first() else {
+            let value = target.controller.get(name).unwrap_or_default();
+            return vec![control_message(name, value)];
+        };
+        let Some(value) = arg.as_int() else {
+            warn!("OSC: invalid value {:?} for control {:?}", arg, name);
+            return vec![];
+        };
+        if let Err(e) = target.set_control(name, value as i64) {
+            warn!("OSC: {}", e);
+        }
+        vec![]
+    }
+
+    fn program(target: &RemoteTarget, msg: &OscMessage) -> Vec<OscMessage> {
+        let Some(arg) = msg.args.first() else {
+            return vec![program_message(&target.program())];
+        };
+        match program_from_arg(target.config, arg) {
+            Some(program) => target.set_program(program),
+            None => warn!("OSC: program {:?} not found", arg)
+        }
+        vec![]
+    }
+
+    /// Current values of all controls and the current program
+    fn snapshot(&self) -> Vec<OscMessage> {
+        let Some(target) = self.target.as_ref() else {
+            return vec![];
+        };
+        let controller = target.controller.lock().unwrap();
+        let mut messages = controller.ordered_controls().iter()
+            .flat_map(|(name, _)| controller.get(name).map(|v| control_message(name, v)))
+            .collect::<Vec<_>>();
+        messages.push(program_message(&target.program()));
+        messages
+    }
+
+    /// Convert an app event into a message for subscribed clients
+    fn publish(event: &AppEvent) -> Option<OscMessage> {
+        match event {
+            AppEvent::ControlChange(ControlChangeEvent { name, value, .. }) =>
+                Some(control_message(name, *value)),
+            AppEvent::ProgramChange(ProgramChangeEvent { program, .. }) =>
+                Some(program_message(program)),
+            _ => None
+        }
+    }
+}
+
+/// An OSC server mapping OSC messages to the controllers of the current
+/// device and publishing control and program changes from the app event
+/// bus to subscribed clients
+#[derive(Clone)]
+pub struct OscServer {
+    state: Arc<Mutex<OscState>>,
+}
+
+impl OscServer {
+    /// Start an OSC server listening on UDP `addr`. Must be called from
+    /// within a tokio runtime.
+    pub fn start(addr: SocketAddr, app_event_tx: &broadcast::Sender<AppEvent>) -> Result<Self> {
+        let socket = std::net::UdpSocket::bind(addr)
+            .with_context(|| format!("Failed to bind OSC server to {}", addr))?;
+        socket.set_nonblocking(true)?;
+        let socket = Arc::new(UdpSocket::from_std(socket)?);
+        info!("OSC server listening on {}", addr);
+
+        let server = Self { state: Arc::new(Mutex::new(OscState::default())) };
+        tokio::spawn(recv_task(socket.clone(), server.state.clone()));
+        tokio::spawn(publish_task(socket, server.state.clone(), app_event_tx.subscribe()));
+
+        Ok(server)
+    }
+
+    /// Set the device context to control, `None` if there is no device
+    pub fn set_ctx(&self, ctx: Option<&Ctx>) {
+        self.state.lock().unwrap().target = ctx.map(RemoteTarget::new);
+    }
+}
+
+async fn send(socket: &UdpSocket, msg: &OscMessage, to: SocketAddr) {
+    if let Err(e) = socket.send_to(&msg.to_bytes(), to).await {
+        warn!("Failed to send OSC message to {}: {}", to, e);
+    }
+}
+
+async fn recv_task(socket: Arc<UdpSocket>, state: Arc<Mutex<OscState>>) {
+    let mut buf = vec![0u8; MAX_PACKET_SIZE];
+    loop {
+        let (len, from) = match socket.recv_from(&mut buf).await {
+            Result::Ok(v) => v,
+            Err(e) => {
+                error!("OSC server receive failed: {}", e);
+                continue;
+            }
+        };
+        let messages = match OscMessage::from_packet(&buf[.. len]) {
+            Result::Ok(messages) => messages,
+            Err(e) => {
+                warn!("Invalid OSC packet from {}: {}", from, e);
+                continue;
+            }
+        };
+        for msg in messages {
+            trace!("OSC << {} {:?}", from, msg);
+            let replies = state.lock().unwrap().handle(&msg, from);
+            for reply in replies {
+                send(&socket, &reply, from).await;
+            }
+        }
+    }
+}
+
+async fn publish_task(socket: Arc<UdpSocket>, state: Arc<Mutex<OscState>>,
+                      mut app_event_rx: broadcast::Receiver<AppEvent>) {
+    loop {
+        let event = match app_event_rx.recv().await {
+            Result::Ok(event) => event,
+            Err(RecvError::Closed) => return,
+            Err(RecvError::Lagged(n)) => {
+                warn!("OSC server lagged: {}", n);
+                continue;
+            }
+        };
+        let Some(msg) = OscState::publish(&event) else {
+            continue;
+        };
+        let clients = {
+            let mut state = state.lock().unwrap();
+            state.expire(Instant::now());
+            state.clients.iter().map(|(c, _)| *c).collect::<Vec<_>>()
+        };
+        for client in clients {
+            send(&socket, &msg, client).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::*;
+    use crate::test_util;
+
+    #[test]
+    fn message_round_trip() {
+        let msg = OscMessage::new("/pod/control/drive", vec![
+            OscArg::Int(-5), OscArg::Float(0.5), OscArg::String("abcd".into())
+        ]);
+        let bytes = msg.to_bytes();
+        assert_eq!(bytes.len() % 4, 0);
+        assert_eq!(OscMessage::from_packet(&bytes).unwrap(), vec![msg]);
+    }
+
+    #[test]
+    fn bundle() {
+        let a = OscMessage::new("/pod/program", vec![OscArg::Int(3)]);
+        let b = OscMessage::new("/pod/control/drive", vec![OscArg::Int(64)]);
+        let mut bytes = BUNDLE_TAG.to_vec();
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        for msg in [&a, &b] {
+            let data = msg.to_bytes();
+            bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            bytes.extend(data);
+        }
+        assert_eq!(OscMessage::from_packet(&bytes).unwrap(), vec![a, b]);
+    }
+
+    #[test]
+    fn malformed_packets() {
+        let msg = OscMessage::new("/pod/program", vec![OscArg::Int(3)]).to_bytes();
+        for len in 0 .. msg.len() {
+            // must not panic
+            let _ = OscMessage::from_packet(&msg[.. len]);
+        }
+        assert!(OscMessage::from_packet(b"pod\0").is_err());
+        assert!(OscMessage::from_packet(b"#bundle\0\0\0\0\0\0\0\0\0\0\0\0\x10").is_err());
+    }
+
+    fn target() -> RemoteTarget {
+        let mut config = test_util::config("OSC POD");
+        config.cab_models = vec!["1x12".into(), "2x12".into()];
+        config.controls.insert("drive".into(), RangeControl {
+            cc: 13, addr: 0, config: RangeConfig::Short { from: 0, to: 63, edge: false },
+            ..Default::default()
+        }.into());
+        config.controls.insert("cab_select".into(), Select { cc: 71, addr: 1 }.into());
+        config.controls.insert("name_change".into(), Button {}.into());
+        let config = test_util::leak(config);
+        let ui_controls = [("program".to_string(), VirtualSelect::default().into())].into_iter().collect();
+
+        RemoteTarget {
+            config,
+            controller: Arc::new(Mutex::new(Controller::new(config.controls.clone()))),
+            ui_controller: Arc::new(Mutex::new(Controller::new(ui_controls))),
+        }
+    }
+
+    #[test]
+    fn control_values_are_range_checked() {
+        let mut state = OscState { target: Some(target()), ..Default::default() };
+        let from = SocketAddr::from(([127, 0, 0, 1], 9000));
+        let mut set = |name: &str, arg: OscArg| {
+            let addr = format!("{}{}", CONTROL_PREFIX, name);
+            state.handle(&OscMessage::new(&addr, vec![arg]), from);
+            let controller = state.target.as_ref().unwrap().controller.lock().unwrap();
+            controller.get(name)
+        };
+
+        assert_eq!(set("drive", OscArg::Int(63)), Some(63));
+        assert_eq!(set("drive", OscArg::Int(64)), Some(63));
+        assert_eq!(set("drive", OscArg::Int(-1)), Some(63));
+        assert_eq!(set("drive", OscArg::Float(10.2)), Some(10));
+        assert_eq!(set("cab_select", OscArg::Int(1)), Some(1));
+        assert_eq!(set("cab_select", OscArg::Int(2)), Some(1));
+        assert_eq!(set("cab_select", OscArg::String("2x12".into())), Some(1));
+        assert_eq!(set("name_change", OscArg::Int(1)), Some(0));
+    }
+
+    #[test]
+    fn subscriptions_are_limited() {
+        let mut state = OscState { target: Some(target()), ..Default::default() };
+        let client = |port| SocketAddr::from(([127, 0, 0, 1], port));
+        let subscribe = OscMessage::new(SUBSCRIBE_ADDR, vec![]);
+        let start = Instant::now();
+
+        assert!(!state.handle(&subscribe, client(9000)).is_empty());
+        // a renewal is not answered with another snapshot
+        assert!(state.handle(&subscribe, client(9000)).is_empty());
+        for port in 9001 .. 9000 + MAX_CLIENTS as u16 {
+            assert!(state.subscribe(client(port), start));
+        }
+        assert!(!state.subscribe(client(9100), start));
+        assert_eq!(state.clients.len(), MAX_CLIENTS);
+
+        // only the renewed subscription survives the timeout
+        let later = start + SUBSCRIPTION_TIMEOUT;
+        assert!(!state.subscribe(client(9000), later - Duration::from_secs(1)));
+        assert!(state.subscribe(client(9100), later));
+        let clients = state.clients.iter().map(|(c, _)| *c).collect::<Vec<_>>();
+        assert_eq!(clients, vec![client(9000), client(9100)]);
+    }
+}
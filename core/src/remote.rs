@@ -0,0 +1,130 @@
+//! Code shared by the remote control servers, the OSC server in `osc`
+//! and the JSON API server in `api`: the device they operate on, parsing
+//! of program and control values sent by clients, and parsing of server
+//! addresses.
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use anyhow::*;
+use crate::context::Ctx;
+use crate::controller::*;
+use crate::event::*;
+use crate::model::Config;
+use crate::program_id_string;
+
+/// Parse a server address given either as `<port>`, which listens
+/// on the loopback interface only, or as `<address>:<port>`
+pub fn parse_addr(str: &str) -> Result<SocketAddr> {
+    if let Result::Ok(port) = str.parse::<u16>() {
+        return Ok(SocketAddr::from(([127, 0, 0, 1], port)));
+    }
+    str.parse::<SocketAddr>()
+        .with_context(|| format!("Invalid server address {:?}", str))
+}
+
+/// Controllers of the current device that a remote control server
+/// operates on
+#[derive(Clone)]
+pub struct RemoteTarget {
+    pub config: &'static Config,
+    pub controller: Arc<Mutex<Controller>>,
+    pub ui_controller: Arc<Mutex<Controller>>,
+}
+
+impl RemoteTarget {
+    pub fn new(ctx: &Ctx) -> Self {
+        Self {
+            config: ctx.config,
+            controller: ctx.controller.clone(),
+            ui_controller: ctx.ui_controller.clone()
+        }
+    }
+
+    pub fn program(&self) -> Program {
+        self.ui_controller.get("program").unwrap().into()
+    }
+
+    /// Same as `Ctx::set_program` with UI origin
+    pub fn set_program(&self, program: Program) {
+        self.ui_controller.set("program", program.into(), Origin::UI.into());
+    }
+
+    /// Set control `name` to `value` with UI origin, if the value is within
+    /// the control's range
+    pub fn set_control(&self, name: &str, value: i64) -> Result<()> {
+        let value = control_value(self.config, name, value)?;
+        self.controller.set(name, value, StoreOrigin::UI);
+        Ok(())
+    }
+}
+
+/// Program number of a program id such as "1A"
+pub fn program_num_from_id(config: &Config, id: &str) -> Option<usize> {
+    (0 .. config.program_num).find(|i| program_id_string(*i).eq_ignore_ascii_case(id))
+}
+
+/// Program `num`, counting from 0, if the device has it
+pub fn program_from_num(config: &Config, num: i64) -> Option<Program> {
+    usize::try_from(num).ok()
+        .filter(|p| *p < config.program_num)
+        .map(|p| Program::Program(p as u16))
+}
+
+/// Program given as "manual", "tuner" or a program id such as "1A",
+/// if the device has it
+pub fn program_from_str(config: &Config, str: &str) -> Option<Program> {
+    if str.eq_ignore_ascii_case("manual") {
+        config.pc_manual_mode.map(|_| Program::ManualMode)
+    } else if str.eq_ignore_ascii_case("tuner") {
+        config.pc_tuner.map(|_| Program::Tuner)
+    } else {
+        program_num_from_id(config, str).map(|p| Program::Program(p as u16))
+    }
+}
+
+/// Value of control `name`, if the control exists and the value is
+/// within its range
+pub fn control_value(config: &Config, name: &str, value: i64) -> Result<u16> {
+    if !config.controls.contains_key(name) {
+        bail!("Control {:?} not found", name);
+    }
+    let Some((min, max)) = config.control_bounds(name) else {
+        bail!("Control {:?} has no value", name);
+    };
+    if value < min as i64 || value > max as i64 {
+        bail!("Value {} for control {:?} out of range {}..{}", value, name, min, max);
+    }
+    Ok(value as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn port_binds_loopback() {
+        assert_eq!(parse_addr("9000").unwrap(), SocketAddr::from(([127, 0, 0, 1], 9000)));
+        assert_eq!(parse_addr("0.0.0.0:9000").unwrap(), SocketAddr::from(([0, 0, 0, 0], 9000)));
+        assert!(parse_addr("localhost").is_err());
+    }
+
+    #[test]
+    fn programs_and_control_values() {
+        let mut config = test_util::config("Remote POD");
+        config.pc_tuner = Some(0);
+        config.controls.insert("name_change".into(), crate::model::Button {}.into());
+
+        assert_eq!(program_from_str(&config, "1d"), Some(Program::Program(3)));
+        assert_eq!(program_from_str(&config, "TUNER"), Some(Program::Tuner));
+        assert_eq!(program_from_str(&config, "manual"), None);
+        assert_eq!(program_from_num(&config, 3), Some(Program::Program(3)));
+        assert_eq!(program_from_num(&config, 4), None);
+        assert_eq!(program_from_num(&config, -1), None);
+
+        assert_eq!(control_value(&config, "drive", 127).unwrap(), 127);
+        assert!(control_value(&config, "drive", 128).is_err());
+        assert!(control_value(&config, "drive", -1).is_err());
+        assert!(control_value(&config, "name_change", 1).is_err());
+        assert!(control_value(&config, "treble", 1).is_err());
+    }
+}
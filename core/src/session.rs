@@ -0,0 +1,349 @@
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use anyhow::*;
+use async_trait::async_trait;
+use log::*;
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+use crate::midi_io::{MidiIn, MidiOut};
+
+const SESSION_HEADER: &str = "# POD UI MIDI session";
+
+/// A single MIDI message as seen by the MIDI in/out tasks
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionEntry {
+    /// Time since the start of the recording
+    pub time: Duration,
+    pub is_out: bool,
+    pub bytes: Vec<u8>,
+}
+
+/// A recorded MIDI session.
+///
+/// A session file is a line-based text file: a header of `key: value`
+/// pairs describing the device, followed by one line per MIDI message
+/// with the time in seconds, the direction (`in` or `out`) and the
+/// message bytes in hex. Lines starting with `#` are comments.
+#[derive(Clone, Debug, Default)]
+pub struct Session {
+    pub device: Option<String>,
+    pub channel: Option<u8>,
+    pub entries: Vec<SessionEntry>,
+}
+
+impl Session {
+    pub fn load(path: &Path) -> Result<Self> {
+        let str = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {:?}", path))?;
+        Self::from_str(&str)
+    }
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+fn entry_line(entry: &SessionEntry) -> String {
+    let dir = if entry.is_out { "out" } else { "in" };
+    format!("{:.3} {} {}", entry.time.as_secs_f64(), dir, to_hex(&entry.bytes))
+}
+
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", SESSION_HEADER)?;
+        if let Some(device) = self.device.as_ref() {
+            writeln!(f, "device: {}", device)?;
+        }
+        if let Some(channel) = self.channel {
+            writeln!(f, "channel: {}", channel)?;
+        }
+        for entry in self.entries.iter() {
+            writeln!(f, "{}", entry_line(entry))?;
+        }
+
+        fmt::Result::Ok(())
+    }
+}
+
+impl FromStr for Session {
+    type Err = Error;
+
+    fn from_str(str: &str) -> Result<Self> {
+        let mut session = Session::default();
+        for (n, line) in str.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once(':') {
+                let value = value.trim();
+                match key.trim() {
+                    "device" => session.device = Some(value.to_string()),
+                    "channel" => {
+                        let channel = value.parse::<u8>()
+                            .with_context(|| format!("Invalid channel {:?}", value))?;
+                        session.channel = Some(channel);
+                    }
+                    key => warn!("Unknown session key {:?}", key)
+                }
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+            let (Some(time), Some(dir)) = (parts.next(), parts.next()) else {
+                bail!("Invalid session entry on line {}", n + 1);
+            };
+            let time = time.parse::<f64>()
+                .with_context(|| format!("Invalid time {:?} on line {}", time, n + 1))?;
+            let is_out = match dir {
+                "in" => false,
+                "out" => true,
+                _ => bail!("Invalid direction {:?} on line {}", dir, n + 1)
+            };
+            let bytes = parts
+                .map(|b| u8::from_str_radix(b, 16)
+                    .with_context(|| format!("Invalid byte {:?} on line {}", b, n + 1)))
+                .collect::<Result<Vec<_>>>()?;
+            session.entries.push(SessionEntry { time: Duration::from_secs_f64(time), is_out, bytes });
+        }
+
+        Ok(session)
+    }
+}
+
+struct RecorderInner {
+    file: File,
+    start: Instant,
+}
+
+/// Writes all MIDI traffic into a session file as it happens
+#[derive(Clone)]
+pub struct SessionRecorder {
+    inner: Arc<Mutex<RecorderInner>>,
+}
+
+impl SessionRecorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        let mut file = File::create(path)
+            .with_context(|| format!("Failed to create {:?}", path))?;
+        writeln!(file, "{}", SESSION_HEADER)?;
+        let inner = RecorderInner { file, start: Instant::now() };
+
+        Ok(Self { inner: Arc::new(Mutex::new(inner)) })
+    }
+
+    /// Record the start of a new MIDI connection
+    pub fn connected(&self, device: &str, channel: u8, in_name: &str, out_name: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        let res = writeln!(inner.file, "# in: {}, out: {}", in_name, out_name)
+            .and_then(|_| writeln!(inner.file, "device: {}", device))
+            .and_then(|_| writeln!(inner.file, "channel: {}", channel));
+        if let Err(e) = res {
+            error!("Failed to write MIDI session: {}", e);
+        }
+    }
+
+    pub fn record(&self, is_out: bool, bytes: &[u8]) {
+        let mut inner = self.inner.lock().unwrap();
+        let entry = SessionEntry { time: inner.start.elapsed(), is_out, bytes: bytes.to_vec() };
+        if let Err(e) = writeln!(inner.file, "{}", entry_line(&entry)) {
+            error!("Failed to write MIDI session: {}", e);
+        }
+    }
+}
+
+struct Replay {
+    entries: Vec<SessionEntry>,
+    /// Index of the next entry to be replayed
+    pos: usize,
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+}
+
+impl Replay {
+    /// Schedule all device responses following the current position up
+    /// to the next outgoing message, keeping their recorded timing
+    /// relative to `start`
+    fn schedule(&mut self, start: Duration) {
+        let responses = self.entries[self.pos ..].iter()
+            .take_while(|e| !e.is_out)
+            .map(|e| (e.time.saturating_sub(start), e.bytes.clone()))
+            .collect::<Vec<_>>();
+        self.pos += responses.len();
+        if responses.is_empty() {
+            return;
+        }
+
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            let mut elapsed = Duration::ZERO;
+            for (time, bytes) in responses {
+                sleep(time.saturating_sub(elapsed)).await;
+                elapsed = elapsed.max(time);
+                if tx.send(bytes).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Find the recorded outgoing message matching `bytes` and schedule
+    /// the device responses to it
+    fn sent(&mut self, bytes: &[u8]) {
+        let found = self.entries[self.pos ..].iter()
+            .position(|e| e.is_out && e.bytes == bytes)
+            .map(|i| self.pos + i);
+        let Some(i) = found else {
+            warn!("Replay: no recorded response to {:02x?}", bytes);
+            return;
+        };
+        if i != self.pos {
+            debug!("Replay: skipping {} recorded messages", i - self.pos);
+        }
+        let start = self.entries[i].time;
+        self.pos = i + 1;
+        self.schedule(start);
+    }
+}
+
+/// MIDI input replaying device responses from a recorded session
+pub struct ReplayMidiIn {
+    name: String,
+    replay: Arc<Mutex<Replay>>,
+    rx: mpsc::UnboundedReceiver<Vec<u8>>,
+    started: bool,
+}
+
+/// MIDI output that matches sent messages against a recorded session
+/// and triggers the recorded device responses on the replay input
+pub struct ReplayMidiOut {
+    name: String,
+    replay: Arc<Mutex<Replay>>,
+}
+
+/// Create a MIDI in/out pair replaying the `session`
+pub fn replay_ports(session: Session, name: &str) -> (ReplayMidiIn, ReplayMidiOut) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let replay = Arc::new(Mutex::new(Replay { entries: session.entries, pos: 0, tx }));
+    let name = format!("replay: {}", name);
+
+    let midi_in = ReplayMidiIn { name: name.clone(), replay: replay.clone(), rx, started: false };
+    let midi_out = ReplayMidiOut { name, replay };
+    (midi_in, midi_out)
+}
+
+#[async_trait]
+impl MidiIn for ReplayMidiIn {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn recv(&mut self) -> Option<Vec<u8>> {
+        if !self.started {
+            // messages the device sent before anything was sent to it
+            self.started = true;
+            self.replay.lock().unwrap().schedule(Duration::ZERO);
+        }
+        self.rx.recv().await
+    }
+
+    fn close(&mut self) {
+        self.rx.close();
+    }
+
+    fn no_reply_retry(&self) -> usize {
+        0
+    }
+}
+
+#[async_trait]
+impl MidiOut for ReplayMidiOut {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn send(&mut self, bytes: &[u8]) -> Result<()> {
+        trace!(">> {:02x?} len={}", bytes, bytes.len());
+        self.replay.lock().unwrap().sent(bytes);
+        Ok(())
+    }
+
+    fn close(&mut self) {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(ms: u64, is_out: bool, bytes: &[u8]) -> SessionEntry {
+        SessionEntry { time: Duration::from_millis(ms), is_out, bytes: bytes.to_vec() }
+    }
+
+    #[test]
+    fn record_then_parse() {
+        let path = std::env::temp_dir()
+            .join(format!("pod-session-{}.txt", std::process::id()));
+        let recorder = SessionRecorder::create(&path).unwrap();
+        recorder.connected("POD 2.0", 2, "POD in", "POD out");
+        recorder.record(true, &[0xf0, 0x7e, 0x7f, 0x06, 0x01, 0xf7]);
+        recorder.record(false, &[0xc2, 0x05]);
+        drop(recorder);
+
+        let str = std::fs::read_to_string(&path).unwrap();
+        let session = Session::load(&path);
+        std::fs::remove_file(&path).ok();
+        let session = session.unwrap();
+
+        assert!(str.starts_with(SESSION_HEADER));
+        assert_eq!(session.device.as_deref(), Some("POD 2.0"));
+        assert_eq!(session.channel, Some(2));
+        assert_eq!(session.entries.len(), 2);
+        assert!(session.entries[0].is_out);
+        assert_eq!(session.entries[0].bytes, vec![0xf0, 0x7e, 0x7f, 0x06, 0x01, 0xf7]);
+        assert!(!session.entries[1].is_out);
+        assert_eq!(session.entries[1].bytes, vec![0xc2, 0x05]);
+        assert!(session.entries[0].time <= session.entries[1].time);
+    }
+
+    #[test]
+    fn display_then_parse() {
+        let session = Session {
+            device: Some("PODxt".into()),
+            channel: None,
+            entries: vec![
+                entry(0, true, &[0xb0, 0x0d, 0x40]),
+                entry(1250, false, &[0xf0, 0x00, 0x01, 0x0c, 0xf7]),
+            ]
+        };
+
+        let parsed = Session::from_str(&session.to_string()).unwrap();
+
+        assert_eq!(parsed.device, session.device);
+        assert_eq!(parsed.channel, None);
+        assert_eq!(parsed.entries, session.entries);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let parse = |line: &str| {
+            Session::from_str(&format!("{}\ndevice: POD 2.0\n{}\n", SESSION_HEADER, line))
+        };
+
+        assert!(parse("0.100 in c0 01").is_ok());
+        assert!(parse("# 0.100 sideways c0 01").is_ok());
+        assert!(parse("0.100").is_err());
+        assert!(parse("soon in c0 01").is_err());
+        assert!(parse("0.100 sideways c0 01").is_err());
+        assert!(parse("0.100 in c0 xyz").is_err());
+        assert!(parse("0.100 in c0 100").is_err());
+        assert!(parse("channel: 300").is_err());
+
+        let err = parse("0.100 in c0 zz").unwrap_err();
+        assert!(err.to_string().contains("line 3"), "{}", err);
+    }
+}
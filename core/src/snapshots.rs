@@ -0,0 +1,69 @@
+use log::*;
+use crate::context::Ctx;
+use crate::controller::Controller;
+use crate::event::*;
+use crate::program;
+
+/// In-memory snapshots of the edit buffer for A/B comparison while
+/// tweaking a tone.
+///
+/// Selecting a slot keeps the current edit buffer in the active slot
+/// and replaces the edit buffer with the contents of the selected one,
+/// sending it to the device as an edit buffer dump. Snapshots never
+/// touch the programs: a snapshot only ends up in a program when the
+/// edit buffer is explicitly stored.
+pub struct EditSnapshots {
+    slots: Vec<Option<Vec<u8>>>,
+    active: usize,
+}
+
+impl EditSnapshots {
+    pub fn new(num: usize) -> Self {
+        Self { slots: vec![None; num], active: 0 }
+    }
+
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    /// Switch the edit buffer to snapshot `slot`. An empty slot starts as
+    /// a copy of the current edit buffer. Returns `false` if there is no
+    /// such slot.
+    pub fn select(&mut self, ctx: &Ctx, slot: usize) -> bool {
+        if slot >= self.slots.len() {
+            warn!("Snapshot slot {} out of range", slot);
+            return false;
+        }
+        if slot == self.active {
+            return true;
+        }
+
+        let mut edit = ctx.edit.lock().unwrap();
+        let current = program::store_patch_dump_ctrl(&edit);
+        let Some(data) = self.slots[slot].take() else {
+            // an empty slot gets a copy of the edit buffer, there is
+            // nothing to send to the device
+            self.slots[self.active] = Some(current.clone());
+            self.slots[slot] = Some(current);
+            self.active = slot;
+            return true;
+        };
+        self.slots[self.active] = Some(current);
+        self.slots[slot] = Some(data.clone());
+        self.active = slot;
+
+        let value_fn = |controller: &mut Controller, name: &str, buffer: &[u8]| {
+            ctx.handler.control_value_from_buffer(controller, name, buffer)
+        };
+        program::load_patch_dump_ctrl(&mut edit, &data, value_fn);
+        edit.set_modified(true);
+        drop(edit);
+
+        let e = BufferStoreEvent { buffer: Buffer::EditBuffer, origin: Origin::UI };
+        ctx.app_event_tx.send_or_warn(AppEvent::Store(e));
+        let e = ModifiedEvent { buffer: Buffer::Current, origin: Origin::UI, modified: true };
+        ctx.app_event_tx.send_or_warn(AppEvent::Modified(e));
+
+        true
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::BTreeSet;
+use anyhow::*;
+use log::*;
+use crate::config::config_for_id;
+use crate::edit::EditBuffer;
+use crate::midi::MidiMessage;
+use crate::model::{AbstractControl, Config};
+use crate::program;
+
+/// Device family of the PODxt line of devices, which use `XtBufferDump`
+/// messages instead of `ProgramEditBufferDump` for the edit buffer.
+const XT_FAMILY: u16 = 0x0003;
+
+fn is_xt_family(config: &Config) -> bool {
+    config.family == XT_FAMILY
+}
+
+/// Program data addresses of all controls stored in the program
+fn program_layout(config: &Config) -> BTreeSet<(&str, u8, u8)> {
+    config.controls.iter()
+        .filter_map(|(name, control)| {
+            control.get_addr().map(|(addr, len)| (name.as_str(), addr, len))
+        })
+        .collect()
+}
+
+/// Two configs share the same patch format if they are of the same
+/// family, have the same program size, the same amp models and the same
+/// controls at the same program addresses. This tells apart, for example,
+/// a PODxt from a Bass PODxt or a POD 2.0 from a Pocket POD.
+pub(crate) fn same_patch_format(a: &Config, b: &Config) -> bool {
+    a.family == b.family &&
+        a.program_size == b.program_size &&
+        a.amp_models.len() == b.amp_models.len() &&
+        a.amp_models.iter().zip(b.amp_models.iter()).all(|(a, b)| a.name == b.name) &&
+        program_layout(a) == program_layout(b)
+}
+
+/// Check that a patch made on a device identified by `family` and `member`
+/// can be loaded into a device with the given `config`
+pub(crate) fn check_patch_source(family: u16, member: u16, config: &Config) -> Result<()> {
+    if family == config.family && member == config.member {
+        return Ok(());
+    }
+    let Some(from) = config_for_id(family, member) else {
+        bail!("Patch is for an unknown device: {:04x}:{:04x}", family, member);
+    };
+    if !same_patch_format(from, config) {
+        bail!("Patch for {:?} cannot be loaded into {:?}", from.name, config.name);
+    }
+    warn!("Loading patch for {:?} into {:?}", from.name, config.name);
+    Ok(())
+}
+
+/// Convert edit buffer data into a sysex message suitable for saving
+/// into a .syx file.
+pub fn edit_buffer_to_syx(config: &Config, edit: &EditBuffer) -> Result<Vec<u8>> {
+    let data = program::store_patch_dump_ctrl(edit);
+    buffer_data_to_syx(config, data)
+}
+
+/// Convert raw program data into an edit buffer dump sysex message.
+/// Edit buffer dumps of devices other than the PODxt family carry no
+/// device id, so they are preceded by a device inquiry response message
+/// identifying the device.
+pub fn buffer_data_to_syx(config: &Config, data: Vec<u8>) -> Result<Vec<u8>> {
+    if is_xt_family(config) {
+        return MidiMessage::XtBufferDump { id: config.member as u8, data }.to_bytes();
+    }
+
+    let id = MidiMessage::UniversalDeviceInquiryResponse {
+        channel: 0x7f, family: config.family, member: config.member, ver: String::new()
+    };
+    let mut bytes = id.to_bytes()?;
+    bytes.extend(MidiMessage::ProgramEditBufferDump { ver: 0, data }.to_bytes()?);
+    Ok(bytes)
+}
+
+/// Parse the contents of a .syx file into raw program data that can be
+/// loaded into the edit buffer of a device with the given `config`.
+/// The file must contain exactly one edit buffer dump message, optionally
+/// preceded by a device inquiry response, for a device with the same
+/// patch format.
+pub fn edit_buffer_from_syx(config: &Config, bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut msgs = bytes.split_inclusive(|b| *b == 0xf7)
+        .map(|bytes| MidiMessage::from_bytes(bytes.to_vec()))
+        .collect::<Result<Vec<_>>>()
+        .context("Failed to parse sysex data")?;
+
+    let id = match msgs.first() {
+        Some(MidiMessage::UniversalDeviceInquiryResponse { family, member, .. }) => {
+            let id = (*family, *member);
+            msgs.remove(0);
+            Some(id)
+        }
+        _ => None
+    };
+    let [msg] = msgs.as_slice() else {
+        bail!("Expected a single edit buffer dump, got {} messages", msgs.len());
+    };
+
+    let data = match msg {
+        MidiMessage::ProgramEditBufferDump { ver, data } if !is_xt_family(config) => {
+            if *ver != 0 {
+                bail!("Program dump version not supported: {}", ver);
+            }
+            match id {
+                Some((family, member)) => check_patch_source(family, member, config)?,
+                None => warn!("Patch has no device id, assuming {:?}", config.name)
+            }
+            data
+        }
+        MidiMessage::XtBufferDump { id, data } if is_xt_family(config) => {
+            check_patch_source(config.family, *id as u16, config)?;
+            data
+        }
+        MidiMessage::ProgramEditBufferDump { .. } | MidiMessage::XtBufferDump { .. } => {
+            bail!("Patch is for a different device family than {:?}", config.name);
+        }
+        _ => {
+            bail!("Not an edit buffer dump");
+        }
+    };
+
+    if data.len() != config.program_size {
+        bail!("Program size mismatch: expected {}, got {}", config.program_size, data.len());
+    }
+
+    Ok(data.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{config_for_name, register_config};
+    use crate::model::{RangeControl, SwitchControl};
+    use crate::test_util;
+    use super::*;
+
+    const FAMILY: u16 = 0x0010;
+
+    fn config(name: &str, family: u16, member: u16) -> Config {
+        let mut config = test_util::config(name);
+        config.family = family;
+        config.member = member;
+        config.controls.insert("reverb_enable".into(), SwitchControl { cc: 36, addr: 1, ..Default::default() }.into());
+        config
+    }
+
+    fn register() {
+        register_config(&config("Syx POD", FAMILY, 1));
+        register_config(&config("Syx POD Pro", FAMILY, 2));
+        let mut pocket = config("Syx Pocket", FAMILY, 3);
+        pocket.controls.remove("reverb_enable");
+        register_config(&pocket);
+        register_config(&config("Syx XT", XT_FAMILY, 0x70));
+        let mut bass_xt = config("Syx Bass XT", XT_FAMILY, 0x71);
+        bass_xt.controls.insert("lo_mid".into(), RangeControl { cc: 15, addr: 2, ..Default::default() }.into());
+        register_config(&bass_xt);
+    }
+
+    fn get(name: &str) -> &'static Config {
+        register();
+        config_for_name(name).unwrap()
+    }
+
+    fn data() -> Vec<u8> {
+        (1 ..= 12).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        for name in ["Syx POD", "Syx XT"] {
+            let config = get(name);
+            let bytes = buffer_data_to_syx(config, data()).unwrap();
+            assert_eq!(edit_buffer_from_syx(config, &bytes).unwrap(), data(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn same_format_different_member() {
+        let bytes = buffer_data_to_syx(get("Syx POD"), data()).unwrap();
+        assert_eq!(edit_buffer_from_syx(get("Syx POD Pro"), &bytes).unwrap(), data());
+    }
+
+    #[test]
+    fn different_format_is_rejected() {
+        let bytes = buffer_data_to_syx(get("Syx POD"), data()).unwrap();
+        assert!(edit_buffer_from_syx(get("Syx Pocket"), &bytes).is_err());
+        let bytes = buffer_data_to_syx(get("Syx Pocket"), data()).unwrap();
+        assert!(edit_buffer_from_syx(get("Syx POD"), &bytes).is_err());
+
+        let bytes = buffer_data_to_syx(get("Syx XT"), data()).unwrap();
+        assert!(edit_buffer_from_syx(get("Syx Bass XT"), &bytes).is_err());
+    }
+
+    #[test]
+    fn different_family_is_rejected() {
+        let bytes = buffer_data_to_syx(get("Syx POD"), data()).unwrap();
+        assert!(edit_buffer_from_syx(get("Syx XT"), &bytes).is_err());
+        let bytes = buffer_data_to_syx(get("Syx XT"), data()).unwrap();
+        assert!(edit_buffer_from_syx(get("Syx POD"), &bytes).is_err());
+    }
+
+    #[test]
+    fn unknown_device_is_rejected() {
+        let mut unknown = get("Syx POD").clone();
+        unknown.member = 0x7e;
+        let bytes = buffer_data_to_syx(&unknown, data()).unwrap();
+        assert!(edit_buffer_from_syx(get("Syx POD"), &bytes).is_err());
+    }
+
+    #[test]
+    fn dump_without_id() {
+        let bytes = MidiMessage::ProgramEditBufferDump { ver: 0, data: data() }.to_bytes().unwrap();
+        assert_eq!(edit_buffer_from_syx(get("Syx Pocket"), &bytes).unwrap(), data());
+    }
+
+    #[test]
+    fn malformed_data_is_rejected() {
+        let config = get("Syx POD");
+        let bytes = buffer_data_to_syx(config, data()).unwrap();
+        assert!(edit_buffer_from_syx(config, &bytes[.. bytes.len() - 3]).is_err());
+        assert!(edit_buffer_from_syx(config, &[bytes.clone(), bytes.clone()].concat()).is_err());
+        let short = buffer_data_to_syx(config, data()[.. 6].to_vec()).unwrap();
+        assert!(edit_buffer_from_syx(config, &short).is_err());
+        assert!(edit_buffer_from_syx(config, &[]).is_err());
+    }
+}
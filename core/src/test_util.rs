@@ -0,0 +1,36 @@
+//! Fixtures shared by the unit tests of `pod-core`
+use crate::controller::{Controller, StoreOrigin};
+use crate::handler::Handler;
+use crate::model::{AbstractControl, Config, RangeControl};
+use crate::store::Store;
+
+/// A test device `name` with 4 programs of 12 bytes each, the program
+/// name in bytes 4-11 and a "drive" control at address 0. Tests add their
+/// own controls and settings on top.
+pub fn config(name: &str) -> Config {
+    let mut config = Config::empty();
+    config.name = name.into();
+    config.program_size = 12;
+    config.program_num = 4;
+    config.program_name_addr = 4;
+    config.program_name_length = 8;
+    config.controls.insert("drive".into(), RangeControl { cc: 13, addr: 0, ..Default::default() }.into());
+    config
+}
+
+/// Leak `config` for code that expects a registered, `'static` config
+pub fn leak(config: Config) -> &'static Config {
+    Box::leak(Box::new(config))
+}
+
+/// Reads single-byte controls straight from the buffer
+pub struct TestHandler;
+
+impl Handler for TestHandler {
+    fn control_value_from_buffer(&self, controller: &mut Controller, name: &str, buffer: &[u8]) {
+        let Some((addr, _)) = controller.get_config(name).and_then(|c| c.get_addr()) else {
+            return;
+        };
+        controller.set(name, buffer[addr as usize] as u16, StoreOrigin::NONE);
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::BTreeMap;
+use anyhow::*;
+use log::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::controller::*;
+use crate::diff::format_control_value;
+use crate::edit::EditBuffer;
+use crate::handler::Handler;
+use crate::model::{Config, Control};
+
+pub const TEXT_PATCH_EXT: &str = "json";
+const TEXT_PATCH_FORMAT_VERSION: u32 = 2;
+/// Format version 1 stored raw control values only, which version 2
+/// still accepts
+const TEXT_PATCH_FORMAT_VERSIONS: std::ops::RangeInclusive<u32> = 1 ..= TEXT_PATCH_FORMAT_VERSION;
+
+/// A human-readable patch, storing the values of all controls that are
+/// part of the program buffer by control name, so that it can be kept
+/// under version control and loaded into any device that shares the
+/// same control names.
+///
+/// Values are written the way the UI shows them: switches as `true` or
+/// `false`, selects by their labels, such as "4x12 Brit V30's", and other
+/// controls through their format, such as "50%". Values that their format
+/// does not map back to the same value, or that have no format, are
+/// written as numbers. Numbers are accepted for any control on load.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TextPatch {
+    pub format: u32,
+    pub device: String,
+    pub name: String,
+    pub controls: BTreeMap<String, Value>,
+}
+
+/// Parse a control value, given as a number, a switch state or the value's
+/// label as produced by `format_control_value`, checking its range
+fn value_from_json(config: &Config, handler: &dyn Handler, controller: &Controller,
+                   name: &str, control: &Control, value: &Value) -> Result<u16> {
+    let (from, to) = config.control_bounds(name)
+        .with_context(|| format!("{}: control has no value", name))?;
+    let value = match (value, control) {
+        (Value::Bool(on), Control::SwitchControl(_) | Control::MidiSwitchControl(_)) => *on as u16,
+        (Value::String(str), _) => {
+            (from ..= to)
+                .find(|v| {
+                    format_control_value(config, handler, controller, name, control, *v)
+                        .eq_ignore_ascii_case(str.trim())
+                })
+                .with_context(|| format!("{}={:?}: unknown value", name, str))?
+        }
+        (value, _) => {
+            value.as_u64()
+                .filter(|v| *v <= u16::MAX as u64)
+                .with_context(|| format!("{}={}: invalid value", name, value))? as u16
+        }
+    };
+    if value < from || value > to {
+        bail!("{}={} ({}..{})", name, value, from, to);
+    }
+    Ok(value)
+}
+
+/// Render a control value as its label, if the label is parsed back
+/// into the same value, or as a number
+fn value_to_json(config: &Config, handler: &dyn Handler, controller: &Controller,
+                 name: &str, control: &Control, value: u16) -> Value {
+    if let Control::SwitchControl(_) | Control::MidiSwitchControl(_) = control {
+        return Value::Bool(value != 0);
+    }
+    let label = format_control_value(config, handler, controller, name, control, value);
+    if label == value.to_string() {
+        return value.into();
+    }
+    let label = Value::String(label);
+    match value_from_json(config, handler, controller, name, control, &label) {
+        Result::Ok(v) if v == value => label,
+        _ => value.into()
+    }
+}
+
+impl TextPatch {
+    pub fn from_edit_buffer(config: &Config, handler: &dyn Handler, edit: &EditBuffer) -> Self {
+        let controller = edit.controller_locked();
+        let controls = controller.ordered_controls().into_iter()
+            .flat_map(|(name, control)| {
+                let value = controller.get(&name)?;
+                let value = value_to_json(config, handler, &controller, &name, &control, value);
+                Some((name, value))
+            })
+            .collect();
+
+        Self {
+            format: TEXT_PATCH_FORMAT_VERSION,
+            device: config.name.clone(),
+            name: edit.name().trim().to_string(),
+            controls
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        let mut str = serde_json::to_string_pretty(self)
+            .context("Failed to serialize patch")?;
+        str.push('\n');
+        Ok(str)
+    }
+
+    pub fn from_json(str: &str) -> Result<Self> {
+        let patch: TextPatch = serde_json::from_str(str)
+            .context("Failed to parse patch")?;
+        if !TEXT_PATCH_FORMAT_VERSIONS.contains(&patch.format) {
+            bail!("Patch format version not supported: {}", patch.format);
+        }
+        let invalid = patch.controls.iter()
+            .filter(|(_, value)| match value {
+                Value::Bool(_) | Value::String(_) => false,
+                value => !value.as_u64().is_some_and(|v| v <= u16::MAX as u64)
+            })
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>();
+        if !invalid.is_empty() {
+            bail!("Invalid patch values: {}", invalid.join(", "));
+        }
+        Ok(patch)
+    }
+
+    /// Parse the patch values of the controls in `controller`, failing if
+    /// any of them are invalid. Labels of some selects depend on the values
+    /// of other controls, so the values are parsed again with the values
+    /// of the first pass.
+    fn values(&self, config: &Config, handler: &dyn Handler, controller: &Controller) -> Result<Vec<(String, u16)>> {
+        let ordered = controller.ordered_controls();
+        let mut scratch = Controller::new(config.controls.clone());
+        let parse = |scratch: &Controller| {
+            let mut values = vec![];
+            let mut invalid = vec![];
+            for (name, control) in ordered.iter() {
+                let Some(value) = self.controls.get(name) else { continue };
+                match value_from_json(config, handler, scratch, name, control, value) {
+                    Result::Ok(v) => values.push((name.clone(), v)),
+                    Err(e) => invalid.push(e.to_string())
+                }
+            }
+            (values, invalid)
+        };
+
+        let (values, _) = parse(&scratch);
+        for (name, value) in values.iter() {
+            scratch.set(name, *value, StoreOrigin::NONE);
+        }
+        let (values, invalid) = parse(&scratch);
+        if !invalid.is_empty() {
+            bail!("Invalid patch values: {}", invalid.join(", "));
+        }
+        Ok(values)
+    }
+
+    /// Set the patch name and control values in the edit buffer through
+    /// its `Controller` with the given `origin`. Returns the names of the
+    /// patch controls that the edit buffer does not have. Fails without
+    /// changing the edit buffer if any of the values are invalid or out
+    /// of range.
+    pub fn apply(&self, config: &Config, handler: &dyn Handler, edit: &mut EditBuffer,
+                 origin: StoreOrigin) -> Result<Vec<String>> {
+        if self.device != config.name {
+            info!("Loading {:?} patch into {:?}", self.device, config.name);
+        }
+
+        let controller = edit.controller();
+        let (ordered, values) = {
+            let controller = controller.lock().unwrap();
+            (controller.ordered_controls(), self.values(config, handler, &controller)?)
+        };
+
+        edit.set_name(&self.name);
+        for (name, value) in values.iter() {
+            controller.set(name, *value, origin);
+        }
+
+        Ok(self.controls.keys()
+            .filter(|name| !ordered.iter().any(|(n, _)| n == *name))
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::model::*;
+    use crate::test_util::{self, TestHandler};
+
+    fn config() -> Config {
+        let mut config = test_util::config("Text POD");
+        config.cab_models = vec!["1x12".into(), "2x12".into(), "4x12".into()];
+        config.controls.insert("drive".into(), RangeControl {
+            cc: 13, addr: 0, config: RangeConfig::Short { from: 0, to: 63, edge: false },
+            ..Default::default()
+        }.into());
+        config.controls.insert("cab_select".into(), Select { cc: 71, addr: 1 }.into());
+        config.controls.insert("reverb_enable".into(), SwitchControl { cc: 36, addr: 2, ..Default::default() }.into());
+        config
+    }
+
+    fn edit_buffer(config: &Config, values: &[(&str, u16)]) -> EditBuffer {
+        let mut edit = EditBuffer::new(config);
+        edit.set_name("Tone");
+        for (name, value) in values {
+            edit.controller().set(name, *value, StoreOrigin::UI);
+        }
+        edit
+    }
+
+    fn values(edit: &EditBuffer) -> Vec<Option<u16>> {
+        let controller = edit.controller_locked();
+        ["drive", "cab_select", "reverb_enable"].iter()
+            .map(|name| controller.get(name))
+            .collect()
+    }
+
+    fn patch_json(drive: &str) -> String {
+        format!(r#"{{ "format": 1, "device": "Text POD", "name": "Tone",
+                      "controls": {{ "drive": {}, "cab_select": 2 }} }}"#, drive)
+    }
+
+    #[test]
+    fn round_trip() {
+        let config = config();
+        let edit = edit_buffer(&config, &[("drive", 40), ("cab_select", 2), ("reverb_enable", 1)]);
+
+        let json = TextPatch::from_edit_buffer(&config, &TestHandler, &edit).to_json().unwrap();
+        let patch = TextPatch::from_json(&json).unwrap();
+        let mut loaded = EditBuffer::new(&config);
+        let unknown = patch.apply(&config, &TestHandler, &mut loaded, StoreOrigin::UI).unwrap();
+
+        assert!(unknown.is_empty());
+        assert_eq!(loaded.name(), edit.name());
+        assert_eq!(values(&loaded), values(&edit));
+        assert_eq!(patch.controls.get("drive"), Some(&json!(40)));
+        assert_eq!(patch.controls.get("cab_select"), Some(&json!("4x12")));
+        assert_eq!(patch.controls.get("reverb_enable"), Some(&json!(true)));
+    }
+
+    #[test]
+    fn labels_are_parsed() {
+        let config = config();
+        let mut edit = edit_buffer(&config, &[("reverb_enable", 1)]);
+        let mut patch = TextPatch::from_json(&patch_json("10")).unwrap();
+        patch.controls.insert("cab_select".into(), json!(" 2X12"));
+        patch.controls.insert("reverb_enable".into(), json!(false));
+
+        patch.apply(&config, &TestHandler, &mut edit, StoreOrigin::UI).unwrap();
+        assert_eq!(values(&edit), vec![Some(10), Some(1), Some(0)]);
+
+        patch.controls.insert("cab_select".into(), json!("8x10"));
+        let err = patch.apply(&config, &TestHandler, &mut edit, StoreOrigin::UI).unwrap_err();
+        assert!(err.to_string().contains("cab_select"), "{}", err);
+        patch.controls.insert("cab_select".into(), json!(true));
+        assert!(patch.apply(&config, &TestHandler, &mut edit, StoreOrigin::UI).is_err());
+    }
+
+    #[test]
+    fn unknown_controls_are_reported() {
+        let config = config();
+        let mut patch = TextPatch::from_json(&patch_json("10")).unwrap();
+        patch.controls.insert("presence".into(), json!(5));
+        let mut edit = EditBuffer::new(&config);
+
+        let unknown = patch.apply(&config, &TestHandler, &mut edit, StoreOrigin::UI).unwrap();
+
+        assert_eq!(unknown, vec!["presence".to_string()]);
+        assert_eq!(edit.controller_locked().get("drive"), Some(10));
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        let config = config();
+        let mut edit = edit_buffer(&config, &[("drive", 1)]);
+
+        let patch = TextPatch::from_json(&patch_json("64")).unwrap();
+        let err = patch.apply(&config, &TestHandler, &mut edit, StoreOrigin::UI).unwrap_err();
+        assert!(err.to_string().contains("drive=64"), "{}", err);
+
+        let mut patch = TextPatch::from_json(&patch_json("10")).unwrap();
+        patch.controls.insert("cab_select".into(), json!(3));
+        assert!(patch.apply(&config, &TestHandler, &mut edit, StoreOrigin::UI).is_err());
+
+        patch.controls.insert("cab_select".into(), json!(0));
+        patch.controls.insert("reverb_enable".into(), json!(2));
+        assert!(patch.apply(&config, &TestHandler, &mut edit, StoreOrigin::UI).is_err());
+
+        // nothing was changed by the rejected patches
+        assert_eq!(values(&edit)[0], Some(1));
+        assert_eq!(values(&edit)[1], Some(0));
+    }
+
+    #[test]
+    fn invalid_json_is_rejected() {
+        assert!(TextPatch::from_json(&patch_json("10")).is_ok());
+        assert!(TextPatch::from_json(&patch_json("-1")).is_err());
+        assert!(TextPatch::from_json(&patch_json("70000")).is_err());
+        assert!(TextPatch::from_json(r#"{ "format": 1, "device": "Text POD" }"#).is_err());
+        assert!(TextPatch::from_json("not a patch").is_err());
+
+        let future = patch_json("10").replace(r#""format": 1"#, r#""format": 3"#);
+        let err = TextPatch::from_json(&future).unwrap_err();
+        assert!(err.to_string().contains("not supported"), "{}", err);
+
+        // labels are only checked against the device when loading
+        let config = config();
+        let patch = TextPatch::from_json(&patch_json("\"loud\"")).unwrap();
+        let mut edit = EditBuffer::new(&config);
+        assert!(patch.apply(&config, &TestHandler, &mut edit, StoreOrigin::UI).is_err());
+    }
+}
@@ -0,0 +1,422 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use log::*;
+use crate::context::Ctx;
+use crate::controller::*;
+use crate::event::*;
+use crate::model::AbstractControl;
+use crate::program;
+
+/// Changes of the same control coming in faster than this are grouped
+/// into one undo step, so that a slider drag can be undone at once
+const GROUP_TIMEOUT: Duration = Duration::from_millis(1000);
+const HISTORY_SIZE: usize = 100;
+
+const NAME_GROUP: &str = "name_change";
+
+/// A snapshot of the edit buffer controls, edit buffer name and,
+/// optionally, the data of a single program
+struct Snapshot {
+    values: HashMap<String, u16>,
+    name: String,
+    program: Option<(usize, Vec<u8>)>,
+}
+
+impl Snapshot {
+    fn new(ctx: &Ctx, program: Option<usize>) -> Self {
+        let edit = ctx.edit.lock().unwrap();
+        let name = edit.name();
+        let values = {
+            let controller = edit.controller_locked();
+            controller.controls.iter()
+                .filter(|(_, control)| control.get_addr().is_some())
+                .flat_map(|(name, _)| controller.get(name).map(|v| (name.clone(), v)))
+                .collect()
+        };
+        let program = program.and_then(|p| {
+            let dump = ctx.dump.lock().unwrap();
+            dump.data(p).map(|data| (p, data.to_vec()))
+        });
+
+        Self { values, name, program }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct UndoStep {
+    /// Control name, old value, new value
+    controls: Vec<(String, u16, u16)>,
+    /// Old and new edit buffer name
+    name: Option<(String, String)>,
+    /// Program number, old data, new data
+    programs: Vec<(usize, Vec<u8>, Vec<u8>)>,
+}
+
+impl UndoStep {
+    fn diff(a: &Snapshot, b: &Snapshot) -> Self {
+        let mut controls = b.values.iter()
+            .flat_map(|(name, new)| {
+                let old = a.values.get(name)?;
+                (old != new).then(|| (name.clone(), *old, *new))
+            })
+            .collect::<Vec<_>>();
+        controls.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let name = (a.name.trim() != b.name.trim())
+            .then(|| (a.name.clone(), b.name.clone()));
+
+        let programs = match (a.program.as_ref(), b.program.as_ref()) {
+            (Some((p, a)), Some((_, b))) if a != b => vec![(*p, a.clone(), b.clone())],
+            _ => vec![]
+        };
+
+        Self { controls, name, programs }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.controls.is_empty() && self.name.is_none() && self.programs.is_empty()
+    }
+
+    fn reversed(&self) -> Self {
+        Self {
+            controls: self.controls.iter()
+                .map(|(name, old, new)| (name.clone(), *new, *old))
+                .collect(),
+            name: self.name.as_ref().map(|(old, new)| (new.clone(), old.clone())),
+            programs: self.programs.iter()
+                .map(|(p, old, new)| (*p, new.clone(), old.clone()))
+                .collect()
+        }
+    }
+}
+
+/// Undo/redo history of the edit buffer and program dump changes made
+/// in the UI.
+///
+/// The history follows the app events: control and name changes with UI
+/// origin are recorded as they come in, while program changes, which load
+/// the program into the edit buffer, and copies and loads into another
+/// buffer, which are rerouted buffer data events, are recorded by comparing
+/// snapshots of the target buffer taken before and after the event is
+/// handled. Buffer data sent by the device on its own is not recorded.
+#[derive(Default)]
+pub struct UndoHistory {
+    undo: Vec<UndoStep>,
+    redo: Vec<UndoStep>,
+    current: Option<Snapshot>,
+    before: Option<Snapshot>,
+    /// Name of the control (or name change) and time of the last change
+    /// of the undo step on top of the history, for grouping changes
+    group: Option<(String, Instant)>,
+}
+
+impl UndoHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    fn push(&mut self, step: UndoStep) {
+        self.undo.push(step);
+        if self.undo.len() > HISTORY_SIZE {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// Record a change of a single control (or the name), grouping
+    /// it with the previous change of the same control
+    fn record(&mut self, group: &str, update: impl Fn(&mut UndoStep), step: UndoStep) {
+        let now = Instant::now();
+        let grouped = match &self.group {
+            Some((name, time)) => name == group && now.duration_since(*time) < GROUP_TIMEOUT,
+            None => false
+        };
+        match self.undo.last_mut() {
+            Some(last) if grouped && self.redo.is_empty() => update(last),
+            _ => self.push(step)
+        }
+        self.group = Some((group.to_string(), now));
+    }
+
+    /// Must be called with every app event before it is handled
+    pub fn before(&mut self, ctx: &Ctx, event: &AppEvent) {
+        if self.current.is_none() {
+            self.current = Some(Snapshot::new(ctx, None));
+        }
+        self.before = match event {
+            AppEvent::ProgramChange(_) => Some(Snapshot::new(ctx, None)),
+            AppEvent::BufferData(BufferDataEvent { buffer, .. }) => {
+                match ctx.reroute.peek(buffer) {
+                    Some(Buffer::EditBuffer) => Some(Snapshot::new(ctx, None)),
+                    Some(Buffer::Program(p)) => Some(Snapshot::new(ctx, Some(p))),
+                    _ => None
+                }
+            }
+            _ => None
+        };
+    }
+
+    /// Must be called with every app event after it is handled
+    pub fn after(&mut self, ctx: &Ctx, event: &AppEvent) {
+        match event {
+            AppEvent::ControlChange(e) if e.name == NAME_GROUP => {
+                let name = ctx.edit.lock().unwrap().name();
+                let Some(current) = self.current.as_mut() else { return };
+                let old = std::mem::replace(&mut current.name, name.clone());
+                if e.origin != StoreOrigin::UI || old.trim() == name.trim() {
+                    return;
+                }
+                let step = UndoStep { name: Some((old.clone(), name.clone())), ..Default::default() };
+                self.record(NAME_GROUP, |last| {
+                    if let Some((_, new)) = last.name.as_mut() {
+                        *new = name.clone();
+                    }
+                }, step);
+            }
+            AppEvent::ControlChange(e) => {
+                let Some(current) = self.current.as_mut() else { return };
+                let Some(old) = current.values.get_mut(&e.name) else { return };
+                let old = std::mem::replace(old, e.value);
+                if e.origin != StoreOrigin::UI || old == e.value {
+                    return;
+                }
+                let step = UndoStep { controls: vec![(e.name.clone(), old, e.value)], ..Default::default() };
+                self.record(&e.name, |last| {
+                    if let Some((_, _, new)) = last.controls.iter_mut().find(|(n, _, _)| *n == e.name) {
+                        *new = e.value;
+                    }
+                }, step);
+            }
+            AppEvent::BufferData(_) | AppEvent::ProgramChange(_) => {
+                let Some(before) = self.before.take() else {
+                    // Buffer data from the device, not an undoable step
+                    self.current = Some(Snapshot::new(ctx, None));
+                    self.group = None;
+                    return;
+                };
+                let after = Snapshot::new(ctx, before.program.as_ref().map(|(p, _)| *p));
+                let step = UndoStep::diff(&before, &after);
+                if !step.is_empty() {
+                    self.push(step);
+                }
+                self.current = Some(Snapshot { program: None, ..after });
+                self.group = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Undo the last step. Returns `false` if there is nothing to undo.
+    pub fn undo(&mut self, ctx: &Ctx) -> bool {
+        let Some(step) = self.undo.pop() else { return false };
+        let reversed = step.reversed();
+        self.apply(ctx, &reversed);
+        self.redo.push(step);
+        true
+    }
+
+    /// Redo the last undone step. Returns `false` if there is nothing to redo.
+    pub fn redo(&mut self, ctx: &Ctx) -> bool {
+        let Some(step) = self.redo.pop() else { return false };
+        self.apply(ctx, &step);
+        self.undo.push(step);
+        true
+    }
+
+    /// Apply the "new" side of the step through the edit buffer controller,
+    /// so that the UI and the device follow
+    fn apply(&mut self, ctx: &Ctx, step: &UndoStep) {
+        self.group = None;
+        let current = self.current.get_or_insert_with(|| Snapshot::new(ctx, None));
+
+        for (p, _, new) in step.programs.iter() {
+            let mut dump = ctx.dump.lock().unwrap();
+            program::load_patch_dump(&mut dump, *p, new, Origin::MIDI);
+            dump.set_modified(*p, true);
+            let e = ModifiedEvent { buffer: Buffer::Program(*p), origin: Origin::UI, modified: true };
+            ctx.app_event_tx.send_or_warn(AppEvent::Modified(e));
+        }
+
+        // Update the snapshot first, so that the control change events
+        // coming back from the controller are not recorded again
+        for (name, _, new) in step.controls.iter() {
+            current.values.insert(name.clone(), *new);
+        }
+        if let Some((_, new)) = step.name.as_ref() {
+            current.name = new.clone();
+        }
+
+        for (name, _, new) in step.controls.iter() {
+            ctx.controller.set(name, *new, StoreOrigin::UI);
+        }
+        if let Some((_, new)) = step.name.as_ref() {
+            ctx.edit.lock().unwrap().set_name(new);
+        }
+        debug!("Applied undo step: {} controls, name: {}, {} programs",
+            step.controls.len(), step.name.is_some(), step.programs.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use tokio::sync::broadcast;
+    use super::*;
+    use crate::dispatch::*;
+    use crate::dump::ProgramsDump;
+    use crate::edit::EditBuffer;
+    use crate::model::*;
+    use crate::test_util::{self, TestHandler};
+
+    fn ctx() -> (Ctx, broadcast::Receiver<AppEvent>) {
+        let mut config = test_util::config("Undo POD");
+        config.controls.insert("bass".into(), RangeControl { cc: 14, addr: 1, ..Default::default() }.into());
+        let config = test_util::leak(config);
+
+        let edit = Arc::new(Mutex::new(EditBuffer::new(config)));
+        let controller = edit.lock().unwrap().controller();
+        let ui_controls = ["midi_channel", "program", "program:prev"].iter()
+            .map(|name| (name.to_string(), VirtualSelect::default().into()))
+            .collect();
+        let ui_controller = Arc::new(Mutex::new(Controller::new(ui_controls)));
+        ui_controller.set("program", Program::ManualMode.into(), StoreOrigin::NONE);
+        let (app_event_tx, app_event_rx) = broadcast::channel(128);
+
+        let ctx = Ctx {
+            config,
+            handler: Box::new(TestHandler),
+            controller,
+            edit,
+            dump: Arc::new(Mutex::new(ProgramsDump::new(config))),
+            reroute: BufferReroute::new(),
+            ui_controller,
+            app_event_tx
+        };
+        (ctx, app_event_rx)
+    }
+
+    fn program(drive: u8, bass: u8, name: &str) -> Vec<u8> {
+        let mut data = vec![drive, bass, 0, 0];
+        data.extend(format!("{:8}", name).bytes());
+        data
+    }
+
+    fn process(history: &mut UndoHistory, ctx: &Ctx, event: AppEvent) {
+        history.before(ctx, &event);
+        dispatch(ctx, &event);
+        history.after(ctx, &event);
+    }
+
+    fn control_change(history: &mut UndoHistory, ctx: &Ctx, name: &str, value: u16, origin: StoreOrigin) {
+        let e = AppEvent::ControlChange(ControlChangeEvent { name: name.into(), value, origin });
+        history.before(ctx, &e);
+        ctx.controller.set(name, value, origin);
+        dispatch(ctx, &e);
+        history.after(ctx, &e);
+    }
+
+    fn buffer_data(buffer: Buffer, origin: Origin, data: Vec<u8>) -> AppEvent {
+        AppEvent::BufferData(BufferDataEvent { buffer, origin, request: origin, data })
+    }
+
+    #[test]
+    fn ui_control_changes_are_undone() {
+        let (ctx, _rx) = ctx();
+        let mut history = UndoHistory::new();
+
+        control_change(&mut history, &ctx, "drive", 10, StoreOrigin::UI);
+        control_change(&mut history, &ctx, "bass", 20, StoreOrigin::UI);
+
+        assert!(history.undo(&ctx));
+        assert_eq!(ctx.controller.get("bass"), Some(0));
+        assert_eq!(ctx.controller.get("drive"), Some(10));
+        assert!(history.redo(&ctx));
+        assert_eq!(ctx.controller.get("bass"), Some(20));
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn device_changes_are_not_recorded() {
+        let (ctx, _rx) = ctx();
+        let mut history = UndoHistory::new();
+
+        control_change(&mut history, &ctx, "drive", 10, StoreOrigin::MIDI);
+        process(&mut history, &ctx, buffer_data(Buffer::EditBuffer, Origin::MIDI, program(5, 6, "Dump")));
+        process(&mut history, &ctx, buffer_data(Buffer::Program(1), Origin::MIDI, program(7, 8, "Dump")));
+
+        assert_eq!(ctx.controller.get("drive"), Some(5));
+        assert!(!history.can_undo());
+
+        // the snapshot follows the device, so the next UI change is
+        // undone to the dumped value
+        control_change(&mut history, &ctx, "drive", 30, StoreOrigin::UI);
+        assert!(history.undo(&ctx));
+        assert_eq!(ctx.controller.get("drive"), Some(5));
+    }
+
+    #[test]
+    fn rerouted_load_into_edit_buffer_is_undone() {
+        let (ctx, _rx) = ctx();
+        let mut history = UndoHistory::new();
+        control_change(&mut history, &ctx, "drive", 10, StoreOrigin::UI);
+
+        ctx.reroute.set(Buffer::Program(2), Buffer::EditBuffer);
+        process(&mut history, &ctx, buffer_data(Buffer::Program(2), Origin::MIDI, program(50, 60, "Loaded")));
+
+        assert_eq!(ctx.controller.get("bass"), Some(60));
+        assert!(history.undo(&ctx));
+        assert_eq!(ctx.controller.get("drive"), Some(10));
+        assert_eq!(ctx.controller.get("bass"), Some(0));
+        assert!(history.undo(&ctx));
+        assert_eq!(ctx.controller.get("drive"), Some(0));
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn copy_into_program_is_undone() {
+        let (ctx, _rx) = ctx();
+        let mut history = UndoHistory::new();
+        let old = program(1, 2, "Old");
+        let new = program(3, 4, "New");
+        program::load_patch_dump(&mut ctx.dump.lock().unwrap(), 2, &old, Origin::MIDI);
+
+        ctx.reroute.set(Buffer::Program(0), Buffer::Program(2));
+        process(&mut history, &ctx, buffer_data(Buffer::Program(0), Origin::UI, new.clone()));
+
+        assert_eq!(ctx.dump.lock().unwrap().data(2), Some(new.as_slice()));
+        assert!(history.undo(&ctx));
+        assert_eq!(ctx.dump.lock().unwrap().data(2), Some(old.as_slice()));
+        assert!(history.redo(&ctx));
+        assert_eq!(ctx.dump.lock().unwrap().data(2), Some(new.as_slice()));
+    }
+
+    #[test]
+    fn program_load_is_undone() {
+        let (ctx, _rx) = ctx();
+        let mut history = UndoHistory::new();
+        program::load_patch_dump(&mut ctx.dump.lock().unwrap(), 1, &program(50, 60, "Other"), Origin::MIDI);
+        control_change(&mut history, &ctx, "drive", 10, StoreOrigin::UI);
+
+        ctx.set_program(Program::Program(1), Origin::UI);
+        let e = AppEvent::ProgramChange(ProgramChangeEvent { program: Program::Program(1), origin: Origin::UI });
+        process(&mut history, &ctx, e);
+
+        assert_eq!(ctx.controller.get("drive"), Some(50));
+        assert!(history.undo(&ctx));
+        assert_eq!(ctx.controller.get("drive"), Some(10));
+        assert_eq!(ctx.controller.get("bass"), Some(0));
+        assert!(history.undo(&ctx));
+        assert_eq!(ctx.controller.get("drive"), Some(0));
+        assert!(history.redo(&ctx));
+        assert!(history.redo(&ctx));
+        assert_eq!(ctx.controller.get("bass"), Some(60));
+    }
+}
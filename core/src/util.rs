@@ -103,4 +103,10 @@ pub fn is_valid_char(c: char) -> bool {
 pub fn program_id_string(i: usize) -> String {
     let (a, b) = (i / 4, i % 4);
     format!("{}{}", a + 1, char::from_u32('A' as u32 + b as u32).unwrap())
-}
\ No newline at end of file
+}
+
+/// Path of a `pod-ui` configuration file `name` in the platform-specific
+/// user configuration directory, `None` if there is no such directory
+pub fn user_config_path(name: &str) -> Option<std::path::PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("pod-ui").join(name))
+}
@@ -0,0 +1,136 @@
+//! Virtual MIDI in/out ports, so that other applications, such as a DAW,
+//! can talk to the device while pod-ui owns its MIDI ports.
+//!
+//! Messages received on the virtual input are sent to the device. Control
+//! and program changes among them are also handled as if they came from
+//! the device, so that they are reflected in the UI. All device traffic,
+//! both from the device and from pod-ui to the device, is echoed to the
+//! virtual output, except for the messages that came from the virtual
+//! input in the first place.
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use anyhow::*;
+use log::*;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use crate::event::*;
+
+/// Messages from the virtual input that are expected to show up on the
+/// app event bus and must not be echoed back
+type Injected = Arc<Mutex<VecDeque<Vec<u8>>>>;
+
+fn is_cc_or_pc(bytes: &[u8]) -> bool {
+    matches!(bytes.first(), Some(0xb0 ..= 0xcf))
+}
+
+fn inject(bytes: &[u8], injected: &Injected, app_event_tx: &broadcast::Sender<AppEvent>) {
+    let mut queue = injected.lock().unwrap();
+    queue.push_back(bytes.to_vec());
+    app_event_tx.send(AppEvent::MidiOut(bytes.to_vec())).ok();
+    if is_cc_or_pc(bytes) {
+        queue.push_back(bytes.to_vec());
+        app_event_tx.send(AppEvent::MidiIn(bytes.to_vec())).ok();
+    }
+}
+
+/// Check if a message is device traffic to be echoed to the virtual output
+fn should_echo(bytes: &[u8], injected: &Injected) -> bool {
+    let mut queue = injected.lock().unwrap();
+    match queue.iter().position(|b| b == bytes) {
+        Some(pos) => {
+            queue.remove(pos);
+            false
+        }
+        None => true
+    }
+}
+
+/// Create virtual MIDI ports named `name` and start bridging them to the
+/// device. Must be called from within a tokio runtime.
+#[cfg(unix)]
+pub fn start_virtual_port(name: &str, app_event_tx: &broadcast::Sender<AppEvent>) -> Result<()> {
+    use midir::{MidiInput, MidiOutput};
+    use midir::os::unix::{VirtualInput, VirtualOutput};
+
+    let injected: Injected = Default::default();
+
+    let midi_in = MidiInput::new("pod virtual in")?;
+    let conn_in = midi_in.create_virtual(name, {
+        let injected = injected.clone();
+        let app_event_tx = app_event_tx.clone();
+        move |_, bytes, _| {
+            trace!("virtual << {:02x?}", bytes);
+            inject(bytes, &injected, &app_event_tx);
+        }
+    }, ())
+        .map_err(|e| anyhow!("Failed to create virtual MIDI input: {}", e))?;
+
+    let midi_out = MidiOutput::new("pod virtual out")?;
+    let mut conn_out = midi_out.create_virtual(name)
+        .map_err(|e| anyhow!("Failed to create virtual MIDI output: {}", e))?;
+    info!("Virtual MIDI port {:?} created", name);
+
+    let mut app_event_rx = app_event_tx.subscribe();
+    tokio::spawn(async move {
+        // keep the virtual input open for as long as the app event bus
+        let _conn_in = conn_in;
+        loop {
+            let bytes = match app_event_rx.recv().await {
+                Result::Ok(AppEvent::MidiIn(bytes)) | Result::Ok(AppEvent::MidiOut(bytes)) => bytes,
+                Result::Ok(_) => continue,
+                Err(RecvError::Closed) => break,
+                Err(RecvError::Lagged(n)) => {
+                    warn!("Virtual MIDI port lagged: {}", n);
+                    continue;
+                }
+            };
+            if !should_echo(&bytes, &injected) {
+                continue;
+            }
+            trace!("virtual >> {:02x?}", bytes);
+            if let Err(e) = conn_out.send(&bytes) {
+                error!("Failed to send to virtual MIDI output: {}", e);
+            }
+        }
+        conn_out.close();
+    });
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn start_virtual_port(_name: &str, _app_event_tx: &broadcast::Sender<AppEvent>) -> Result<()> {
+    bail!("Virtual MIDI ports are not supported on this platform")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn injected_messages_are_not_echoed() {
+        let (tx, mut rx) = broadcast::channel(16);
+        let injected: Injected = Default::default();
+
+        let cc = vec![0xb0, 13, 64];
+        let sysex = vec![0xf0, 0x7e, 0x00, 0x06, 0x01, 0xf7];
+        inject(&cc, &injected, &tx);
+        inject(&sysex, &injected, &tx);
+
+        let mut echoed = vec![];
+        while let Result::Ok(event) = rx.try_recv() {
+            let bytes = match event {
+                AppEvent::MidiIn(bytes) | AppEvent::MidiOut(bytes) => bytes,
+                _ => continue
+            };
+            if should_echo(&bytes, &injected) {
+                echoed.push(bytes);
+            }
+        }
+        assert!(echoed.is_empty());
+        assert!(injected.lock().unwrap().is_empty());
+
+        // the same message from the device is echoed
+        assert!(should_echo(&cc, &injected));
+    }
+}
@@ -4,6 +4,7 @@ use pod_core::model::Config;
 use anyhow::Result;
 use multimap::MultiMap;
 use pod_core::edit::EditBuffer;
+use pod_core::emulator::EmulatedDevice;
 use pod_core::handler::BoxedHandler;
 
 use crate::ObjectList;
@@ -14,6 +15,24 @@ pub trait Module {
     fn config(&self) -> Box<[Config]>;
     fn init(&self, config: &'static Config) -> Box<dyn Interface>;
     fn handler(&self, config: &'static Config) -> BoxedHandler;
+
+    /// Software emulator of a device with the given `config`, if the
+    /// module provides one
+    fn emulator(&self, config: &'static Config) -> Option<Box<dyn EmulatedDevice>> {
+        let _ = config;
+        None
+    }
+
+    /// File extensions of module-specific tone files that `import` can read
+    fn import_extensions(&self) -> &[&str] { &[] }
+
+    /// Convert a module-specific tone file into edit buffer data on top of
+    /// the `base` edit buffer data. Returns the new edit buffer data and a
+    /// list of tone file entries that could not be imported.
+    fn import(&self, config: &'static Config, ext: &str, bytes: &[u8], base: &[u8]) -> Result<(Vec<u8>, Vec<String>)> {
+        let _ = (config, bytes, base);
+        anyhow::bail!("Importing {:?} files is not supported", ext)
+    }
 }
 
 pub trait Interface {
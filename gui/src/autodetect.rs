@@ -1,45 +1,94 @@
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use anyhow::*;
 use core::result::Result::Ok;
 use log::*;
-use pod_core::config::configs;
-use pod_core::midi::Channel;
+use pod_core::config::{config_for_name, config_for_str, configs};
+use pod_core::emulator::{emulator_open, is_emulator_port};
+use pod_core::midi::{channel_from_arg, Channel};
 use pod_core::midi_io::*;
 use pod_core::model::Config;
+use pod_core::session::{replay_ports, Session};
 use pod_gtk::prelude::*;
+use pod_modules::usb;
+use crate::connection::{ConnectionSettings, load_connection, save_connection, usb_name_without_address};
+use crate::mirror::last_mirror_config;
 use crate::opts::Opts;
-use crate::{set_midi_in_out, State, usb};
+use crate::{set_midi_in_out, State};
 
-fn config_for_str(config_str: &str) -> Result<&'static Config> {
-    use std::str::FromStr;
-    use regex::Regex;
+/// A device with its ports open: MIDI in and out ports, MIDI channel,
+/// whether it is connected over USB and its config
+pub type OpenedDevice = (BoxedMidiIn, BoxedMidiOut, u8, bool, &'static Config);
 
-    let n_re = Regex::new(r"\d+").unwrap();
+/// A device found when autodetecting all devices
+enum FoundDevice {
+    Midi(AutodetectResult),
+    Usb(String),
+}
 
-    let mut found = None;
-    if n_re.is_match(&config_str) {
-        let index = usize::from_str(&config_str)
-            .with_context(|| format!("Unrecognized config index {:?}", config_str))?;
-        let config = configs().get(index)
-            .with_context(|| format!("Config with index {} not found!", index))?;
-        found = Some(config);
-    } else {
-        for c in configs().iter() {
-            if c.name.eq_ignore_ascii_case(&config_str) {
-                found = Some(c);
-                break;
+impl FoundDevice {
+    async fn open(self) -> Result<OpenedDevice> {
+        match self {
+            FoundDevice::Midi(res) => {
+                Ok((res.in_port, res.out_port, res.channel, false, res.config))
+            }
+            FoundDevice::Usb(name) => {
+                let (midi_in, midi_out) = open(&name, &name, true)?;
+                let res = autodetect_with_ports(
+                    vec![midi_in], vec![midi_out], Some(Channel::num(0))
+                ).await?;
+                Ok((res.in_port, res.out_port, res.channel, true, res.config))
             }
         }
-        if found.is_none() {
-            bail!("Config with name {:?} not found!", config_str);
+    }
+}
+
+/// Detect all devices connected over MIDI or USB
+async fn autodetect_all(channel: Option<u8>) -> Result<Vec<OpenedDevice>> {
+    let mut devices = pod_core::midi_io::autodetect_all(channel).await
+        .unwrap_or_else(|e| {
+            warn!("MIDI autodetect failed: {}", e);
+            vec![]
+        })
+        .into_iter()
+        .map(FoundDevice::Midi)
+        .collect::<Vec<_>>();
+    devices.extend(
+        usb::usb_list_devices().into_iter()
+            .filter(|(_, is_ok)| *is_ok)
+            .map(|(name, _)| FoundDevice::Usb(name))
+    );
+
+    let mut opened = vec![];
+    for device in devices {
+        match device.open().await {
+            Ok(device) => opened.push(device),
+            Err(e) => warn!("Failed to open device: {}", e)
         }
     }
+    if opened.is_empty() {
+        bail!("No devices found");
+    }
 
-    Ok(found.unwrap())
+    Ok(opened)
 }
 
-pub fn detect(state: Arc<Mutex<State>>, opts: Opts, window: &gtk::Window) -> Result<()>
+/// Autodetect or open the device given on the command line and connect
+/// it to `state`. When all devices are detected, the first one found is
+/// connected to `state` and every other device is passed to `open_window`.
+pub fn detect<F>(state: Arc<Mutex<State>>, opts: Opts, window: &gtk::Window,
+                 open_window: F) -> Result<()>
+    where F: Fn(OpenedDevice) + 'static
 {
+    if let Some(path) = opts.replay.as_ref() {
+        return replay(state, &opts, path);
+    }
+
+    let all_devices = opts.all_devices;
+    if all_devices && (opts.input.is_some() || opts.output.is_some() || opts.usb.is_some()) {
+        bail!("MIDI and USB inputs cannot be set on command line together with opening all devices!")
+    }
+
     let mut ports: Option<(BoxedMidiIn, BoxedMidiOut)> = None;
     let mut midi_channel: Option<u8> = None;
     let mut config = None;
@@ -60,15 +109,11 @@ pub fn detect(state: Arc<Mutex<State>>, opts: Opts, window: &gtk::Window) -> Res
         }
         // MIDI
         (Some(i), Some(o), None, None) => {
-            let midi_in = MidiInPort::new_for_address(i)?;
-            let midi_out = MidiOutPort::new_for_address(o)?;
-            ports = Some((Box::new(midi_in), Box::new(midi_out)));
+            ports = Some(open_addr(i, o)?);
             true
         }
         (Some(i), Some(o), None, Some(m)) => {
-            let midi_in = MidiInPort::new_for_address(i)?;
-            let midi_out = MidiOutPort::new_for_address(o)?;
-            ports = Some((Box::new(midi_in), Box::new(midi_out)));
+            ports = Some(open_addr(i, o)?);
             config = Some(config_for_str(m)?);
             false
         }
@@ -88,45 +133,72 @@ pub fn detect(state: Arc<Mutex<State>>, opts: Opts, window: &gtk::Window) -> Res
     };
     let midi_channel = match opts.channel {
         None => midi_channel, // use channel default of None, unless set by the logic above
-        Some(x) if x == 0 => Some(Channel::all()),
-        Some(x) if (1u8 ..= 16).contains(&x) => Some(x - 1),
-        Some(x) => {
-            bail!("Midi channel {} out of bounds (0, 1..16)", x);
-        }
+        Some(x) => Some(channel_from_arg(x)?)
     };
     // channel, when not auto-detected
     let midi_channel_u8 = midi_channel.unwrap_or(Channel::all());
 
+    // last connection, tried before autodetect when no connection options are given
+    let saved = match (&opts.input, &opts.output, &opts.usb, &opts.model, &opts.channel) {
+        (None, None, None, None, None) if !all_devices => load_connection(),
+        _ => None
+    };
+
     let state = state.clone();
     let window = window.clone();
     let (tx, rx) = glib::MainContext::channel(glib::Priority::DEFAULT);
 
     if autodetect {
         tokio::spawn(async move {
+            if all_devices {
+                tx.send(autodetect_all(midi_channel).await).ok();
+                return;
+            }
             let res = if let Some((midi_in, midi_out)) = ports {
                 // autodetect device on provided ports
                 pod_core::midi_io::autodetect_with_ports(
                     vec![midi_in], vec![midi_out], midi_channel
                 ).await
             } else {
+                if let Some(saved) = saved {
+                    match open_saved(&saved).await {
+                        Ok(res) => {
+                            tx.send(Ok(vec![res])).ok();
+                            return;
+                        }
+                        Err(e) => {
+                            info!("Last connection failed, running autodetect: {}", e);
+                        }
+                    }
+                }
                 // autodetect device
                 run_autodetect(midi_channel).await
             };
             let res = res.and_then(|res|
-                Ok((res.in_port, res.out_port, res.channel, false, res.config)));
+                Ok(vec![(res.in_port, res.out_port, res.channel, false, res.config)]));
             tx.send(res).ok();
         });
     } else {
         // manually configured device
         let (midi_in, midi_out) = ports.unwrap();
-        tx.send(Ok((midi_in, midi_out, midi_channel_u8, false, config.unwrap()))).ok();
+        tx.send(Ok(vec![(midi_in, midi_out, midi_channel_u8, false, config.unwrap())])).ok();
     }
 
     rx.attach(None, move |autodetect| {
         match autodetect {
-            Ok((midi_in, midi_out, midi_channel, is_usb, config)) => {
-                set_midi_in_out(&mut state.lock().unwrap(),
-                                Some(midi_in), Some(midi_out), midi_channel, is_usb, Some(config));
+            Ok(mut devices) => {
+                let (midi_in, midi_out, midi_channel, is_usb, config) = devices.remove(0);
+                {
+                    let mut state = state.lock().unwrap();
+                    set_midi_in_out(&mut state,
+                                    Some(midi_in), Some(midi_out), midi_channel, is_usb, Some(config));
+                    if !all_devices {
+                        save_connection(&state);
+                    }
+                }
+                for device in devices {
+                    open_window(device);
+                }
             }
             Err(e) => {
                 error!("MIDI autodetect failed: {}", e);
@@ -149,7 +221,8 @@ pub fn detect(state: Arc<Mutex<State>>, opts: Opts, window: &gtk::Window) -> Res
 
                 let config = opts.model.as_ref()
                     .and_then(|str| config_for_str(&str).ok())
-                    .or_else(|| configs().iter().next());
+                    .or_else(last_mirror_config)
+                    .or_else(|| configs().first().copied());
                 set_midi_in_out(&mut state.lock().unwrap(),
                                 None, None, midi_channel_u8, false, config);
             }
@@ -161,12 +234,87 @@ pub fn detect(state: Arc<Mutex<State>>, opts: Opts, window: &gtk::Window) -> Res
     Ok(())
 }
 
+/// Open and test the device of the last connection
+async fn open_saved(saved: &ConnectionSettings) -> Result<OpenedDevice> {
+    let config = config_for_name(&saved.model)
+        .with_context(|| format!("Config with name {:?} not found!", saved.model))?;
+    let channel = channel_from_arg(saved.channel)?;
+
+    let (midi_in, midi_out, is_usb) = match (&saved.midi_in, &saved.midi_out, &saved.usb) {
+        (Some(in_name), Some(out_name), None) => {
+            let (midi_in, midi_out) = open(in_name, out_name, false)?;
+            (midi_in, midi_out, false)
+        }
+        (None, None, Some(usb_name)) => {
+            // the bus address changes when the device is reconnected
+            let name = usb::usb_list_devices().into_iter()
+                .filter(|(_, is_ok)| *is_ok)
+                .map(|(name, _)| name)
+                .find(|name| usb_name_without_address(name) == usb_name_without_address(usb_name))
+                .with_context(|| format!("USB device {:?} not found", usb_name))?;
+            let (midi_in, midi_out) = open(&name, &name, true)?;
+            (midi_in, midi_out, true)
+        }
+        _ => bail!("Invalid connection settings: {:?}", saved)
+    };
+    info!("Testing last connection: {:?}", saved);
+    let (midi_in, midi_out, channel) = test_with_ports(midi_in, midi_out, channel, config).await?;
+    Ok((midi_in, midi_out, channel, is_usb, config))
+}
+
+/// MIDI channel given as a command line argument: 0 for "omni" mode or 1..16
+/// Connect to a recorded MIDI session instead of a device
+fn replay(state: Arc<Mutex<State>>, opts: &Opts, path: &Path) -> Result<()> {
+    if opts.input.is_some() || opts.output.is_some() || opts.usb.is_some() {
+        bail!("MIDI or USB inputs cannot be set on command line together with a replay session!")
+    }
+
+    let session = Session::load(path)?;
+    let config = match (opts.model.as_ref(), session.device.as_ref()) {
+        (Some(m), _) | (None, Some(m)) => config_for_str(m)?,
+        (None, None) => bail!("Session {:?} does not name a device model, set it with `-m`!", path)
+    };
+    let midi_channel = session.channel.unwrap_or(Channel::all());
+    let name = path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let (midi_in, midi_out) = replay_ports(session, &name);
+    set_midi_in_out(&mut state.lock().unwrap(),
+                    Some(box_midi_in(midi_in)), Some(box_midi_out(midi_out)),
+                    midi_channel, false, Some(config));
+
+    Ok(())
+}
+
 pub async fn test(in_name: &str, out_name: &str, channel: u8, is_usb: bool, config: &Config) -> Result<(BoxedMidiIn, BoxedMidiOut, u8)> {
     let (midi_in, midi_out) = open(in_name, out_name, is_usb)?;
     test_with_ports(midi_in, midi_out, channel, config).await
 }
 
+/// Open the MIDI ports selected on the command line by address,
+/// or an emulated device selected by name
+fn open_addr(in_addr: &str, out_addr: &str) -> Result<(BoxedMidiIn, BoxedMidiOut)> {
+    if is_emulator_port(in_addr) || is_emulator_port(out_addr) {
+        return open_emulator(in_addr, out_addr);
+    }
+    let midi_in = MidiInPort::new_for_address(in_addr)?;
+    let midi_out = MidiOutPort::new_for_address(out_addr)?;
+    Ok((box_midi_in(midi_in), box_midi_out(midi_out)))
+}
+
+fn open_emulator(in_name: &str, out_name: &str) -> Result<(BoxedMidiIn, BoxedMidiOut)> {
+    if in_name != out_name {
+        bail!("Emulated device input/output names do not match");
+    }
+    let (midi_in, midi_out) = emulator_open(in_name)?;
+    Ok((box_midi_in(midi_in), box_midi_out(midi_out)))
+}
+
 pub fn open(in_name: &str, out_name: &str, is_usb: bool) -> Result<(BoxedMidiIn, BoxedMidiOut)> {
+    if is_emulator_port(in_name) || is_emulator_port(out_name) {
+        return open_emulator(in_name, out_name);
+    }
     let res = if is_usb {
         if in_name != out_name {
             bail!("USB device input/output names do not match");
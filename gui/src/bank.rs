@@ -0,0 +1,122 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use anyhow::*;
+use core::result::Result::Ok;
+use log::*;
+use pod_gtk::prelude::*;
+use gtk::{FileChooserAction, ResponseType};
+use pod_core::bank::Bank;
+use pod_core::event::*;
+use crate::{gtk, State};
+use crate::patch::{file_chooser, notify};
+
+const BANK_EXT: &str = "podbank";
+
+fn backup_bank(state: &State, path: &PathBuf) -> Result<usize> {
+    let (Some(config), Some(dump)) = (state.config, state.dump.as_ref()) else {
+        bail!("No device configured");
+    };
+
+    let version = state.detected.as_ref()
+        .map(|d| d.version.clone())
+        .unwrap_or_default();
+    let bank = Bank::from_dump(config, &dump.lock().unwrap(), &version);
+    std::fs::write(path, bank.to_string())
+        .with_context(|| format!("Failed to write {:?}", path))?;
+
+    Ok(bank.program_num())
+}
+
+fn restore_bank(state: &State, path: &PathBuf) -> Result<usize> {
+    let Some(config) = state.config else {
+        bail!("No device configured");
+    };
+
+    let str = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {:?}", path))?;
+    let bank = Bank::from_str(&str)?;
+    let data = bank.data_for(config)?.to_vec();
+
+    // Reroute the "all programs" buffer data event, so that every
+    // restored program is marked as modified, same as a program
+    // copied in the UI, ready to be stored to the device
+    state.reroute.set(Buffer::All, Buffer::All);
+    let e = BufferDataEvent {
+        buffer: Buffer::All,
+        origin: Origin::MIDI,
+        request: Origin::UI,
+        data
+    };
+    state.app_event_tx.send_or_warn(AppEvent::BufferData(e));
+
+    Ok(bank.program_num())
+}
+
+pub fn create_backup_bank_action(state: Arc<Mutex<State>>) -> gio::ActionEntry<gtk::ApplicationWindow> {
+    gio::ActionEntry::builder("backup-bank").activate(move |window: &gtk::ApplicationWindow, _, _| {
+        let dialog = file_chooser(window, "Back up all programs", FileChooserAction::Save,
+                                  "Program banks", &[BANK_EXT]);
+        {
+            let state = state.lock().unwrap();
+            let name = state.config.map(|c| c.name.clone())
+                .unwrap_or_else(|| "bank".to_string());
+            dialog.set_current_name(&format!("{}.{}", name, BANK_EXT));
+        }
+
+        dialog.connect_response({
+            let state = state.clone();
+            move |dialog, response| {
+                let path = dialog.filename();
+                dialog.close();
+                let (ResponseType::Accept, Some(mut path)) = (response, path) else { return };
+                if path.extension().is_none() {
+                    path.set_extension(BANK_EXT);
+                }
+
+                let state = state.lock().unwrap();
+                match backup_bank(&state, &path) {
+                    Ok(n) => {
+                        info!("Bank of {} programs backed up to {:?}", n, path);
+                        notify(&state.app_event_tx, format!("{} programs backed up", n));
+                    }
+                    Err(e) => {
+                        error!("Bank backup failed: {}", e);
+                        notify(&state.app_event_tx, format!("Bank backup failed: {}", e));
+                    }
+                }
+            }
+        });
+        dialog.show();
+    }).build()
+}
+
+pub fn create_restore_bank_action(state: Arc<Mutex<State>>) -> gio::ActionEntry<gtk::ApplicationWindow> {
+    gio::ActionEntry::builder("restore-bank").activate(move |window: &gtk::ApplicationWindow, _, _| {
+        let dialog = file_chooser(window, "Restore all programs", FileChooserAction::Open,
+                                  "Program banks", &[BANK_EXT]);
+
+        dialog.connect_response({
+            let state = state.clone();
+            move |dialog, response| {
+                let path = dialog.filename();
+                dialog.close();
+                let (ResponseType::Accept, Some(path)) = (response, path) else { return };
+
+                let state = state.lock().unwrap();
+                match restore_bank(&state, &path) {
+                    Ok(n) => {
+                        info!("Bank of {} programs restored from {:?}", n, path);
+                        notify(&state.app_event_tx,
+                               format!("{} programs restored, store them to the device to keep", n));
+                    }
+                    Err(e) => {
+                        error!("Bank restore failed: {}", e);
+                        notify(&state.app_event_tx, format!("Bank restore failed: {}", e));
+                    }
+                }
+            }
+        });
+        dialog.show();
+    }).build()
+}
@@ -0,0 +1,176 @@
+use std::sync::{Arc, Mutex};
+use anyhow::*;
+use core::result::Result::Ok;
+use log::*;
+use pod_gtk::prelude::*;
+use gtk::{FileChooserAction, ResponseType};
+use pod_core::diff::diff_programs;
+use pod_core::program;
+use pod_core::program_id_string;
+use pod_core::syx;
+use crate::{gtk, State};
+use crate::patch::file_chooser;
+use crate::registry::module_for_config;
+
+const TARGET_EDIT_BUFFER: &str = "edit";
+const TARGET_FILE: &str = "file";
+
+fn program_label(state: &State, program: usize) -> String {
+    let name = state.dump.as_ref()
+        .and_then(|dump| dump.lock().unwrap().name(program))
+        .unwrap_or_default();
+    format!("{} {}", program_id_string(program), name.trim())
+}
+
+fn program_data(state: &State, target: &str) -> Result<Vec<u8>> {
+    let (Some(edit), Some(dump)) = (state.edit_buffer.as_ref(), state.dump.as_ref()) else {
+        bail!("No device configured");
+    };
+    if target == TARGET_EDIT_BUFFER {
+        return Ok(program::store_patch_dump_ctrl(&edit.lock().unwrap()));
+    }
+    let program = target.parse::<usize>()
+        .with_context(|| format!("Unknown compare target {:?}", target))?;
+    let dump = dump.lock().unwrap();
+    dump.data(program).map(|data| data.to_vec())
+        .with_context(|| format!("Program {} not found", program))
+}
+
+fn file_data(state: &State, path: &std::path::Path, base: &[u8]) -> Result<Vec<u8>> {
+    let Some(config) = state.config else {
+        bail!("No device configured");
+    };
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Failed to read {:?}", path))?;
+    let ext = path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if ext == "syx" {
+        return syx::edit_buffer_from_syx(config, &bytes);
+    }
+    let module = module_for_config(config)
+        .with_context(|| format!("No module for config {:?}", config.name))?;
+    let (data, _) = module.import(config, &ext, &bytes, base)?;
+    Ok(data)
+}
+
+fn fill_diff(state: &State, store: &gtk::ListStore, a: &[u8], b: &[u8]) {
+    store.clear();
+    let Some(config) = state.config else { return };
+    let Some(module) = module_for_config(config) else { return };
+    let handler = module.handler(config);
+
+    let diffs = diff_programs(config, handler.as_ref(), a, b);
+    if diffs.is_empty() {
+        store.insert_with_values(None, &[(0, &"No differences"), (1, &""), (2, &"")]);
+    }
+    for diff in diffs {
+        store.insert_with_values(None, &[(0, &diff.name), (1, &diff.a), (2, &diff.b)]);
+    }
+}
+
+/// Show a dialog comparing `program` with the edit buffer, another
+/// program or a patch file, control by control
+pub fn show_compare_dialog(window: &gtk::Window, state: Arc<Mutex<State>>, program: usize) {
+    let (title, a) = {
+        let state = state.lock().unwrap();
+        let data = program_data(&state, &program.to_string());
+        let Ok(data) = data else {
+            error!("Compare failed: {}", data.unwrap_err());
+            return;
+        };
+        (program_label(&state, program), data)
+    };
+
+    let dialog = gtk::Dialog::with_buttons(
+        Some(&format!("Compare {}", title)), Some(window),
+        gtk::DialogFlags::DESTROY_WITH_PARENT,
+        &[("_Close", ResponseType::Close)]
+    );
+    dialog.set_default_size(480, 480);
+
+    let combo = gtk::ComboBoxText::new();
+    {
+        let state = state.lock().unwrap();
+        combo.append(Some(TARGET_EDIT_BUFFER), "Edit buffer");
+        let program_num = state.config.map(|c| c.program_num).unwrap_or_default();
+        for i in (0 .. program_num).filter(|i| *i != program) {
+            combo.append(Some(&i.to_string()), &program_label(&state, i));
+        }
+        combo.append(Some(TARGET_FILE), "File...");
+    }
+
+    let store = gtk::ListStore::new(&[glib::Type::STRING, glib::Type::STRING, glib::Type::STRING]);
+    let view = gtk::TreeView::with_model(&store);
+    for (i, column_title) in ["Control", title.as_str(), "Other"].iter().enumerate() {
+        let renderer = gtk::CellRendererText::new();
+        let column = gtk::TreeViewColumn::new();
+        column.set_title(column_title);
+        column.set_resizable(true);
+        CellLayoutExt::pack_start(&column, &renderer, true);
+        CellLayoutExt::add_attribute(&column, &renderer, "text", i as i32);
+        view.append_column(&column);
+    }
+
+    let scrolled = gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+    scrolled.set_vexpand(true);
+    scrolled.add(&view);
+
+    let content = dialog.content_area();
+    content.set_spacing(6);
+    content.pack_start(&combo, false, false, 0);
+    content.pack_start(&scrolled, true, true, 0);
+
+    combo.connect_changed({
+        let state = state.clone();
+        let store = store.clone();
+        let window = window.clone();
+        move |combo| {
+            let Some(target) = combo.active_id() else { return };
+            if target.as_str() != TARGET_FILE {
+                let state = state.lock().unwrap();
+                match program_data(&state, target.as_str()) {
+                    Ok(b) => fill_diff(&state, &store, &a, &b),
+                    Err(e) => error!("Compare failed: {}", e)
+                }
+                return;
+            }
+
+            let exts = {
+                let state = state.lock().unwrap();
+                let mut exts = vec!["syx"];
+                if let Some(module) = state.config.and_then(module_for_config) {
+                    exts.extend(module.import_extensions());
+                }
+                exts
+            };
+            let chooser = file_chooser(&window, "Compare with file", FileChooserAction::Open,
+                                       "Patch files", &exts);
+            chooser.connect_response({
+                let state = state.clone();
+                let store = store.clone();
+                let a = a.clone();
+                move |chooser, response| {
+                    let path = chooser.filename();
+                    chooser.close();
+                    let (ResponseType::Accept, Some(path)) = (response, path) else { return };
+
+                    let state = state.lock().unwrap();
+                    match file_data(&state, &path, &a) {
+                        Ok(b) => fill_diff(&state, &store, &a, &b),
+                        Err(e) => {
+                            error!("Compare failed: {}", e);
+                            store.clear();
+                            store.insert_with_values(None, &[(0, &e.to_string()), (1, &""), (2, &"")]);
+                        }
+                    }
+                }
+            });
+            chooser.show();
+        }
+    });
+    combo.set_active_id(Some(TARGET_EDIT_BUFFER));
+
+    dialog.connect_response(|dialog, _| dialog.close());
+    dialog.show_all();
+}
@@ -0,0 +1,111 @@
+use std::fs;
+use std::path::Path;
+use anyhow::*;
+use core::result::Result::Ok;
+use log::*;
+use serde::{Deserialize, Serialize};
+use pod_core::midi_io::{MidiInPort, MidiOutPort, MidiPorts};
+use pod_core::midi::channel_to_arg;
+use pod_core::user_config_path;
+use pod_modules::usb::usb_list_devices;
+use crate::platform::get_platform_hack_flags_explicit;
+use crate::State;
+
+/// Name of the last connection settings file in the user configuration directory
+pub const CONNECTION_FILE: &str = "connection.json";
+
+/// Settings of the last successful connection, tried first on the next start
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionSettings {
+    /// MIDI input port name
+    pub midi_in: Option<String>,
+    /// MIDI output port name
+    pub midi_out: Option<String>,
+    /// USB device name, which includes its bus address
+    pub usb: Option<String>,
+    /// MIDI channel as for `-c`: 0 for "omni" mode or 1..16
+    pub channel: u8,
+    /// Device model name
+    pub model: String,
+    /// Platform hack flags as for `-p`
+    pub platform: String,
+}
+
+impl ConnectionSettings {
+    pub fn load(path: &Path) -> Result<Self> {
+        let str = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {:?}", path))?;
+        serde_json::from_str(&str)
+            .with_context(|| format!("Failed to parse {:?}", path))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {:?}", dir))?;
+        }
+        let str = serde_json::to_string_pretty(self)?;
+        fs::write(path, str)
+            .with_context(|| format!("Failed to write {:?}", path))
+    }
+
+    /// Settings of the current connection, `None` if not connected to
+    /// MIDI ports or a USB device present on this system, such as when
+    /// connected to an emulated device or a replayed session
+    pub fn from_state(state: &State) -> Option<Self> {
+        state.midi_in_cancel.as_ref()?;
+        let in_name = state.midi_in_name.clone()?;
+        let out_name = state.midi_out_name.clone()?;
+        let config = state.config?;
+
+        let contains = |list: Result<Vec<String>>, name: &String| {
+            list.map(|list| list.contains(name)).unwrap_or(false)
+        };
+        let (midi_in, midi_out, usb) =
+            if contains(MidiInPort::all_ports(), &in_name) &&
+                contains(MidiOutPort::all_ports(), &out_name) {
+                (Some(in_name), Some(out_name), None)
+            } else if in_name == out_name &&
+                usb_list_devices().iter().any(|(name, ok)| *ok && *name == in_name) {
+                (None, None, Some(in_name))
+            } else {
+                return None;
+            };
+
+        Some(Self {
+            midi_in, midi_out, usb,
+            channel: channel_to_arg(state.midi_channel_num),
+            model: config.name.clone(),
+            platform: get_platform_hack_flags_explicit(),
+        })
+    }
+}
+
+/// USB device name without the bus address, which may change when the
+/// device is reconnected: "<name> <serial> [<bus>:<address>]"
+pub fn usb_name_without_address(name: &str) -> &str {
+    name.rsplit_once(" [").map(|(name, _)| name).unwrap_or(name)
+}
+
+/// Load the last connection settings, if any
+pub fn load_connection() -> Option<ConnectionSettings> {
+    let path = user_config_path(CONNECTION_FILE)?;
+    if !path.exists() {
+        return None;
+    }
+    ConnectionSettings::load(&path)
+        .map_err(|e| warn!("Failed to load connection settings: {}", e))
+        .ok()
+}
+
+/// Save the current connection as the last connection settings
+pub fn save_connection(state: &State) {
+    let (Some(settings), Some(path)) = (ConnectionSettings::from_state(state),
+                                        user_config_path(CONNECTION_FILE)) else {
+        return;
+    };
+    match settings.save(&path) {
+        Ok(_) => debug!("Connection settings saved to {:?}", path),
+        Err(e) => error!("Failed to save connection settings: {}", e)
+    }
+}
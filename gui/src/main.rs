@@ -7,8 +7,15 @@ mod widgets;
 mod autodetect;
 mod check;
 mod icon;
-mod usb;
 mod platform;
+mod patch;
+mod bank;
+mod compare;
+mod mirror;
+mod monitor;
+mod midi_learn;
+mod watchdog;
+mod connection;
 
 use std::collections::HashMap;
 use std::sync::{Arc, atomic, Mutex};
@@ -17,13 +24,14 @@ use anyhow::*;
 use clap::{Args, Command, FromArgMatches};
 use core::result::Result::Ok;
 use std::env;
+use std::cell::Cell;
 use std::rc::Rc;
 use futures_util::future::{join_all, JoinAll};
 use futures_util::FutureExt;
 use log::*;
 use maplit::*;
 use once_cell::sync::Lazy;
-use tokio::sync::{broadcast, oneshot};
+use tokio::sync::{broadcast, oneshot, Notify};
 use tokio::sync::broadcast::error::RecvError;
 use tokio::task::JoinHandle;
 use tokio::time::sleep;
@@ -33,26 +41,45 @@ use pod_core::context::Ctx;
 use pod_core::controller::*;
 use pod_core::event::*;
 use pod_core::dispatch::*;
+use pod_core::definition::register_user_definitions;
 use pod_core::dump::ProgramsDump;
+use pod_core::edit::EditBuffer;
 use pod_core::midi::MidiMessage;
 use pod_core::model::{Button, Config, Control, DeviceFlags, MidiQuirks, VirtualSelect};
+use pod_core::osc::OscServer;
+use pod_core::api::ApiServer;
+use pod_core::remote;
 use pod_core::program_id_string;
+use pod_core::session::SessionRecorder;
+use pod_core::snapshots::EditSnapshots;
+use pod_core::undo::UndoHistory;
+use pod_core::virtual_port::start_virtual_port;
 use pod_gtk::logic::LogicBuilder;
 use pod_gtk::prelude::gtk::gdk;
+use pod_modules::usb::start_usb;
+use crate::autodetect::OpenedDevice;
 use crate::check::{current_platform, new_release_check};
+use crate::connection::load_connection;
 use crate::icon::set_app_icon;
 use crate::opts::*;
 use crate::panic::*;
+use crate::patch::*;
+use crate::bank::*;
+use crate::mirror::*;
+use crate::monitor::*;
+use crate::midi_learn::*;
 use crate::registry::*;
 use crate::settings::*;
 use crate::util::{next_thread_id, SenderExt as SenderExt2};
-use crate::usb::start_usb;
+use crate::watchdog::start_midi_watchdog;
 use crate::widgets::*;
 use crate::widgets::templated::Templated;
 use crate::platform::*;
 
 const MIDI_OUT_CHANNEL_CAPACITY: usize = 512;
 const CLOSE_QUIET_DURATION_MS: u64 = 1000;
+/// Number of A/B edit buffer snapshots, must match the snapshot buttons in the UI
+const SNAPSHOT_NUM: usize = 2;
 
 
 #[derive(Clone, Debug)]
@@ -65,6 +92,8 @@ pub enum UIEvent {
     Panic,
     Modified(usize, bool),
     Name(usize, String),
+    Compare(usize),
+    MirrorChanges,
     Notification(String, Option<String>),
     Shutdown,
     Quit
@@ -86,7 +115,14 @@ pub struct State {
     pub ui_event_tx: glib::Sender<UIEvent>,
 
     pub config: Option<&'static Config>,
+    pub edit_buffer: Option<Arc<Mutex<EditBuffer>>>,
+    pub dump: Option<Arc<Mutex<ProgramsDump>>>,
     pub detected: Option<DeviceDetectedEvent>,
+    pub mirror: Option<MirrorFile>,
+    pub recorder: Option<SessionRecorder>,
+    pub reroute: BufferReroute,
+    /// Signalled by the MIDI OUT task when sending to the device fails
+    pub send_failed: Arc<Notify>,
 }
 
 static UI_CONTROLS: Lazy<HashMap<String, Control>> = Lazy::new(|| {
@@ -195,11 +231,17 @@ pub fn midi_in_out_start(state: &mut State,
 
     notify(state);
 
+    if let Some(recorder) = state.recorder.as_ref() {
+        let device = state.config.map(|c| c.name.clone()).unwrap_or_default();
+        recorder.connected(&device, midi_channel, &midi_in.name(), &midi_out.name());
+    }
+
     // midi in
     let midi_in_handle =
         tokio::spawn({
             let app_event_tx = state.app_event_tx.clone();
             let ui_event_tx = state.ui_event_tx.clone();
+            let recorder = state.recorder.clone();
             let mut in_cancel_rx = in_cancel_rx.fuse();
 
             async move {
@@ -212,6 +254,9 @@ pub fn midi_in_out_start(state: &mut State,
                         msg = midi_in.recv() => {
                             match msg {
                                 Some(bytes) => {
+                                    if let Some(recorder) = recorder.as_ref() {
+                                        recorder.record(false, &bytes);
+                                    }
                                     app_event_tx.send_or_warn(AppEvent::MidiIn(bytes));
                                     ui_event_tx.send_or_warn(UIEvent::MidiRx);
                                 }
@@ -247,6 +292,8 @@ pub fn midi_in_out_start(state: &mut State,
         tokio::spawn({
             let ui_event_tx = state.ui_event_tx.clone();
             let mut app_event_rx = state.app_event_tx.subscribe();
+            let recorder = state.recorder.clone();
+            let send_failed = state.send_failed.clone();
             let mut out_cancel_rx = out_cancel_rx.fuse();
 
             async move {
@@ -257,8 +304,14 @@ pub fn midi_in_out_start(state: &mut State,
                         msg = app_event_rx.recv() => {
                             match msg {
                                 Ok(AppEvent::MidiOut(bytes)) => {
+                                    if let Some(recorder) = recorder.as_ref() {
+                                        recorder.record(true, &bytes);
+                                    }
                                     midi_out.send(&bytes)
-                                    .unwrap_or_else(|e| error!("MIDI OUT thread tx error: {}", e));
+                                    .unwrap_or_else(|e| {
+                                        error!("MIDI OUT thread tx error: {}", e);
+                                        send_failed.notify_one();
+                                    });
                                     ui_event_tx.send_or_warn(UIEvent::MidiTx);
                                 }
                                 Err(err) => {
@@ -597,6 +650,10 @@ async fn main() -> Result<()> {
     register_module(pod_mod_pocket::module())?;
     register_module(pod_mod_xt::module())?;
     register_module(pod_mod_bassxt::module())?;
+    register_module(pod_mod_basspod::module())?;
+    register_module(pod_mod_floorpod::module())?;
+    register_user_definitions();
+    register_emulators();
 
     let help_text = generate_help_text()?;
     let cli = Command::new("Pod UI")
@@ -610,6 +667,10 @@ async fn main() -> Result<()> {
 
     if let Some(platform) = &opts.platform {
         set_platform_hack_flags(&platform)?;
+    } else if let Some(platform) = load_connection().map(|c| c.platform).filter(|p| !p.is_empty()) {
+        set_platform_hack_flags(&platform)
+            .map_err(|e| warn!("Ignoring saved platform hacks {:?}: {}", platform, e))
+            .ok();
     }
     let platform_hack_flags = get_platform_hack_flags();
     sentry::configure_scope(|scope| {
@@ -632,7 +693,7 @@ async fn main() -> Result<()> {
         if let Some(win) = app.windows().first() {
             win.present();
         } else {
-            activate(app, &title, opts.clone(), sentry_enabled);
+            activate(app, &title, opts.clone(), sentry_enabled, None);
         }
     });
     app.connect_startup(|app| {
@@ -641,7 +702,14 @@ async fn main() -> Result<()> {
         debug!("Application menu: {}", app.prefers_app_menu());
         if app.prefers_app_menu() {
             let menu = gio::Menu::new();
-            menu.append(Some("Settings"), Some("app.preferences"));
+            menu.append(Some("Undo"), Some("win.undo"));
+            menu.append(Some("Redo"), Some("win.redo"));
+            menu.append(Some("Import patch..."), Some("win.import-patch"));
+            menu.append(Some("Export patch..."), Some("win.export-patch"));
+            menu.append(Some("Back up all programs..."), Some("win.backup-bank"));
+            menu.append(Some("Restore all programs..."), Some("win.restore-bank"));
+            menu.append(Some("MIDI monitor"), Some("win.midi-monitor"));
+            menu.append(Some("Settings"), Some("win.preferences"));
             menu.append(Some("Quit"), Some("app.quit"));
             app.set_app_menu(Some(&menu));
         }
@@ -655,7 +723,24 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled: bool) {
+/// Options of an additional device window: the servers, the MIDI
+/// controller input, the virtual port and session recording stay with
+/// the first window
+fn extra_window_opts(opts: &Opts) -> Opts {
+    Opts {
+        osc: None,
+        api: None,
+        controller: None,
+        virtual_port: None,
+        record: None,
+        ..opts.clone()
+    }
+}
+
+/// Open a device window. Without a `device`, the device is autodetected
+/// or opened as given by `opts`.
+fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled: bool,
+            device: Option<OpenedDevice>) {
     let (app_event_tx, mut app_event_rx) = broadcast::channel::<AppEvent>(MIDI_OUT_CHANNEL_CAPACITY);
     let (ui_event_tx, ui_event_rx) = glib::MainContext::channel::<UIEvent>(glib::Priority::DEFAULT);
     let state = Arc::new(Mutex::new(State {
@@ -670,10 +755,41 @@ fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled:
         app_event_tx: app_event_tx.clone(),
         ui_event_tx: ui_event_tx.clone(),
         config: None,
+        edit_buffer: None,
+        dump: None,
         detected: None,
+        mirror: None,
+        recorder: opts.record.as_ref().and_then(|path| {
+            SessionRecorder::create(path)
+                .map_err(|e| error!("Failed to start MIDI session recording: {}", e))
+                .ok()
+        }),
+        reroute: BufferReroute::new(),
+        send_failed: Arc::new(Notify::new()),
     }));
 
     let ctx_share = Arc::new(Mutex::new(Option::<Ctx>::None));
+    let osc = opts.osc.as_ref().and_then(|addr| {
+        remote::parse_addr(addr)
+            .and_then(|addr| OscServer::start(addr, &app_event_tx))
+            .map_err(|e| error!("Failed to start OSC server: {}", e))
+            .ok()
+    });
+    let api = opts.api.as_ref().and_then(|addr| {
+        remote::parse_addr(addr)
+            .and_then(|addr| ApiServer::start(addr, &opts.api_origin, &app_event_tx))
+            .map_err(|e| error!("Failed to start API server: {}", e))
+            .ok()
+    });
+    let midi_learn = opts.controller.as_ref().and_then(|addr| {
+        start_midi_learn(addr, &app_event_tx)
+            .map_err(|e| error!("Failed to open MIDI controller input: {}", e))
+            .ok()
+    });
+    if let Some(name) = opts.virtual_port.as_ref() {
+        start_virtual_port(name, &app_event_tx)
+            .unwrap_or_else(|e| error!("Failed to create virtual MIDI port: {}", e));
+    }
 
     if let Some(path) = env::var("GTK_ADD_ICON_PATH").ok() {
         let icon_theme = gtk::IconTheme::default().unwrap();
@@ -692,26 +808,81 @@ fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled:
     let window: gtk::Window = app_window.clone().into();
     window.set_application(Some(app));
     window.set_title(&title);
+    // the window is closed once its device is shut down
+    let closing = Rc::new(Cell::new(false));
     window.connect_delete_event({
-        let app = app.clone();
+        let app_event_tx = app_event_tx.clone();
+        let closing = closing.clone();
         move |_, _| {
-            info!("Window delete...");
-            app.activate_action("quit", None);
+            if closing.get() {
+                return Propagation::Proceed;
+            }
+            info!("Shutting down...");
+            app_event_tx.send_or_warn(AppEvent::Shutdown);
             Propagation::Stop
         }
     });
+    window.connect_destroy({
+        let app = app.clone();
+        move |window| {
+            // quit when the last device window is closed
+            let other = app.windows().iter()
+                .any(|w| w != window && w.is::<gtk::ApplicationWindow>());
+            if !other {
+                app.quit();
+            }
+        }
+    });
 
     // connect & register signals
     let quit_action = gio::ActionEntry::builder("quit")
+        .activate(|app: &gtk::Application, _, _| {
+            info!("Closing all windows...");
+            for window in app.windows() {
+                if window.is::<gtk::ApplicationWindow>() {
+                    window.close();
+                }
+            }
+        }).build();
+    let preferences_action = create_settings_action(state.clone(), &ui);
+    let export_patch_action = create_export_patch_action(state.clone());
+    let import_patch_action = create_import_patch_action(state.clone());
+    let backup_bank_action = create_backup_bank_action(state.clone());
+    let restore_bank_action = create_restore_bank_action(state.clone());
+    let monitor_action = create_monitor_action(state.clone());
+    let undo_action = gio::ActionEntry::builder("undo")
         .activate({
             let app_event_tx = app_event_tx.clone();
             move |_, _, _| {
-                info!("Shutting down...");
-                app_event_tx.send_or_warn(AppEvent::Shutdown);
+                app_event_tx.send_or_warn(AppEvent::Undo);
             }
         }).build();
-    let preferences_action = create_settings_action(state.clone(), &ui);
-    app.add_action_entries([quit_action, preferences_action]);
+    let redo_action = gio::ActionEntry::builder("redo")
+        .activate({
+            let app_event_tx = app_event_tx.clone();
+            move |_, _, _| {
+                app_event_tx.send_or_warn(AppEvent::Redo);
+            }
+        }).build();
+    let snapshot_action = gio::ActionEntry::builder("snapshot")
+        .parameter_type(Some(&u32::static_variant_type()))
+        .state(0u32.to_variant())
+        .change_state({
+            let app_event_tx = app_event_tx.clone();
+            move |_, action, value| {
+                let Some(slot) = value.and_then(|v| v.get::<u32>()) else { return };
+                action.set_state(&slot.to_variant());
+                app_event_tx.send_or_warn(AppEvent::SelectSnapshot(slot as usize));
+            }
+        }).build();
+    app.add_action_entries([quit_action]);
+    app_window.add_action_entries([preferences_action,
+                                   export_patch_action, import_patch_action,
+                                   backup_bank_action, restore_bank_action,
+                                   undo_action, redo_action, snapshot_action,
+                                   monitor_action]);
+    app.set_accels_for_action("win.undo", &["<Primary>z"]);
+    app.set_accels_for_action("win.redo", &["<Primary><Shift>z", "<Primary>y"]);
 
     set_app_icon(&window).expect("Failed to test application icon");
     // Re-parent window content into a notification overlay
@@ -736,19 +907,41 @@ fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled:
         gtk::STYLE_PROVIDER_PRIORITY_APPLICATION
     );
 
-    // autodetect or open devices specified on command line
-    autodetect::detect(state.clone(), opts, &window)
-        .expect("Autodetect failed");
-    new_release_check(&app_event_tx);
+    match device {
+        // a device found while autodetecting all devices
+        Some((midi_in, midi_out, midi_channel, is_usb, config)) => {
+            set_midi_in_out(&mut state.lock().unwrap(),
+                            Some(midi_in), Some(midi_out), midi_channel, is_usb, Some(config));
+        }
+        None => {
+            // autodetect or open devices specified on command line,
+            // other devices found are opened in new windows
+            let open_window = {
+                let app = app.clone();
+                let title = title.clone();
+                let opts = extra_window_opts(&opts);
+                move |device| {
+                    activate(&app, &title, opts.clone(), sentry_enabled, Some(device));
+                }
+            };
+            autodetect::detect(state.clone(), opts, &window, open_window)
+                .expect("Autodetect failed");
+            new_release_check(&app_event_tx);
+        }
+    }
+    start_midi_watchdog(&state);
 
     // app event handling in a separate thread
     tokio::spawn({
         let app_event_tx = app_event_tx.clone();
         let ui_event_tx = ui_event_tx.clone();
         let ctx_share = ctx_share.clone();
+        let midi_learn = midi_learn.clone();
 
         async move {
             let mut ctx: Option<Ctx> = None;
+            let mut history = UndoHistory::new();
+            let mut snapshots = EditSnapshots::new(SNAPSHOT_NUM);
 
             loop {
                 let msg = match app_event_rx.recv().await {
@@ -766,73 +959,40 @@ fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled:
 
                 // execute device-specific handlers
                 if let Some(ctx) = &ctx {
+                    history.before(ctx, &msg);
+                    let handled = dispatch(ctx, &msg);
                     match &msg {
-                        // device inquiry
-                        AppEvent::MidiMsgIn(msg @ MidiMessage::UniversalDeviceInquiry { .. }) |
-                        AppEvent::MidiMsgIn(msg @ MidiMessage::UniversalDeviceInquiryResponse { .. }) => {
-                            midi_udi_handler(ctx, msg);
-                        }
-
-                        // control change
-                        AppEvent::MidiMsgIn(msg @ MidiMessage::ControlChange { .. }) => {
-                            midi_cc_in_handler(ctx, msg);
-                        }
-                        AppEvent::MidiMsgOut(msg @ MidiMessage::ControlChange { .. }) => {
-                            midi_cc_out_handler(ctx, msg);
-                        }
-                        AppEvent::ControlChange(cc) => {
-                            cc_handler(ctx, cc);
-                        }
-
-                        // program change
-                        AppEvent::MidiMsgIn(msg @ MidiMessage::ProgramChange { .. }) => {
-                            midi_pc_in_handler(ctx, msg);
-                        }
-                        AppEvent::MidiMsgOut(msg @ MidiMessage::ProgramChange { .. }) => {
-                            midi_pc_out_handler(ctx, msg);
-                        }
-                        AppEvent::ProgramChange(pc) => {
-                            pc_handler(ctx, pc);
-                        }
-
-                        // store & load
-                        AppEvent::Load(event) => {
-                            load_handler(ctx, event)
-                        }
-                        AppEvent::Store(event) => {
-                            store_handler(ctx, event)
-                        }
-                        AppEvent::Copy(event) => {
-                            copy_handler(ctx, event)
-                        }
-                        AppEvent::BufferData(event) => {
-                            buffer_handler(ctx, event)
-                        }
                         AppEvent::Modified(event) => {
-                            modified_handler(ctx, event);
                             ui_modified_handler(ctx, event, &ui_event_tx)
                         }
 
-                        // other
-                        AppEvent::MidiMsgIn(msg) => {
-                            midi_in_handler(ctx, msg);
+                        // undo & redo
+                        AppEvent::Undo => {
+                            if !history.undo(ctx) {
+                                notify(&app_event_tx, "Nothing to undo".to_string());
+                            }
                         }
-                        AppEvent::MidiMsgOut(msg) => {
-                            midi_out_handler(ctx, msg);
+                        AppEvent::Redo => {
+                            if !history.redo(ctx) {
+                                notify(&app_event_tx, "Nothing to redo".to_string());
+                            }
                         }
-                        AppEvent::Marker(marker) => {
-                           marker_handler(ctx, *marker);
+
+                        // A/B snapshots
+                        AppEvent::SelectSnapshot(slot) => {
+                            snapshots.select(ctx, *slot);
                         }
 
                         // silently ignoring
                         AppEvent::MidiIn(_) | AppEvent::MidiOut(_)  => { /* handled in MIDI OUT thread */ }
-                        e if is_system_app_event(e) => {}
+                        e if handled || is_system_app_event(e) => {}
 
                         // error message
                         _ => {
                             error!("Unhandled app event: {:?}", msg);
                         }
                     }
+                    history.after(ctx, &msg);
                 } else {
                     if !is_system_app_event(&msg) {
                         warn!("MIDI CC event {:?} without context", msg);
@@ -868,6 +1028,15 @@ fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled:
                             // ask it to initialize a new Ctx
                             let mut ctx_share = ctx_share.lock().unwrap();
                             *ctx_share = ctx.take();
+                            if let Some(osc) = &osc {
+                                osc.set_ctx(None);
+                            }
+                            if let Some(api) = &api {
+                                api.set_ctx(None);
+                            }
+                            if let Some(midi_learn) = &midi_learn {
+                                midi_learn.set_ctx(None);
+                            }
 
                             ui_event_tx.send_or_warn(UIEvent::NewConfig);
                         }
@@ -876,9 +1045,30 @@ fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled:
                         trace!("New context installed...");
                         let mut ctx_share = ctx_share.lock().unwrap();
                         ctx.replace(ctx_share.take().unwrap());
+                        if let Some(osc) = &osc {
+                            osc.set_ctx(ctx.as_ref());
+                        }
+                        if let Some(api) = &api {
+                            api.set_ctx(ctx.as_ref());
+                        }
+                        if let Some(midi_learn) = &midi_learn {
+                            midi_learn.set_ctx(ctx.as_ref());
+                        }
+                        history = UndoHistory::new();
+                        snapshots = EditSnapshots::new(SNAPSHOT_NUM);
                         new_device_handler(ctx.as_ref().unwrap());
                     }
                     AppEvent::Shutdown => {
+                        // the window is closed, other windows keep running
+                        if let Some(osc) = &osc {
+                            osc.set_ctx(None);
+                        }
+                        if let Some(api) = &api {
+                            api.set_ctx(None);
+                        }
+                        if let Some(midi_learn) = &midi_learn {
+                            midi_learn.set_ctx(None);
+                        }
                         ui_event_tx.send_or_warn(UIEvent::Shutdown);
                     }
 
@@ -892,8 +1082,10 @@ fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled:
                         }
                     }
                     AppEvent::MidiMsgOut(msg) => {
-                        let bytes = MidiMessage::to_bytes(&msg);
-                        app_event_tx.send_or_warn(AppEvent::MidiOut(bytes));
+                        match MidiMessage::to_bytes(&msg) {
+                            Ok(bytes) => app_event_tx.send_or_warn(AppEvent::MidiOut(bytes)),
+                            Err(e) => error!("{}", e)
+                        }
                     }
 
                     // silently ignore everything else
@@ -910,7 +1102,6 @@ fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled:
     // run UI event handling on the GTK thread
     ui_event_rx.attach(None, {
         let ui_event_tx = ui_event_tx.clone();
-        let app = app.clone();
 
         let mut program_grid: Option<ProgramGrid> = None;
         let mut shutting_down = false;
@@ -928,10 +1119,15 @@ fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled:
         move |event| {
             match event {
                 UIEvent::NewConfig => {
-                    dispatch_buffer_clear();
+                    // snapshots are reset with the new context
+                    if let Some(action) = app_window.lookup_action("snapshot")
+                        .and_then(|a| a.downcast::<gio::SimpleAction>().ok()) {
+                        action.set_state(&0u32.to_variant());
+                    }
 
-                    let state = state.lock().unwrap();
+                    let mut state = state.lock().unwrap();
                     let config = state.config.unwrap();
+                    state.reroute.clear();
 
                     info!("Initiating module for config {:?}", &config.name);
                     let interface = init_module(config)
@@ -948,11 +1144,19 @@ fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled:
                         ctx.dump.lock().unwrap().broadcast_names(None);
                     }
 
+                    save_mirror(&state);
+                    state.edit_buffer.replace(interface.edit_buffer.clone());
+                    state.dump.replace(interface.dump.clone());
+
                     let handler = interface.handler;
                     let controller = interface.edit_buffer.lock().unwrap().controller();
                     let objs = interface.objects;
                     let callbacks = interface.callbacks;
 
+                    if let Some(midi_learn) = &midi_learn {
+                        wire_midi_learn(midi_learn, config, &objs, &app_event_tx);
+                    }
+
                     {
                         // start event handlers
                         let app_event_tx = app_event_tx.clone();
@@ -966,6 +1170,7 @@ fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled:
 
                         start_names_rx(ui_event_tx.clone(), interface.dump.clone());
                     }
+                    restore_mirror(&mut state);
 
                     let ctx = Ctx {
                         config,
@@ -973,11 +1178,13 @@ fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled:
                         handler,
                         edit: interface.edit_buffer.clone(),
                         dump: interface.dump.clone(),
+                        reroute: state.reroute.clone(),
                         ui_controller: ui_controller.clone(),
                         app_event_tx: app_event_tx.clone()
                     };
                     ctx_share.lock().unwrap().replace(ctx);
                     app_event_tx.send_or_warn(AppEvent::NewCtx);
+                    ui_event_tx.send_or_warn(UIEvent::MirrorChanges);
 
                     // attach new device UI
 
@@ -1027,6 +1234,8 @@ fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled:
                     grid.attach(&g, 0, 1, 2, 18);
                     g.connect_action({
                         let app_event_tx = app_event_tx.clone();
+                        let ui_event_tx = ui_event_tx.clone();
+                        let reroute = state.reroute.clone();
                         move |action| {
                             match action {
                                 ProgramGridAction::Load { program } => {
@@ -1037,7 +1246,7 @@ fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled:
                                     app_event_tx.send_or_warn(AppEvent::Copy(e));
                                 }
                                 ProgramGridAction::LoadUnmodified { program } => {
-                                    dispatch_buffer_set(Buffer::Program(program), Buffer::EditBuffer);
+                                    reroute.set(Buffer::Program(program), Buffer::EditBuffer);
                                     let e = BufferLoadEvent { buffer: Buffer::Program(program), origin: Origin::UI };
                                     app_event_tx.send_or_warn(AppEvent::Load(e));
                                 }
@@ -1056,6 +1265,9 @@ fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled:
                                     let e = BufferStoreEvent { buffer: Buffer::Program(program), origin: Origin::UI };
                                     app_event_tx.send_or_warn(AppEvent::Store(e));
                                 }
+                                ProgramGridAction::Compare { program } => {
+                                    ui_event_tx.send_or_warn(UIEvent::Compare(program));
+                                }
                             };
                         }
                     });
@@ -1090,10 +1302,17 @@ fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled:
                     make_window_smaller(window.clone());
                 }
                 UIEvent::Modified(page, modified) => {
+                    track_offline_change(&mut state.lock().unwrap(), page, modified);
                     if let Some(grid) = &program_grid {
                         grid.set_program_modified(page, modified);
                     }
                 }
+                UIEvent::Compare(program) => {
+                    compare::show_compare_dialog(&window, state.clone(), program);
+                }
+                UIEvent::MirrorChanges => {
+                    confirm_mirror_changes(&window, state.clone());
+                }
                 UIEvent::Name(page, name) => {
                     if let Some(grid) = &program_grid {
                         grid.set_program_name(page, &name);
@@ -1140,6 +1359,7 @@ fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled:
                 }
                 UIEvent::NewMidiConnection => {
                     let state = state.lock().unwrap();
+                    ui_event_tx.send_or_warn(UIEvent::MirrorChanges);
                     let midi_in_name = state.midi_in_name.as_ref();
                     let midi_out_name = state.midi_out_name.as_ref();
                     let name = {
@@ -1199,6 +1419,7 @@ fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled:
                     shutting_down = true;
 
                     let mut state = state.lock().unwrap();
+                    save_mirror(&state);
                     let handle = midi_in_out_stop(&mut state);
                     let ui_tx = ui_event_tx.clone();
                     tokio::spawn(async move {
@@ -1240,8 +1461,10 @@ fn activate(app: &gtk::Application, title: &String, opts: Opts, sentry_enabled:
                     }
                     r.emit_by_name::<()>("group-changed", &[]);
 
-                    // quit
-                    app.quit();
+                    // close the window
+                    closing.set(true);
+                    window.close();
+                    return ControlFlow::Break;
                 }
             }
 
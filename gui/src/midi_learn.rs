@@ -0,0 +1,82 @@
+use anyhow::*;
+use log::*;
+use pod_gtk::prelude::*;
+use pod_core::event::EventSender;
+use pod_core::midi_io::{box_midi_in, MidiInPort, MidiOpen};
+use pod_core::midi_learn::{MidiLearn, MAPPINGS_FILE};
+use pod_core::model::Config;
+use pod_core::user_config_path;
+use crate::gtk;
+use crate::patch::notify;
+
+/// Open the MIDI input port of an external controller selected on the
+/// command line and start translating its messages
+pub fn start_midi_learn(addr: &str, app_event_tx: &EventSender) -> Result<MidiLearn> {
+    let midi_in = MidiInPort::new_for_address(addr)?;
+    let learn = MidiLearn::new(user_config_path(MAPPINGS_FILE));
+    learn.start(box_midi_in(midi_in), app_event_tx);
+    Ok(learn)
+}
+
+/// Add a right-click menu for MIDI learn to all widgets of the device
+/// controls in `objs`
+pub fn wire_midi_learn(learn: &MidiLearn, config: &Config, objs: &ObjectList,
+                       app_event_tx: &EventSender) {
+    for (obj, name) in objs.named_objects() {
+        // radio buttons are named "<control>:<value>"
+        let name = name.split(':').next().unwrap_or_default().to_string();
+        if !config.controls.contains_key(&name) {
+            continue;
+        }
+        let Some(widget) = obj.dynamic_cast_ref::<gtk::Widget>() else {
+            continue;
+        };
+
+        let menu = gtk::Menu::new();
+        let learn_item = gtk::MenuItem::with_label("MIDI learn");
+        let forget_item = gtk::MenuItem::with_label("Forget MIDI mapping");
+        menu.append(&learn_item);
+        menu.append(&forget_item);
+        menu.set_attach_widget(Some(widget));
+
+        learn_item.connect_activate({
+            let learn = learn.clone();
+            let name = name.clone();
+            let app_event_tx = app_event_tx.clone();
+            move |_| {
+                learn.learn(&name);
+                notify(&app_event_tx,
+                       format!("Move a control on the MIDI controller to map it to {:?}", name));
+            }
+        });
+        forget_item.connect_activate({
+            let learn = learn.clone();
+            let name = name.clone();
+            move |_| learn.forget(&name)
+        });
+
+        widget.connect_button_press_event({
+            let learn = learn.clone();
+            move |_, event| {
+                if event.button() != 3 { return Propagation::Proceed }
+
+                match learn.mapping(&name) {
+                    Some(m) => {
+                        let label = format!("Forget MIDI mapping (CC {} on channel {})",
+                                            m.cc, m.channel + 1);
+                        forget_item.set_label(&label);
+                        forget_item.set_sensitive(true);
+                    }
+                    None => {
+                        forget_item.set_label("Forget MIDI mapping");
+                        forget_item.set_sensitive(false);
+                    }
+                }
+                trace!("MIDI learn menu for {:?}", name);
+                menu.show_all();
+                menu.popup_at_pointer(Some(event));
+                Propagation::Stop
+            }
+        });
+    }
+}
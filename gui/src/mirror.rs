@@ -0,0 +1,248 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use core::result::Result::Ok;
+use log::*;
+use pod_core::config::config_for_name;
+use pod_core::event::*;
+use pod_core::mirror::*;
+use pod_core::model::Config;
+use pod_core::program_id_string;
+use pod_gtk::prelude::*;
+use crate::{State, UIEvent};
+use crate::patch::notify;
+use crate::util::SenderExt as SenderExt2;
+
+/// The mirror file that the programs dump of the current device
+/// is saved to
+pub struct MirrorFile {
+    pub config: &'static Config,
+    pub path: PathBuf,
+    /// Programs modified while no device was connected
+    pub offline: BTreeSet<usize>,
+    /// A confirmation to store the offline changes is shown
+    pub confirming: bool,
+}
+
+pub fn mirror_dir() -> PathBuf {
+    glib::user_data_dir().join("pod-ui").join("mirror")
+}
+
+/// Extract the serial number from a USB device name, as reported by
+/// `pod-usb`: "<name> <serial> [<bus>:<address>]"
+fn usb_serial(name: &str) -> Option<String> {
+    let (name, address) = name.rsplit_once(" [")?;
+    if !address.ends_with(']') {
+        return None;
+    }
+    let (_, serial) = name.rsplit_once(' ')?;
+    if serial.is_empty() || !serial.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(serial.to_string())
+}
+
+fn device_serial(state: &State) -> Option<String> {
+    state.midi_in_name.as_ref().and_then(|name| usb_serial(name))
+}
+
+/// Device config of the most recently used mirror, used to start in
+/// offline mode when no device is found
+pub fn last_mirror_config() -> Option<&'static Config> {
+    let device = last_mirror_device(&mirror_dir())?;
+    config_for_name(&device)
+}
+
+/// Restore the programs dump of the current device from its mirror file,
+/// if one exists. A device with a known serial number only gets its own
+/// mirror, otherwise the most recently saved mirror for the same device
+/// model is used.
+pub fn restore_mirror(state: &mut State) {
+    let (Some(config), Some(dump)) = (state.config, state.dump.as_ref()) else {
+        return;
+    };
+
+    let dir = mirror_dir();
+    let serial = device_serial(state);
+    let path = match serial.as_ref() {
+        Some(serial) => mirror_path(&dir, config, Some(serial)),
+        None => find_mirror(&dir, config)
+            .unwrap_or_else(|| mirror_path(&dir, config, None))
+    };
+    state.mirror = Some(MirrorFile {
+        config, path: path.clone(), offline: BTreeSet::new(), confirming: false
+    });
+    if !path.exists() {
+        return;
+    }
+
+    let res = Mirror::load(&path)
+        .and_then(|mirror| {
+            mirror.restore(config, &mut dump.lock().unwrap())?;
+            Ok(mirror)
+        });
+    match res {
+        Ok(mirror) => {
+            info!("Programs restored from {:?}", path);
+            if let Some(m) = state.mirror.as_mut() {
+                m.offline = mirror.modified.iter().copied().collect();
+            }
+            for &program in mirror.modified.iter() {
+                state.ui_event_tx.send_or_warn(UIEvent::Modified(program, true));
+            }
+            if state.midi_out_name.is_none() && !mirror.modified.is_empty() {
+                notify(&state.app_event_tx,
+                       format!("{} programs changed offline", mirror.modified.len()));
+            }
+        }
+        Err(e) => {
+            error!("Failed to restore programs from {:?}: {}", path, e);
+        }
+    }
+}
+
+/// Save the programs dump of the current device to its mirror file
+pub fn save_mirror(state: &State) {
+    let (Some(mirror), Some(dump)) = (state.mirror.as_ref(), state.dump.as_ref()) else {
+        return;
+    };
+    let version = state.detected.as_ref()
+        .map(|d| d.version.clone())
+        .unwrap_or_default();
+
+    let m = {
+        let dump = dump.lock().unwrap();
+        let mut m = Mirror::from_dump(mirror.config, &dump, &version);
+        // only the programs changed offline are pending for the device,
+        // other changes were made with the device connected
+        m.modified.retain(|p| mirror.offline.contains(p));
+        m
+    };
+    match m.save(&mirror.path) {
+        Ok(_) => info!("Programs saved to {:?}", mirror.path),
+        Err(e) => error!("Failed to save programs: {}", e)
+    }
+}
+
+/// Keep track of the programs modified while no device is connected
+pub fn track_offline_change(state: &mut State, program: usize, modified: bool) {
+    let connected = state.midi_out_name.is_some();
+    let Some(mirror) = state.mirror.as_mut() else { return };
+    if !modified {
+        mirror.offline.remove(&program);
+    } else if !connected {
+        mirror.offline.insert(program);
+    }
+}
+
+/// Programs changed offline that can be stored to the connected device
+fn offline_changes(state: &State) -> Vec<usize> {
+    let (Some(config), Some(mirror), Some(dump)) =
+        (state.config, state.mirror.as_ref(), state.dump.as_ref()) else {
+        return vec![];
+    };
+    if state.midi_out_name.is_none() || mirror.config != config {
+        return vec![];
+    }
+    if let Some(serial) = device_serial(state) {
+        if mirror_path(&mirror_dir(), config, Some(&serial)) != mirror.path {
+            warn!("Device serial {} does not match mirror {:?}, not storing programs",
+                serial, mirror.path);
+            return vec![];
+        }
+    }
+
+    let dump = dump.lock().unwrap();
+    mirror.offline.iter()
+        .copied()
+        .filter(|p| dump.modified(*p))
+        .collect()
+}
+
+fn program_label(state: &State, program: usize) -> String {
+    let name = state.dump.as_ref()
+        .and_then(|dump| dump.lock().unwrap().name(program))
+        .unwrap_or_default();
+    format!("{} {}", program_id_string(program), name.trim())
+}
+
+/// Ask whether the programs changed offline should be stored to the
+/// connected device, listing the programs that will be overwritten.
+/// Keeping the programs on the device discards the offline changes.
+pub fn confirm_mirror_changes(window: &gtk::Window, state: Arc<Mutex<State>>) {
+    let labels = {
+        let mut state = state.lock().unwrap();
+        let modified = offline_changes(&state);
+        let labels = modified.iter()
+            .map(|p| program_label(&state, *p))
+            .collect::<Vec<_>>();
+        let Some(mirror) = state.mirror.as_mut() else { return };
+        if modified.is_empty() || mirror.confirming {
+            return;
+        }
+        mirror.confirming = true;
+        labels
+    };
+
+    let dialog = gtk::MessageDialog::new(
+        Some(window),
+        gtk::DialogFlags::DESTROY_WITH_PARENT | gtk::DialogFlags::MODAL,
+        gtk::MessageType::Question,
+        gtk::ButtonsType::None,
+        &format!("Store {} programs changed offline to the device?", labels.len())
+    );
+    dialog.set_secondary_text(Some(&format!(
+        "The following programs on the device will be overwritten:\n\n{}\n\n\
+         Keeping the programs on the device discards the offline changes.",
+        labels.join("\n")
+    )));
+    dialog.add_buttons(&[("_Keep on device", gtk::ResponseType::Cancel),
+                         ("_Store", gtk::ResponseType::Accept)]);
+    dialog.set_default_response(gtk::ResponseType::Cancel);
+    dialog.connect_response(move |dialog, response| {
+        dialog.close();
+        let mut state = state.lock().unwrap();
+        if let Some(mirror) = state.mirror.as_mut() {
+            mirror.confirming = false;
+        }
+        match response {
+            gtk::ResponseType::Accept => push_mirror_changes(&state),
+            gtk::ResponseType::Cancel => discard_mirror_changes(&state),
+            // dialog closed, ask again on the next connection
+            _ => {}
+        }
+    });
+    dialog.show();
+}
+
+/// Replace the programs that were modified while the device was not
+/// connected with the programs loaded from the device
+fn discard_mirror_changes(state: &State) {
+    let modified = offline_changes(state);
+    if modified.is_empty() {
+        return;
+    }
+
+    info!("Loading {} programs changed offline from the device", modified.len());
+    for &program in modified.iter() {
+        let e = BufferLoadEvent { buffer: Buffer::Program(program), origin: Origin::UI };
+        state.app_event_tx.send_or_warn(AppEvent::Load(e));
+    }
+}
+
+/// Store the programs that were modified while the device was not
+/// connected to the device
+fn push_mirror_changes(state: &State) {
+    let modified = offline_changes(state);
+    if modified.is_empty() {
+        return;
+    }
+
+    info!("Storing {} modified programs to the device", modified.len());
+    for &program in modified.iter() {
+        let e = BufferStoreEvent { buffer: Buffer::Program(program), origin: Origin::UI };
+        state.app_event_tx.send_or_warn(AppEvent::Store(e));
+    }
+    notify(&state.app_event_tx,
+           format!("{} programs changed offline stored to the device", modified.len()));
+}
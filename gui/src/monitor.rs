@@ -0,0 +1,340 @@
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use anyhow::*;
+use core::result::Result::Ok;
+use log::*;
+use pod_gtk::prelude::*;
+use gtk::{FileChooserAction, ResponseType};
+use tokio::sync::broadcast::error::RecvError;
+use pod_core::event::*;
+use pod_core::midi::MidiMessage;
+use pod_core::model::Config;
+use crate::{gtk, State};
+use crate::patch::{file_chooser, notify};
+
+const LOG_EXT: &str = "log";
+/// Oldest entries are dropped when the monitor holds more than this
+const MAX_ENTRIES: usize = 5000;
+
+const COL_TIME: u32 = 0;
+const COL_DIR: u32 = 1;
+const COL_HEX: u32 = 2;
+const COL_DECODED: u32 = 3;
+const COL_KIND: u32 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    SysEx = 0,
+    ControlChange = 1,
+    ProgramChange = 2,
+    Other = 3,
+}
+
+impl Kind {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        match bytes.first() {
+            Some(0xf0) => Kind::SysEx,
+            Some(0xb0 ..= 0xbf) => Kind::ControlChange,
+            Some(0xc0 ..= 0xcf) => Kind::ProgramChange,
+            _ => Kind::Other
+        }
+    }
+
+    fn from_u32(v: u32) -> Self {
+        match v {
+            0 => Kind::SysEx,
+            1 => Kind::ControlChange,
+            2 => Kind::ProgramChange,
+            _ => Kind::Other
+        }
+    }
+}
+
+struct Entry {
+    time: f64,
+    is_out: bool,
+    bytes: Vec<u8>,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ")
+}
+
+/// Describe a decoded MIDI message. Control changes are resolved to the
+/// control name of the current device config, dumps only show their size.
+fn describe(config: Option<&Config>, msg: &MidiMessage) -> String {
+    match msg {
+        MidiMessage::ControlChange { channel, control, value } => {
+            let name = config
+                .and_then(|c| c.cc_to_control(*control))
+                .map(|(name, _)| name.as_str())
+                .unwrap_or("?");
+            format!("CC {} ({}) = {}, channel {}", control, name, value, channel + 1)
+        }
+        MidiMessage::ProgramChange { channel, program } => {
+            format!("PC {}, channel {}", program, channel + 1)
+        }
+        MidiMessage::ProgramPatchDump { patch, ver, data } => {
+            format!("ProgramPatchDump {{ patch: {}, ver: {}, data: {} bytes }}", patch, ver, data.len())
+        }
+        MidiMessage::ProgramEditBufferDump { ver, data } => {
+            format!("ProgramEditBufferDump {{ ver: {}, data: {} bytes }}", ver, data.len())
+        }
+        MidiMessage::AllProgramsDump { ver, data } => {
+            format!("AllProgramsDump {{ ver: {}, data: {} bytes }}", ver, data.len())
+        }
+        MidiMessage::XtBufferDump { id, data } => {
+            format!("XtBufferDump {{ id: {}, data: {} bytes }}", id, data.len())
+        }
+        MidiMessage::XtPatchDump { patch, id, data } => {
+            format!("XtPatchDump {{ patch: {}, id: {}, data: {} bytes }}", patch, id, data.len())
+        }
+        msg => format!("{:?}", msg)
+    }
+}
+
+fn decode(config: Option<&Config>, bytes: &[u8]) -> String {
+    match MidiMessage::from_bytes(bytes.to_vec()) {
+        Ok(msg) => describe(config, &msg),
+        Err(e) => format!("Unknown: {}", e)
+    }
+}
+
+fn save_log(store: &gtk::TreeModel, path: &Path) -> Result<usize> {
+    let mut s = String::new();
+    let mut n = 0;
+    if let Some(iter) = store.iter_first() {
+        loop {
+            let time = store.value(&iter, COL_TIME as i32).get::<String>()?;
+            let dir = store.value(&iter, COL_DIR as i32).get::<String>()?;
+            let hex = store.value(&iter, COL_HEX as i32).get::<String>()?;
+            let decoded = store.value(&iter, COL_DECODED as i32).get::<String>()?;
+            writeln!(s, "{}\t{}\t{}\t{}", time, dir, hex, decoded)?;
+            n += 1;
+            if !store.iter_next(&iter) { break; }
+        }
+    }
+    std::fs::write(path, s)
+        .with_context(|| format!("Failed to write {:?}", path))?;
+
+    Ok(n)
+}
+
+fn filter_button(label: &str, container: &gtk::Box) -> gtk::CheckButton {
+    let button = gtk::CheckButton::with_label(label);
+    button.set_active(true);
+    container.pack_start(&button, false, false, 0);
+    button
+}
+
+fn show_monitor(parent: &gtk::ApplicationWindow, state: Arc<Mutex<State>>) -> gtk::Window {
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    window.set_title("MIDI monitor");
+    window.set_default_size(720, 480);
+    window.set_application(parent.application().as_ref());
+
+    let store = gtk::ListStore::new(&[
+        glib::Type::STRING, glib::Type::STRING, glib::Type::STRING, glib::Type::STRING,
+        glib::Type::U32
+    ]);
+    let filter = gtk::TreeModelFilter::new(&store, None);
+    let view = gtk::TreeView::with_model(&filter);
+    for (column_title, i) in [("Time", COL_TIME), ("", COL_DIR), ("Data", COL_HEX), ("Message", COL_DECODED)] {
+        let renderer = gtk::CellRendererText::new();
+        if i == COL_HEX {
+            renderer.set_property("ellipsize", gtk::pango::EllipsizeMode::End);
+            renderer.set_property("width-chars", 32);
+        }
+        let column = gtk::TreeViewColumn::new();
+        column.set_title(column_title);
+        column.set_resizable(true);
+        column.set_expand(i == COL_HEX || i == COL_DECODED);
+        CellLayoutExt::pack_start(&column, &renderer, true);
+        CellLayoutExt::add_attribute(&column, &renderer, "text", i as i32);
+        view.append_column(&column);
+    }
+
+    let scrolled = gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+    scrolled.set_vexpand(true);
+    scrolled.add(&view);
+
+    let toolbar = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    toolbar.set_border_width(6);
+    let show_in = filter_button("In", &toolbar);
+    let show_out = filter_button("Out", &toolbar);
+    toolbar.pack_start(&gtk::Separator::new(gtk::Orientation::Vertical), false, false, 0);
+    let show_sysex = filter_button("SysEx", &toolbar);
+    let show_cc = filter_button("CC", &toolbar);
+    let show_pc = filter_button("PC", &toolbar);
+    let show_other = filter_button("Other", &toolbar);
+    let save_button = gtk::Button::with_label("Save log...");
+    toolbar.pack_end(&save_button, false, false, 0);
+    let clear_button = gtk::Button::with_label("Clear");
+    toolbar.pack_end(&clear_button, false, false, 0);
+
+    let content = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    content.pack_start(&toolbar, false, false, 0);
+    content.pack_start(&scrolled, true, true, 0);
+    window.add(&content);
+
+    filter.set_visible_func({
+        let buttons = [show_in.clone(), show_out.clone(),
+            show_sysex.clone(), show_cc.clone(), show_pc.clone(), show_other.clone()];
+        move |model, iter| {
+            let [show_in, show_out, show_sysex, show_cc, show_pc, show_other] = &buttons;
+            let dir = model.value(iter, COL_DIR as i32).get::<String>().unwrap_or_default();
+            let kind = model.value(iter, COL_KIND as i32).get::<u32>().unwrap_or_default();
+            let dir_ok = if dir == "OUT" { show_out.is_active() } else { show_in.is_active() };
+            let kind_ok = match Kind::from_u32(kind) {
+                Kind::SysEx => show_sysex.is_active(),
+                Kind::ControlChange => show_cc.is_active(),
+                Kind::ProgramChange => show_pc.is_active(),
+                Kind::Other => show_other.is_active()
+            };
+            dir_ok && kind_ok
+        }
+    });
+    for button in [&show_in, &show_out, &show_sysex, &show_cc, &show_pc, &show_other] {
+        button.connect_toggled({
+            let filter = filter.clone();
+            move |_| filter.refilter()
+        });
+    }
+
+    clear_button.connect_clicked({
+        let store = store.clone();
+        move |_| store.clear()
+    });
+
+    save_button.connect_clicked({
+        let window = window.clone();
+        let state = state.clone();
+        let filter = filter.clone();
+        move |_| {
+            let dialog = file_chooser(&window, "Save MIDI log", FileChooserAction::Save,
+                                      "Log files", &[LOG_EXT]);
+            dialog.set_current_name(&format!("midi.{}", LOG_EXT));
+            dialog.connect_response({
+                let state = state.clone();
+                let filter = filter.clone();
+                move |dialog, response| {
+                    let path = dialog.filename();
+                    dialog.close();
+                    let (ResponseType::Accept, Some(mut path)) = (response, path) else { return };
+                    if path.extension().is_none() {
+                        path.set_extension(LOG_EXT);
+                    }
+
+                    let state = state.lock().unwrap();
+                    match save_log(filter.upcast_ref(), &path) {
+                        Ok(n) => {
+                            info!("MIDI log of {} messages saved to {:?}", n, path);
+                            notify(&state.app_event_tx, format!("{} MIDI messages saved", n));
+                        }
+                        Err(e) => {
+                            error!("Saving MIDI log failed: {}", e);
+                            notify(&state.app_event_tx, format!("Saving MIDI log failed: {}", e));
+                        }
+                    }
+                }
+            });
+            dialog.show();
+        }
+    });
+
+    // Collect MIDI traffic from the app event bus. The task stops when
+    // the receiving end is removed together with the window.
+    let (tx, rx) = glib::MainContext::channel::<Entry>(glib::Priority::DEFAULT_IDLE);
+    let mut app_event_rx = state.lock().unwrap().app_event_tx.subscribe();
+    tokio::spawn(async move {
+        let start = Instant::now();
+        loop {
+            let (is_out, bytes) = match app_event_rx.recv().await {
+                Ok(AppEvent::MidiIn(bytes)) => (false, bytes),
+                Ok(AppEvent::MidiOut(bytes)) => (true, bytes),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(n)) => {
+                    warn!("MIDI monitor lagged: {}", n);
+                    continue;
+                }
+                Err(RecvError::Closed) => break
+            };
+            let entry = Entry { time: start.elapsed().as_secs_f64(), is_out, bytes };
+            if tx.send(entry).is_err() {
+                break;
+            }
+        }
+    });
+
+    let source = rx.attach(None, {
+        let state = state.clone();
+        let store = store.clone();
+        let view = view.clone();
+        move |entry| {
+            let config = state.lock().unwrap().config;
+            let time = format!("{:.3}", entry.time);
+            let dir = if entry.is_out { "OUT" } else { "IN" };
+            let kind = Kind::from_bytes(&entry.bytes) as u32;
+            let hex = to_hex(&entry.bytes);
+            let decoded = decode(config, &entry.bytes);
+            store.insert_with_values(None, &[
+                (COL_TIME, &time), (COL_DIR, &dir), (COL_HEX, &hex),
+                (COL_DECODED, &decoded), (COL_KIND, &kind)
+            ]);
+            while store.iter_n_children(None) as usize > MAX_ENTRIES {
+                let Some(iter) = store.iter_first() else { break };
+                store.remove(&iter);
+            }
+
+            // follow the traffic, unless the view is scrolled up
+            let adj = view.vadjustment();
+            if let Some(adj) = adj {
+                if adj.value() + adj.page_size() >= adj.upper() - 1.0 {
+                    let n = view.model().map(|m| m.iter_n_children(None)).unwrap_or(0);
+                    if n > 0 {
+                        let path = gtk::TreePath::from_indicesv(&[n - 1]);
+                        view.scroll_to_cell(Some(&path), None::<&gtk::TreeViewColumn>, false, 0.0, 0.0);
+                    }
+                }
+            }
+
+            ControlFlow::Continue
+        }
+    });
+    let source = Rc::new(RefCell::new(Some(source)));
+    window.connect_destroy(move |_| {
+        if let Some(source) = source.borrow_mut().take() {
+            source.remove();
+        }
+    });
+
+    window.show_all();
+    window
+}
+
+pub fn create_monitor_action(state: Arc<Mutex<State>>) -> gio::ActionEntry<gtk::ApplicationWindow> {
+    let window = Rc::new(RefCell::new(Option::<gtk::Window>::None));
+
+    gio::ActionEntry::builder("midi-monitor").activate(move |parent: &gtk::ApplicationWindow, _, _| {
+        if let Some(window) = window.borrow().as_ref() {
+            window.present();
+            return;
+        }
+
+        let w = show_monitor(parent, state.clone());
+        w.connect_destroy({
+            let window = window.clone();
+            move |_| { window.borrow_mut().take(); }
+        });
+        // the monitor is closed together with its device window
+        parent.connect_destroy({
+            let w = w.clone();
+            move |_| w.close()
+        });
+        window.borrow_mut().replace(w);
+    }).build()
+}
@@ -1,17 +1,21 @@
 use clap::Parser;
 use anyhow::Result;
 use std::fmt::Write;
+use std::path::PathBuf;
 use pod_core::config::configs;
+use pod_core::emulator::emulator_ports;
 use pod_core::midi_io::{MidiInPort, MidiOutPort, MidiPorts};
+use pod_modules::usb::*;
 use crate::get_platform_hack_flags;
-use crate::usb::*;
 
 #[derive(Parser, Clone)]
 pub struct Opts {
     #[clap(short, long)]
     /// Select the MIDI port to be connected as input. <INPUT> must be an
     /// integer index of a MIDI input port present on this system. On Linux,
-    /// this can also be an ALSA <client>:<port> pair, such as "20:0".
+    /// this can also be an ALSA <client>:<port> pair, such as "20:0", the
+    /// full name of a MIDI port, or the name of an emulated device, which
+    /// must then be given to `-o` as well.
     /// To select ports manually, both `-i` and `-o` options must be provided.
     /// If both `-i` and `-o` are provided, port autodetect will be skipped.
     /// If only `-i` is provided, an error will be reported.
@@ -20,7 +24,9 @@ pub struct Opts {
     #[clap(short, long)]
     /// Select the MIDI port to be connected as output. <OUTPUT> must be an
     /// integer index of a MIDI output port present on this system. On Linux,
-    /// this can also be an ALSA <client>:<port> pair, such as "20:0".
+    /// this can also be an ALSA <client>:<port> pair, such as "20:0", the
+    /// full name of a MIDI port, or the name of an emulated device, which
+    /// must then be given to `-i` as well.
     /// If both `-i` and `-o` are provided, port autodetect will be skipped.
     /// If only `-o` is provided, an error will be reported.
     pub output: Option<String>,
@@ -35,7 +41,8 @@ pub struct Opts {
 
     /// Select the USB device to be connected as MIDI input/output. <USB> must be
     /// an integer index of a recognized USB device present on this system. This
-    /// can also be an <bus>:<address> pair, such as "5:8".
+    /// can also be an <bus>:<address> pair, such as "5:8", or the full name
+    /// of the device.
     /// When `-u` is provided, neither `-i` nor `-o` can be provided, or an error
     /// will be reported.
     #[cfg(feature = "usb")]
@@ -53,12 +60,65 @@ pub struct Opts {
     /// omitted, the device model on specified ports will be detected.
     pub model: Option<String>,
 
+    #[clap(long, value_name = "FILE")]
+    /// Record all MIDI traffic to and from the device into a session
+    /// file <FILE>, which can later be replayed with `--replay`.
+    pub record: Option<PathBuf>,
+
+    #[clap(long, value_name = "FILE")]
+    /// Replay a MIDI session recorded with `--record` instead of
+    /// connecting to a device. The device model is taken from the
+    /// session file, unless set with `-m`. When `--replay` is provided,
+    /// neither `-i`, `-o` nor `-u` can be provided.
+    pub replay: Option<PathBuf>,
+
+    #[clap(long, value_name = "[ADDR:]PORT")]
+    /// Start an OSC server on UDP port <PORT> for remote control of
+    /// device parameters. The server listens on the loopback interface,
+    /// unless an address <ADDR> is given, such as "0.0.0.0:9000" to listen
+    /// on all interfaces.
+    pub osc: Option<String>,
+
+    #[clap(long, value_name = "[ADDR:]PORT")]
+    /// Start a JSON API server over WebSocket and HTTP on TCP port <PORT>
+    /// for remote panels and third-party editors. The server listens on the
+    /// loopback interface, unless an address <ADDR> is given.
+    pub api: Option<String>,
+
+    #[clap(long, value_name = "ORIGIN")]
+    /// Allow web pages from <ORIGIN>, such as "http://localhost:8000",
+    /// to connect to the API server. Can be given multiple times.
+    /// Connections from web pages of any other origin are rejected.
+    pub api_origin: Vec<String>,
+
+    #[clap(long, value_name = "INPUT")]
+    /// Select the MIDI port of an external MIDI controller to be connected
+    /// as a second input. <INPUT> is given as for `-i`. Right-click any
+    /// control and select "MIDI learn" to map a knob or a pedal of the
+    /// controller to it.
+    pub controller: Option<String>,
+
+    #[clap(long, value_name = "NAME", min_values = 0, default_missing_value = "pod-ui")]
+    /// Create a virtual MIDI in/out port named <NAME> ("pod-ui" if not
+    /// given), through which other applications, such as a DAW, can talk
+    /// to the device while pod-ui is connected to it. Not available on
+    /// Windows.
+    pub virtual_port: Option<String>,
+
     #[clap(short, long)]
     /// Run a stand-alone instance of the pod-ui GTK application
     /// instead of triggering any events on an already-running
     /// pod-ui application.
     pub standalone: bool,
 
+    #[clap(long)]
+    /// Detect all devices connected over MIDI or USB and open each of
+    /// them in its own window. Cannot be used together with `-i`, `-o`
+    /// or `-u`. The OSC and JSON API servers, the MIDI controller input,
+    /// the virtual port and MIDI session recording apply to the first
+    /// window only.
+    pub all_devices: bool,
+
     #[clap(short, long, value_name = "FLAGS")]
     /// Set active platform hack flags. <FLAGS> must be a comma-separated
     /// list of platform hack names. To enable a specific hack, it should
@@ -99,5 +159,11 @@ pub fn generate_help_text() -> Result<String> {
         writeln!(s, "")?;
     }
 
+    writeln!(s, "Emulated devices (-i/-o):")?;
+    for n in emulator_ports() {
+        writeln!(s, "{}{}", tab, n)?;
+    }
+    writeln!(s, "")?;
+
     Ok(s)
 }
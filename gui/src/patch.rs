@@ -0,0 +1,215 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use anyhow::*;
+use core::result::Result::Ok;
+use log::*;
+use pod_gtk::prelude::*;
+use gtk::{FileChooserAction, ResponseType};
+use pod_core::event::*;
+use pod_core::store::Origin as StoreOrigin;
+use pod_core::syx;
+use pod_core::text_patch::{TextPatch, TEXT_PATCH_EXT};
+use crate::{gtk, State};
+use crate::registry::module_for_config;
+
+const SYX_EXT: &str = "syx";
+
+pub fn file_chooser(window: &impl IsA<gtk::Window>, title: &str, action: FileChooserAction,
+                    filter_name: &str, exts: &[&str]) -> gtk::FileChooserDialog {
+    let accept = match action {
+        FileChooserAction::Save => "_Save",
+        _ => "_Open"
+    };
+    let dialog = gtk::FileChooserDialog::with_buttons(
+        Some(title), Some(window), action,
+        &[("_Cancel", ResponseType::Cancel), (accept, ResponseType::Accept)]
+    );
+    dialog.set_do_overwrite_confirmation(true);
+
+    let filter = gtk::FileFilter::new();
+    let patterns = exts.iter().map(|ext| format!("*.{}", ext)).collect::<Vec<_>>();
+    filter.set_name(Some(&format!("{} ({})", filter_name, patterns.join(", "))));
+    for ext in exts {
+        filter.add_pattern(&format!("*.{}", ext));
+        filter.add_pattern(&format!("*.{}", ext.to_uppercase()));
+    }
+    dialog.add_filter(filter);
+
+    dialog
+}
+
+pub fn notify(app_event_tx: &EventSender, msg: String) {
+    app_event_tx.send_or_warn(AppEvent::Notification(NotificationEvent::msg(msg)));
+}
+
+fn file_ext(path: &PathBuf) -> String {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+fn export_patch(state: &State, path: &PathBuf) -> Result<String> {
+    let (Some(config), Some(edit)) = (state.config, state.edit_buffer.as_ref()) else {
+        bail!("No device configured");
+    };
+
+    let edit = edit.lock().unwrap();
+    let bytes = if file_ext(path) == TEXT_PATCH_EXT {
+        let Some(module) = module_for_config(config) else {
+            bail!("No module for config {:?}", config.name);
+        };
+        let handler = module.handler(config);
+        TextPatch::from_edit_buffer(config, handler.as_ref(), &edit).to_json()?.into_bytes()
+    } else {
+        syx::edit_buffer_to_syx(config, &edit)?
+    };
+    std::fs::write(path, bytes)
+        .with_context(|| format!("Failed to write {:?}", path))?;
+
+    Ok(edit.name())
+}
+
+fn import_patch(state: &State, path: &PathBuf) -> Result<()> {
+    let Some(config) = state.config else {
+        bail!("No device configured");
+    };
+
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Failed to read {:?}", path))?;
+    let ext = file_ext(path);
+    if ext == TEXT_PATCH_EXT {
+        // text patch is loaded through the controller, which will
+        // update the edit buffer and send the values to the device
+        let Some(edit) = state.edit_buffer.as_ref() else {
+            bail!("No device configured");
+        };
+        let str = String::from_utf8(bytes)
+            .context("Failed to read patch as text")?;
+        let Some(module) = module_for_config(config) else {
+            bail!("No module for config {:?}", config.name);
+        };
+        let handler = module.handler(config);
+        let patch = TextPatch::from_json(&str)?;
+        let unknown = patch.apply(config, handler.as_ref(), &mut edit.lock().unwrap(), StoreOrigin::UI)?;
+        if !unknown.is_empty() {
+            warn!("Controls not found in {:?}: {}", config.name, unknown.join(", "));
+            notify(&state.app_event_tx,
+                   format!("{} patch controls not found in {}", unknown.len(), config.name));
+        }
+        return Ok(());
+    }
+
+    let data = if ext == SYX_EXT {
+        syx::edit_buffer_from_syx(config, &bytes)?
+    } else {
+        // module-specific tone file
+        let Some(module) = module_for_config(config) else {
+            bail!("No module for config {:?}", config.name);
+        };
+        let Some(edit) = state.edit_buffer.as_ref() else {
+            bail!("No device configured");
+        };
+        let base = edit.lock().unwrap().raw_locked().to_vec();
+        let (data, unmapped) = module.import(config, &ext, &bytes, &base)?;
+        if !unmapped.is_empty() {
+            for msg in unmapped.iter() {
+                warn!("Not imported: {}", msg);
+            }
+            notify(&state.app_event_tx,
+                   format!("{} tone file entries could not be imported, see the log for details",
+                           unmapped.len()));
+        }
+        data
+    };
+
+    // Load the data into the edit buffer as if it came from the device...
+    let e = BufferDataEvent {
+        buffer: Buffer::EditBuffer,
+        origin: Origin::MIDI,
+        request: Origin::UI,
+        data
+    };
+    state.app_event_tx.send_or_warn(AppEvent::BufferData(e));
+    // ...and send the edit buffer to the device
+    let e = BufferStoreEvent { buffer: Buffer::EditBuffer, origin: Origin::UI };
+    state.app_event_tx.send_or_warn(AppEvent::Store(e));
+
+    Ok(())
+}
+
+pub fn create_export_patch_action(state: Arc<Mutex<State>>) -> gio::ActionEntry<gtk::ApplicationWindow> {
+    gio::ActionEntry::builder("export-patch").activate(move |window: &gtk::ApplicationWindow, _, _| {
+        let dialog = file_chooser(window, "Export patch", FileChooserAction::Save,
+                                  "Patch files", &[SYX_EXT, TEXT_PATCH_EXT]);
+        {
+            let state = state.lock().unwrap();
+            let name = state.edit_buffer.as_ref()
+                .map(|edit| edit.lock().unwrap().name())
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| "patch".to_string());
+            dialog.set_current_name(&format!("{}.{}", name, SYX_EXT));
+        }
+
+        dialog.connect_response({
+            let state = state.clone();
+            move |dialog, response| {
+                let path = dialog.filename();
+                dialog.close();
+                let (ResponseType::Accept, Some(mut path)) = (response, path) else { return };
+                if path.extension().is_none() {
+                    path.set_extension(SYX_EXT);
+                }
+
+                let state = state.lock().unwrap();
+                match export_patch(&state, &path) {
+                    Ok(name) => {
+                        info!("Patch {:?} exported to {:?}", name, path);
+                        notify(&state.app_event_tx, format!("Patch exported: {}", name.trim()));
+                    }
+                    Err(e) => {
+                        error!("Patch export failed: {}", e);
+                        notify(&state.app_event_tx, format!("Patch export failed: {}", e));
+                    }
+                }
+            }
+        });
+        dialog.show();
+    }).build()
+}
+
+pub fn create_import_patch_action(state: Arc<Mutex<State>>) -> gio::ActionEntry<gtk::ApplicationWindow> {
+    gio::ActionEntry::builder("import-patch").activate(move |window: &gtk::ApplicationWindow, _, _| {
+        let exts = {
+            let state = state.lock().unwrap();
+            let mut exts = vec![SYX_EXT, TEXT_PATCH_EXT];
+            if let Some(module) = state.config.and_then(module_for_config) {
+                exts.extend(module.import_extensions());
+            }
+            exts
+        };
+        let dialog = file_chooser(window, "Import patch", FileChooserAction::Open,
+                                  "Patch files", &exts);
+
+        dialog.connect_response({
+            let state = state.clone();
+            move |dialog, response| {
+                let path = dialog.filename();
+                dialog.close();
+                let (ResponseType::Accept, Some(path)) = (response, path) else { return };
+
+                let state = state.lock().unwrap();
+                match import_patch(&state, &path) {
+                    Ok(_) => {
+                        info!("Patch imported from {:?}", path);
+                    }
+                    Err(e) => {
+                        error!("Patch import failed: {}", e);
+                        notify(&state.app_event_tx, format!("Patch import failed: {}", e));
+                    }
+                }
+            }
+        });
+        dialog.show();
+    }).build()
+}
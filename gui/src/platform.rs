@@ -67,6 +67,22 @@ pub fn get_platform_hack_flags() -> String {
         .join(",")
 }
 
+/// All platform hack flags, each either enabled by name or disabled
+/// with a `no-` prefix, so that setting them reproduces the current flags
+pub fn get_platform_hack_flags_explicit() -> String {
+    let flags = platform_hack_flags();
+    PlatformHackFlags::FLAGS.iter().map(|flag| {
+        let name = flag.name().to_ascii_lowercase().replace("_", "-");
+        if flags.contains(flag.value().clone()) {
+            name
+        } else {
+            format!("no-{}", name)
+        }
+    })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
 mod imp {
     use pod_gtk::prelude::*;
     use crate::platform::platform_hack_flags;
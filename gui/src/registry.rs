@@ -1,9 +1,10 @@
 use std::sync::{Arc, Mutex};
 use anyhow::*;
 use log::error;
-use pod_core::config::register_config;
+use pod_core::config::{config_base, configs, register_config};
 use pod_core::dump::ProgramsDump;
 use pod_core::edit::EditBuffer;
+use pod_core::emulator::register_emulator;
 use pod_core::handler::BoxedHandler;
 use pod_core::model::{AbstractControl, Config};
 use pod_core::store::{Signal, Store};
@@ -54,7 +55,20 @@ pub fn module_for_config(config: &Config) -> Option<&Box<dyn Module>> {
         }
     }
 
-    None
+    // configs loaded from definition files are handled by their base config's module
+    config_base(config).and_then(module_for_config)
+}
+
+/// Register software emulators for all device configs of the registered
+/// modules that provide one
+pub fn register_emulators() {
+    for config in configs().iter() {
+        let emulator = module_for_config(config)
+            .and_then(|module| module.emulator(config));
+        if let Some(emulator) = emulator {
+            register_emulator(emulator);
+        }
+    }
 }
 
 pub struct InitializedInterface {
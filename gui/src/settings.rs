@@ -6,12 +6,14 @@ use gtk::{IconSize, ResponseType};
 use crate::{gtk, midi_in_out_start, midi_in_out_stop, set_midi_in_out, State};
 
 use log::*;
-use pod_core::config::configs;
+use pod_core::config::{config_for_name, configs};
+use pod_core::emulator::emulator_ports;
 use pod_core::midi::Channel;
 use pod_core::midi_io::{AutodetectResult, MidiInPort, MidiOutPort, MidiPorts};
 use pod_gtk::prelude::glib::bitflags::bitflags;
+use pod_modules::usb;
 use crate::autodetect::{open, run_autodetect, test};
-use crate::usb;
+use crate::connection::save_connection;
 
 #[derive(Clone)]
 struct SettingsDialog {
@@ -36,7 +38,8 @@ bitflags! {
         const ENTRY_HEADER = 0x01;
         const ENTRY_TEXT   = 0x02;
 
-        const ENTRY_USB    = 0x10;
+        const ENTRY_USB      = 0x10;
+        const ENTRY_EMULATED = 0x20;
     }
 }
 
@@ -111,7 +114,7 @@ impl SettingsDialog {
         for (src, target) in combos {
             src.connect_active_notify(move |combo| {
                 let Some((name, flags)) = combo_get_active(combo) else { return };
-                if !flags.contains(EntryFlags::ENTRY_USB) { return };
+                if !flags.intersects(EntryFlags::ENTRY_USB | EntryFlags::ENTRY_EMULATED) { return };
 
                 let model = target.model().unwrap();
                 let store = model.dynamic_cast_ref::<gtk::ListStore>().unwrap();
@@ -205,7 +208,8 @@ fn populate_midi_channel_combo(settings: &SettingsDialog) {
     CHANNELS.iter().for_each(|i| settings.midi_channel_combo.append_text(i));
 }
 
-fn combo_model_populate(model: &gtk::ListStore, midi_devices: &Vec<String>, usb_devices: &Vec<(String, bool)>) {
+fn combo_model_populate(model: &gtk::ListStore, midi_devices: &Vec<String>, usb_devices: &Vec<(String, bool)>,
+                        emulated_devices: &Vec<String>) {
     model.clear();
 
     let mut n: u32 = 0;
@@ -234,6 +238,13 @@ fn combo_model_populate(model: &gtk::ListStore, midi_devices: &Vec<String>, usb_
     } else {
         add("No devices found...", EntryFlags::ENTRY_TEXT);
     }
+
+    if !emulated_devices.is_empty() {
+        add("Emulated", EntryFlags::ENTRY_HEADER);
+        for name in emulated_devices {
+            add(name, EntryFlags::ENTRY_EMULATED);
+        }
+    }
 }
 
 fn combo_model_find(model: &gtk::ListStore, value: &Option<String>) -> Option<u32> {
@@ -284,13 +295,14 @@ fn populate_midi_combos(settings: &SettingsDialog,
     // populate "midi in" combo box
     let midi_ports = MidiInPort::ports().ok().unwrap_or_default();
     let usb_ports = usb::usb_list_devices();
-    combo_model_populate(&settings.midi_in_combo_model, &midi_ports, &usb_ports);
+    let emulated_ports = emulator_ports();
+    combo_model_populate(&settings.midi_in_combo_model, &midi_ports, &usb_ports, &emulated_ports);
     let active = combo_model_find(&settings.midi_in_combo_model, in_name);
     settings.midi_in_combo.set_active(active);
 
     // populate "midi out" combo box
     let midi_ports = MidiOutPort::ports().ok().unwrap_or_default();
-    combo_model_populate(&settings.midi_out_combo_model, &midi_ports, &usb_ports);
+    combo_model_populate(&settings.midi_out_combo_model, &midi_ports, &usb_ports, &emulated_ports);
     let active = combo_model_find(&settings.midi_out_combo_model, out_name);
     settings.midi_out_combo.set_active(active);
 }
@@ -298,7 +310,7 @@ fn populate_midi_combos(settings: &SettingsDialog,
 fn populate_model_combo(settings: &SettingsDialog, selected: &Option<String>) {
     settings.model_combo.remove_all();
 
-    let mut names = configs().iter().map(|c| &c.name).collect::<Vec<_>>();
+    let mut names = configs().into_iter().map(|c| &c.name).collect::<Vec<_>>();
     names.sort();
     for &name in names.iter() {
         settings.model_combo.append_text(name.as_str());
@@ -359,7 +371,7 @@ fn wire_test_button(settings: &SettingsDialog) {
         let midi_channel = settings.midi_channel_combo.active();
         let config = settings.model_combo.active_text()
             .and_then(|name| {
-                configs().iter().find(|c| c.name == name)
+                config_for_name(&name)
             });
 
         if midi_in.is_none() || midi_out.is_none() {
@@ -408,21 +420,17 @@ fn wire_test_button(settings: &SettingsDialog) {
     });
 }
 
-pub fn create_settings_action(state: Arc<Mutex<State>>, ui: &gtk::Builder) -> gio::ActionEntry<gtk::Application> {
+pub fn create_settings_action(state: Arc<Mutex<State>>, ui: &gtk::Builder) -> gio::ActionEntry<gtk::ApplicationWindow> {
     let settings = SettingsDialog::new(ui);
 
     populate_midi_channel_combo(&settings);
     wire_autodetect_button(&settings);
     wire_test_button(&settings);
 
-    gio::ActionEntry::builder("preferences").activate(move |app: &gtk::Application, _, _| {
-        settings.dialog.set_application(Some(app));
-        let window = app.windows().iter()
-            .find(|w| w.downcast_ref::<gtk::ApplicationWindow>().is_some())
-            .cloned();
-        if let Some(window) = window {
-            settings.dialog.set_transient_for(Some(&window));
-        }
+    gio::ActionEntry::builder("preferences").activate(move |window: &gtk::ApplicationWindow, _, _| {
+        settings.dialog.set_application(window.application().as_ref());
+        settings.dialog.set_transient_for(Some(window));
+        settings.dialog.set_destroy_with_parent(true);
 
         // reset the dialog
         settings.set_interactive(true);
@@ -487,7 +495,7 @@ pub fn create_settings_action(state: Arc<Mutex<State>>, ui: &gtk::Builder) -> gi
                 let midi_channel = settings.midi_channel_combo.active();
                 let config = settings.model_combo.active_text()
                     .and_then(|name| {
-                        configs().iter().find(|c| c.name == name)
+                        config_for_name(&name)
                     });
 
                 let is_usb = midi_in_is_usb || midi_out_is_usb;
@@ -506,7 +514,9 @@ pub fn create_settings_action(state: Arc<Mutex<State>>, ui: &gtk::Builder) -> gi
                         }
                     })
                     .unzip();
-                set_midi_in_out(&mut state.lock().unwrap(), midi_in, midi_out, midi_channel, is_usb, config);
+                let mut state = state.lock().unwrap();
+                set_midi_in_out(&mut state, midi_in, midi_out, midi_channel, is_usb, config);
+                save_connection(&state);
             }
             _ => {
                 let mut state = state.lock().unwrap();
@@ -0,0 +1,160 @@
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use core::result::Result::Ok;
+use log::*;
+use pod_core::event::*;
+use pod_core::midi_io::{MidiInPort, MidiOutPort, MidiPorts};
+use pod_core::model::MidiQuirks;
+use tokio::time::sleep;
+use crate::autodetect::open;
+use crate::{midi_in_out_start, midi_in_out_stop, State};
+
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Id of the connection status notification, so that a later
+/// notification replaces the previous one
+pub const NOTIFICATION_ID: &str = "midi-connection";
+
+#[derive(Clone, PartialEq)]
+struct Ports {
+    in_name: String,
+    out_name: String,
+}
+
+enum Watch {
+    /// Not connected
+    Idle,
+    /// Connected to ports that are not listed by the system, such as an
+    /// emulated device or a replayed session
+    Ignored(Ports),
+    /// Connected to MIDI ports
+    Connected(Ports),
+    /// Ports disappeared or failed, waiting for them to reappear
+    Lost(Ports),
+}
+
+struct Watchdog {
+    state: Arc<Mutex<State>>,
+    watch: Watch,
+}
+
+fn notify(state: &State, msg: String) {
+    let e = NotificationEvent { msg, id: Some(NOTIFICATION_ID.into()) };
+    state.app_event_tx.send_or_warn(AppEvent::Notification(e));
+}
+
+fn list_contains(list: anyhow::Result<Vec<String>>, name: &str) -> bool {
+    match list {
+        Ok(list) => list.iter().any(|n| n == name),
+        Err(e) => {
+            // failing to list the ports doesn't mean they are gone
+            warn!("Failed to list MIDI ports: {}", e);
+            true
+        }
+    }
+}
+
+impl Watchdog {
+    fn midi_present(&self, ports: &Ports) -> bool {
+        list_contains(MidiInPort::all_ports(), &ports.in_name) &&
+            list_contains(MidiOutPort::all_ports(), &ports.out_name)
+    }
+
+    /// Start watching newly opened ports
+    fn watch(&mut self, ports: Ports) -> Watch {
+        if self.midi_present(&ports) {
+            return Watch::Connected(ports);
+        }
+        Watch::Ignored(ports)
+    }
+
+    async fn lost(&mut self, ports: Ports) -> Watch {
+        info!("MIDI ports {:?}/{:?} lost", ports.in_name, ports.out_name);
+        let join = {
+            let mut state = self.state.lock().unwrap();
+            notify(&state, format!("Device <b>{}</b> disconnected", ports.in_name));
+            midi_in_out_stop(&mut state)
+        };
+        join.await;
+        Watch::Lost(ports)
+    }
+
+    fn reconnect(&mut self, ports: Ports) -> Watch {
+        let (midi_in, midi_out) = match open(&ports.in_name, &ports.out_name, false) {
+            Ok(v) => v,
+            Err(e) => {
+                debug!("Failed to reopen MIDI ports: {}", e);
+                return Watch::Lost(ports);
+            }
+        };
+        info!("MIDI ports {:?}/{:?} reappeared", ports.in_name, ports.out_name);
+
+        let mut state = self.state.lock().unwrap();
+        let midi_channel = state.midi_channel_num;
+        let quirks = state.config.map(|c| c.midi_quirks)
+            .unwrap_or_else(MidiQuirks::empty);
+        midi_in_out_start(&mut state, Some(midi_in), Some(midi_out), midi_channel,
+                          false, quirks, false);
+        notify(&state, format!("Device <b>{}</b> reconnected", ports.in_name));
+
+        // the device may have changed while disconnected, re-sync the edit buffer
+        // and leave the (possibly modified) programs as they are
+        let e = BufferLoadEvent { buffer: Buffer::EditBuffer, origin: Origin::UI };
+        state.app_event_tx.send_or_warn(AppEvent::Load(e));
+
+        Watch::Connected(ports)
+    }
+
+    async fn tick(&mut self, send_failed: bool) {
+        let (ports, running) = {
+            let state = self.state.lock().unwrap();
+            let ports = state.midi_in_name.clone().zip(state.midi_out_name.clone())
+                .map(|(in_name, out_name)| Ports { in_name, out_name });
+            (ports, state.midi_in_cancel.is_some())
+        };
+
+        let watch = std::mem::replace(&mut self.watch, Watch::Idle);
+        self.watch = match (watch, ports) {
+            (Watch::Connected(p), Some(ports)) if p == ports && running => {
+                if !send_failed && self.midi_present(&p) {
+                    Watch::Connected(p)
+                } else {
+                    self.lost(p).await
+                }
+            }
+            (Watch::Lost(p), Some(ports)) if p == ports && !running => {
+                if self.midi_present(&p) {
+                    self.reconnect(p)
+                } else {
+                    Watch::Lost(p)
+                }
+            }
+            (Watch::Ignored(p), Some(ports)) if p == ports && running => Watch::Ignored(p),
+            (_, Some(ports)) if running => self.watch(ports),
+            _ => Watch::Idle
+        };
+    }
+}
+
+/// Start a watchdog that notices the MIDI ports of the current connection
+/// disappearing or failing and reopens them by name once they are listed
+/// again. The device context, and with it any unsaved programs, is kept.
+/// The watchdog stops when the window of `state` is closed.
+pub fn start_midi_watchdog(state: &Arc<Mutex<State>>) {
+    let send_failed = state.lock().unwrap().send_failed.clone();
+    let state: Weak<Mutex<State>> = Arc::downgrade(state);
+    let mut watch = Watch::Idle;
+
+    tokio::spawn(async move {
+        loop {
+            let failed = tokio::select! {
+                _ = sleep(WATCHDOG_INTERVAL) => false,
+                _ = send_failed.notified() => true
+            };
+            let Some(state) = state.upgrade() else { break };
+            let mut watchdog = Watchdog { state, watch };
+            watchdog.tick(failed).await;
+            watch = watchdog.watch;
+        }
+    });
+}
@@ -25,7 +25,8 @@ pub enum ProgramGridAction {
     LoadUnmodified { program: usize },
     Store { program: usize },
     LoadDevice { program: usize },
-    StoreDevice { program: usize }
+    StoreDevice { program: usize },
+    Compare { program: usize }
 }
 
 #[derive(Clone, Debug)]
@@ -237,6 +238,7 @@ impl ProgramGridPriv {
             "store" => ProgramGridAction::Store { program },
             "load-device" => ProgramGridAction::LoadDevice { program },
             "store-device" => ProgramGridAction::StoreDevice { program },
+            "compare" => ProgramGridAction::Compare { program },
             _ => {
                 warn!("Unknown right-click menu action: {}", action);
                 return;
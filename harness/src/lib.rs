@@ -0,0 +1,304 @@
+//! A test harness driving a device handler through the same app event
+//! dispatch as the GUI, synchronously and without any MIDI ports.
+mod peer;
+pub mod registry;
+
+pub use peer::ScriptedPeer;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::TryRecvError;
+use pod_core::context::Ctx;
+use pod_core::controller::*;
+use pod_core::dispatch::*;
+use pod_core::dump::ProgramsDump;
+use pod_core::edit::EditBuffer;
+use pod_core::emulator::SharedEmulatedDevice;
+use pod_core::event::*;
+use pod_core::handler::BoxedHandler;
+use pod_core::midi::{Channel, MidiMessage};
+use pod_core::model::{Config, Control, VirtualSelect};
+use pod_core::program;
+
+const APP_EVENT_CHANNEL_CAPACITY: usize = 4096;
+/// Upper limit of events processed by a single `run`, catches event loops
+const MAX_EVENTS: usize = 100_000;
+
+fn ui_controls() -> HashMap<String, Control> {
+    ["midi_channel", "program", "program:prev"].iter()
+        .map(|name| (name.to_string(), VirtualSelect::default().into()))
+        .collect()
+}
+
+pub struct Harness {
+    pub ctx: Ctx,
+    app_event_rx: broadcast::Receiver<AppEvent>,
+    controller_rx: broadcast::Receiver<Event<String,u16>>,
+    ui_controller_rx: broadcast::Receiver<Event<String,u16>>,
+    peer: Option<SharedEmulatedDevice>,
+    events: Vec<AppEvent>,
+}
+
+impl Harness {
+    /// Create a harness for the registered device config `name`
+    pub fn new(name: &str) -> Self {
+        let config = registry::config(name);
+        Self::with_handler(config, registry::handler(config))
+    }
+
+    pub fn with_handler(config: &'static Config, handler: BoxedHandler) -> Self {
+        let (app_event_tx, app_event_rx) =
+            broadcast::channel::<AppEvent>(APP_EVENT_CHANNEL_CAPACITY);
+
+        let edit = Arc::new(Mutex::new(EditBuffer::new(config)));
+        let dump = Arc::new(Mutex::new(ProgramsDump::new(config)));
+        let controller = edit.lock().unwrap().controller();
+        let (tx, controller_rx) = broadcast::channel(APP_EVENT_CHANNEL_CAPACITY);
+        controller.broadcast(Some(tx));
+
+        let ui_controller = Arc::new(Mutex::new(Controller::new(ui_controls())));
+        ui_controller.set("program", Program::ManualMode.into(), StoreOrigin::NONE);
+        ui_controller.set("program:prev", Program::ManualMode.into(), StoreOrigin::NONE);
+        let (tx, ui_controller_rx) = broadcast::channel(APP_EVENT_CHANNEL_CAPACITY);
+        ui_controller.broadcast(Some(tx));
+
+        let ctx = Ctx {
+            config,
+            handler,
+            controller,
+            edit,
+            dump,
+            reroute: BufferReroute::new(),
+            ui_controller,
+            app_event_tx
+        };
+        ctx.set_midi_channel(Channel::num(0));
+
+        let mut harness = Self {
+            ctx, app_event_rx, controller_rx, ui_controller_rx,
+            peer: None,
+            events: vec![],
+        };
+        harness.run();
+        harness.clear_events();
+        harness
+    }
+
+    /// Connect a MIDI peer, such as an emulated device or a `ScriptedPeer`,
+    /// that answers all MIDI messages sent by the handler
+    pub fn connect(&mut self, peer: SharedEmulatedDevice) {
+        self.peer = Some(peer);
+    }
+
+    /// Send an app event and process all events that follow from it
+    pub fn send(&mut self, event: AppEvent) {
+        self.ctx.app_event_tx.send_or_warn(event);
+        self.run();
+    }
+
+    /// Process app events until the event bus is idle
+    pub fn run(&mut self) {
+        for _ in 0 .. MAX_EVENTS {
+            self.bridge_controllers();
+            match self.app_event_rx.try_recv() {
+                Ok(event) => self.process(event),
+                Err(TryRecvError::Empty) => {
+                    if self.controller_rx.is_empty() && self.ui_controller_rx.is_empty() {
+                        return;
+                    }
+                }
+                Err(TryRecvError::Lagged(n)) => panic!("App event bus lagged: {}", n),
+                Err(TryRecvError::Closed) => panic!("App event bus closed")
+            }
+        }
+        panic!("App event bus not idle after {} events", MAX_EVENTS);
+    }
+
+    /// Forward controller changes to the app event bus the same way the
+    /// GUI does: edit buffer controls as control changes, UI program
+    /// selection as program changes
+    fn bridge_controllers(&mut self) {
+        while let Ok(Event { key, value, origin, .. }) = self.controller_rx.try_recv() {
+            let e = ControlChangeEvent { name: key, value, origin };
+            self.ctx.app_event_tx.send_or_warn(AppEvent::ControlChange(e));
+        }
+        while let Ok(Event { key, value, origin, .. }) = self.ui_controller_rx.try_recv() {
+            if key != "program" || value >= 1000 {
+                continue;
+            }
+            let Ok(origin) = Origin::try_from(origin) else {
+                continue;
+            };
+            let e = ProgramChangeEvent { program: value.into(), origin };
+            self.ctx.app_event_tx.send_or_warn(AppEvent::ProgramChange(e));
+        }
+    }
+
+    fn process(&mut self, event: AppEvent) {
+        let ctx = &self.ctx;
+        dispatch(ctx, &event);
+
+        // message conversion & MIDI peer
+        match &event {
+            AppEvent::MidiIn(bytes) => {
+                let msg = MidiMessage::from_bytes(bytes.clone())
+                    .unwrap_or_else(|e| panic!("Invalid MIDI in message {:02x?}: {}", bytes, e));
+                ctx.app_event_tx.send_or_warn(AppEvent::MidiMsgIn(msg));
+            }
+            AppEvent::MidiMsgOut(msg) => {
+                let bytes = msg.to_bytes()
+                    .unwrap_or_else(|e| panic!("Failed to encode {:?}: {}", msg, e));
+                ctx.app_event_tx.send_or_warn(AppEvent::MidiOut(bytes));
+            }
+            AppEvent::MidiOut(bytes) => {
+                if let Some(peer) = self.peer.as_ref() {
+                    let msg = MidiMessage::from_bytes(bytes.clone())
+                        .unwrap_or_else(|e| panic!("Invalid MIDI out message {:02x?}: {}", bytes, e));
+                    for reply in peer.lock().unwrap().message(msg) {
+                        let bytes = reply.to_bytes()
+                            .unwrap_or_else(|e| panic!("Failed to encode {:?}: {}", reply, e));
+                        ctx.app_event_tx.send_or_warn(AppEvent::MidiIn(bytes));
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        self.events.push(event);
+    }
+
+    // actions
+
+    /// Select a program in the UI
+    pub fn select_program(&mut self, program: Program) {
+        self.ctx.ui_controller.set("program", program.into(), StoreOrigin::UI);
+        self.run();
+    }
+
+    /// Change an edit buffer control value in the UI
+    pub fn set_control(&mut self, name: &str, value: u16) {
+        self.ctx.controller.set(name, value, StoreOrigin::UI);
+        self.run();
+    }
+
+    /// Receive a MIDI message from the device
+    pub fn midi_in(&mut self, msg: MidiMessage) {
+        let bytes = msg.to_bytes()
+            .unwrap_or_else(|e| panic!("Failed to encode {:?}: {}", msg, e));
+        self.send(AppEvent::MidiIn(bytes));
+    }
+
+    /// Set program data in the programs dump without any events
+    pub fn set_program_data(&mut self, program: usize, data: &[u8]) {
+        let mut dump = self.ctx.dump.lock().unwrap();
+        program::load_patch_dump(&mut dump, program, data, Origin::MIDI);
+        dump.set_modified(program, false);
+    }
+
+    // inspection
+
+    /// All events processed since the last `clear_events`
+    pub fn events(&self) -> &[AppEvent] {
+        &self.events
+    }
+
+    pub fn clear_events(&mut self) {
+        self.events.clear();
+    }
+
+    /// MIDI messages sent to the device since the last `clear_events`
+    pub fn midi_out(&self) -> Vec<MidiMessage> {
+        self.events.iter()
+            .filter_map(|e| match e {
+                AppEvent::MidiMsgOut(msg) => Some(msg.clone()),
+                _ => None
+            })
+            .collect()
+    }
+
+    pub fn program(&self) -> Program {
+        self.ctx.program()
+    }
+
+    pub fn control(&self, name: &str) -> Option<u16> {
+        self.ctx.controller.get(name)
+    }
+
+    pub fn program_data(&self, program: usize) -> Vec<u8> {
+        program::store_patch_dump(&self.ctx.dump.lock().unwrap(), program)
+    }
+
+    pub fn edit_data(&self) -> Vec<u8> {
+        program::store_patch_dump_ctrl(&self.ctx.edit.lock().unwrap())
+    }
+
+    pub fn modified(&self, program: usize) -> bool {
+        self.ctx.dump.lock().unwrap().modified(program)
+    }
+
+    pub fn edit_modified(&self) -> bool {
+        self.ctx.edit.lock().unwrap().modified()
+    }
+
+    // assertions
+
+    #[track_caller]
+    pub fn assert_event<F>(&self, desc: &str, f: F)
+        where F: Fn(&AppEvent) -> bool
+    {
+        assert!(self.events.iter().any(f), "Expected {} in events: {:#?}", desc, self.events);
+    }
+
+    #[track_caller]
+    pub fn assert_no_event<F>(&self, desc: &str, f: F)
+        where F: Fn(&AppEvent) -> bool
+    {
+        assert!(!self.events.iter().any(f), "Unexpected {} in events: {:#?}", desc, self.events);
+    }
+
+    /// Assert that exactly these MIDI messages were sent to the device,
+    /// in order
+    #[track_caller]
+    pub fn assert_midi_out(&self, expected: &[MidiMessage]) {
+        assert_eq!(self.midi_out(), expected);
+    }
+
+    #[track_caller]
+    pub fn assert_program(&self, program: Program) {
+        assert_eq!(self.program(), program);
+    }
+
+    #[track_caller]
+    pub fn assert_control(&self, name: &str, value: u16) {
+        assert_eq!(self.control(name), Some(value), "Control {:?} value", name);
+    }
+
+    #[track_caller]
+    pub fn assert_program_data(&self, program: usize, data: &[u8]) {
+        assert_eq!(self.program_data(program), data, "Program {} data", program);
+    }
+
+    #[track_caller]
+    pub fn assert_edit_data(&self, data: &[u8]) {
+        assert_eq!(self.edit_data(), data, "Edit buffer data");
+    }
+
+    #[track_caller]
+    pub fn assert_modified(&self, program: usize, modified: bool) {
+        assert_eq!(self.modified(program), modified, "Program {} modified", program);
+    }
+}
+
+/// Program data of the given `config` filled with a pattern derived
+/// from `seed` and named after it
+pub fn test_program(config: &Config, seed: u8) -> Vec<u8> {
+    let mut data = (0 .. config.program_size)
+        .map(|i| (i as u8).wrapping_add(seed) & 0x3f)
+        .collect::<Vec<_>>();
+    let name = format!("{:1$}", format!("TEST {}", seed), config.program_name_length);
+    let addr = config.program_name_addr;
+    data[addr .. addr + config.program_name_length]
+        .copy_from_slice(&name.as_bytes()[.. config.program_name_length]);
+    data
+}
@@ -0,0 +1,54 @@
+use std::collections::VecDeque;
+use log::*;
+use pod_core::emulator::EmulatedDevice;
+use pod_core::midi::MidiMessage;
+use pod_core::model::Config;
+
+/// A MIDI peer answering the expected messages with canned replies,
+/// in order. Messages that do not match the next expected message are
+/// recorded as unexpected and left unanswered.
+pub struct ScriptedPeer {
+    config: &'static Config,
+    script: VecDeque<(MidiMessage, Vec<MidiMessage>)>,
+    unexpected: Vec<MidiMessage>,
+}
+
+impl ScriptedPeer {
+    pub fn new(config: &'static Config) -> Self {
+        Self { config, script: VecDeque::new(), unexpected: vec![] }
+    }
+
+    /// Expect `msg` next and answer it with `replies`
+    pub fn expect(mut self, msg: MidiMessage, replies: Vec<MidiMessage>) -> Self {
+        self.script.push_back((msg, replies));
+        self
+    }
+
+    /// Whether all expected messages were received
+    pub fn is_done(&self) -> bool {
+        self.script.is_empty()
+    }
+
+    pub fn unexpected(&self) -> &[MidiMessage] {
+        &self.unexpected
+    }
+}
+
+impl EmulatedDevice for ScriptedPeer {
+    fn config(&self) -> &'static Config {
+        self.config
+    }
+
+    fn message(&mut self, msg: MidiMessage) -> Vec<MidiMessage> {
+        match self.script.front() {
+            Some((expected, _)) if *expected == msg => {
+                self.script.pop_front().map(|(_, replies)| replies).unwrap_or_default()
+            }
+            _ => {
+                debug!("Scripted peer: unexpected {:?}", msg);
+                self.unexpected.push(msg);
+                vec![]
+            }
+        }
+    }
+}
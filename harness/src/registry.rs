@@ -0,0 +1,26 @@
+use pod_core::config::{config_for_name, configs};
+use pod_core::handler::BoxedHandler;
+use pod_core::model::Config;
+use pod_modules::{handler_for_config, register_modules};
+
+/// Find a registered device config by name, registering all device
+/// modules on first use. Panics if no such config exists.
+pub fn config(name: &str) -> &'static Config {
+    register_modules();
+    config_for_name(name)
+        .unwrap_or_else(|| panic!("Config {:?} not found", name))
+}
+
+/// All registered device configs, registering all device modules on
+/// first use
+pub fn all_configs() -> Vec<&'static Config> {
+    register_modules();
+    configs()
+}
+
+/// Create the handler of the module providing `config`, or the config
+/// it is based on
+pub fn handler(config: &'static Config) -> BoxedHandler {
+    handler_for_config(config)
+        .unwrap_or_else(|| panic!("No module for config {:?}", config.name))
+}
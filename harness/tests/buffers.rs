@@ -0,0 +1,230 @@
+use std::sync::{Arc, Mutex};
+use pod_core::emulator::Pod2Emulator;
+use pod_core::event::*;
+use pod_core::midi::MidiMessage;
+use pod_harness::*;
+
+fn emulated(name: &str) -> Harness {
+    let mut h = Harness::new(name);
+    let config = h.ctx.config;
+    let emulator = Pod2Emulator::new(config, registry::handler(config));
+    h.connect(Arc::new(Mutex::new(emulator)));
+    h
+}
+
+/// Connect a scripted peer answering a single program dump request
+/// with `data`
+fn scripted(h: &mut Harness, patch: u8, data: &[u8]) -> Arc<Mutex<ScriptedPeer>> {
+    let peer = ScriptedPeer::new(h.ctx.config)
+        .expect(MidiMessage::ProgramPatchDumpRequest { patch },
+                vec![MidiMessage::ProgramPatchDump { patch, ver: 0, data: data.to_vec() }]);
+    let peer = Arc::new(Mutex::new(peer));
+    h.connect(peer.clone());
+    peer
+}
+
+fn load(h: &mut Harness, buffer: Buffer) {
+    h.send(AppEvent::Load(BufferLoadEvent { buffer, origin: Origin::UI }));
+}
+
+fn store(h: &mut Harness, buffer: Buffer) {
+    h.send(AppEvent::Store(BufferStoreEvent { buffer, origin: Origin::UI }));
+}
+
+fn copy(h: &mut Harness, from: Buffer, to: Buffer) {
+    h.send(AppEvent::Copy(BufferCopyEvent { from, to }));
+}
+
+fn name(h: &Harness, program: usize) -> String {
+    h.ctx.dump.lock().unwrap().name(program).unwrap()
+}
+
+#[test]
+fn load_program() {
+    let mut h = Harness::new("POD 2.0");
+    let data = test_program(h.ctx.config, 3);
+    let peer = scripted(&mut h, 3, &data);
+
+    load(&mut h, Buffer::Program(3));
+
+    assert!(peer.lock().unwrap().is_done());
+    assert!(peer.lock().unwrap().unexpected().is_empty());
+    h.assert_program_data(3, &data);
+    h.assert_modified(3, false);
+}
+
+#[test]
+fn load_current_program_updates_edit_buffer() {
+    let mut h = Harness::new("POD 2.0");
+    h.select_program(Program::Program(3));
+    let data = test_program(h.ctx.config, 3);
+    scripted(&mut h, 3, &data);
+
+    load(&mut h, Buffer::Current);
+
+    h.assert_program_data(3, &data);
+    h.assert_edit_data(&data);
+}
+
+#[test]
+fn load_all_single_dump() {
+    let mut h = emulated("POD 2.0");
+
+    load(&mut h, Buffer::All);
+
+    h.assert_midi_out(&[MidiMessage::AllProgramsDumpRequest]);
+    h.assert_event("all programs dump", |e| matches!(e,
+        AppEvent::MidiMsgIn(MidiMessage::AllProgramsDump { .. })
+    ));
+    assert_eq!(name(&h, 0), "Program 1A");
+    assert_eq!(name(&h, 35), "Program 9D");
+}
+
+#[test]
+fn load_all_program_dumps() {
+    let mut h = emulated("Pocket POD");
+
+    load(&mut h, Buffer::All);
+
+    h.assert_midi_out(&[MidiMessage::AllProgramsDumpRequest]);
+    let dumps = h.events().iter()
+        .filter(|e| matches!(e, AppEvent::MidiMsgIn(MidiMessage::ProgramPatchDump { .. })))
+        .count();
+    assert_eq!(dumps, h.ctx.config.program_num);
+    assert_eq!(name(&h, 0), "Program 1A");
+    assert_eq!(name(&h, 123), "Program 31D");
+}
+
+#[test]
+fn load_all_floor_pod_banks() {
+    let mut h = emulated("Floor POD Plus");
+
+    load(&mut h, Buffer::All);
+
+    // 16 banks of 4 channels
+    assert_eq!(h.ctx.config.program_num, 64);
+    assert_eq!(name(&h, 0), "Program 1A");
+    assert_eq!(name(&h, 63), "Program 16D");
+}
+
+#[test]
+fn store_program() {
+    let mut h = emulated("POD 2.0");
+    let data = test_program(h.ctx.config, 2);
+    h.set_program_data(2, &data);
+
+    store(&mut h, Buffer::Program(2));
+
+    h.assert_midi_out(&[MidiMessage::ProgramPatchDump { patch: 2, ver: 0, data }]);
+    h.assert_modified(2, false);
+}
+
+#[test]
+fn store_edit_buffer() {
+    let mut h = emulated("POD 2.0");
+    h.select_program(Program::Program(1));
+    h.set_control("drive", 10);
+    h.clear_events();
+
+    store(&mut h, Buffer::EditBuffer);
+
+    let data = h.edit_data();
+    h.assert_midi_out(&[MidiMessage::ProgramEditBufferDump { ver: 0, data }]);
+}
+
+#[test]
+fn copy_program_to_program() {
+    let mut h = emulated("POD 2.0");
+    let data = test_program(h.ctx.config, 1);
+    h.set_program_data(1, &data);
+
+    copy(&mut h, Buffer::Program(1), Buffer::Program(4));
+
+    // the copy is local until the program is stored
+    h.assert_midi_out(&[]);
+    h.assert_program_data(4, &data);
+    h.assert_modified(4, true);
+    h.assert_modified(1, false);
+}
+
+#[test]
+fn copy_program_to_edit_buffer() {
+    let mut h = emulated("POD 2.0");
+    let data = test_program(h.ctx.config, 1);
+    h.set_program_data(1, &data);
+    h.select_program(Program::Program(2));
+    h.clear_events();
+
+    copy(&mut h, Buffer::Program(1), Buffer::EditBuffer);
+
+    // the edit buffer is sent to the device right away
+    h.assert_edit_data(&data);
+    assert!(h.edit_modified());
+    h.assert_midi_out(&[MidiMessage::ProgramEditBufferDump { ver: 0, data }]);
+}
+
+#[test]
+fn rerouted_load_into_edit_buffer() {
+    let mut h = Harness::new("POD 2.0");
+    h.select_program(Program::Program(2));
+    let data = test_program(h.ctx.config, 5);
+    let peer = scripted(&mut h, 5, &data);
+    h.clear_events();
+
+    // load program 5 from the device into the edit buffer
+    h.ctx.reroute.set(Buffer::Program(5), Buffer::EditBuffer);
+    load(&mut h, Buffer::Program(5));
+
+    assert!(peer.lock().unwrap().is_done());
+    h.assert_edit_data(&data);
+    assert!(h.edit_modified());
+    h.assert_midi_out(&[
+        MidiMessage::ProgramPatchDumpRequest { patch: 5 },
+        MidiMessage::ProgramEditBufferDump { ver: 0, data: data.clone() }
+    ]);
+    // program 5 itself is left as it was
+    assert_ne!(h.program_data(5), data);
+    h.assert_modified(5, false);
+}
+
+#[test]
+fn reroutes_are_per_context() {
+    let mut h = Harness::new("POD 2.0");
+    let mut other = Harness::new("POD 2.0");
+    let data = test_program(h.ctx.config, 5);
+    scripted(&mut h, 5, &data);
+    scripted(&mut other, 5, &data);
+
+    // only the first device loads program 5 into the edit buffer
+    h.ctx.reroute.set(Buffer::Program(5), Buffer::EditBuffer);
+    load(&mut other, Buffer::Program(5));
+    load(&mut h, Buffer::Program(5));
+
+    h.assert_edit_data(&data);
+    other.assert_program_data(5, &data);
+    assert_ne!(other.edit_data(), data);
+}
+
+#[test]
+fn new_device_keeps_restored_programs() {
+    let mut h = Harness::new("POD 2.0");
+    let channel = h.ctx.midi_channel();
+
+    pod_core::dispatch::new_device_handler(&h.ctx);
+    h.run();
+    h.assert_midi_out(&[MidiMessage::UniversalDeviceInquiry { channel },
+                        MidiMessage::AllProgramsDumpRequest]);
+
+    // programs restored from a mirror, one of them changed offline
+    {
+        let mut dump = h.ctx.dump.lock().unwrap();
+        dump.set_modified(3, true);
+        dump.set_restored(true);
+    }
+    h.clear_events();
+    pod_core::dispatch::new_device_handler(&h.ctx);
+    h.run();
+    h.assert_midi_out(&[MidiMessage::UniversalDeviceInquiry { channel },
+                        MidiMessage::ProgramEditBufferDumpRequest]);
+    h.assert_modified(3, true);
+}
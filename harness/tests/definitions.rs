@@ -0,0 +1,38 @@
+use pod_core::definition::*;
+use pod_core::midi::MidiMessage;
+use pod_harness::*;
+
+#[test]
+fn builtin_configs_are_valid() {
+    for config in registry::all_configs() {
+        validate_config(config).unwrap();
+    }
+}
+
+#[test]
+fn definition_based_on_builtin_config() {
+    registry::all_configs();
+    let def = ConfigDefinition::from_toml(r#"
+        name = "POD 2.0 (sibling)"
+        base = "POD 2.0"
+        member = 0x0399
+
+        [controls.drive]
+        type = "range_control"
+        cc = 70
+        addr = 9
+    "#).unwrap();
+    let config = register_definition(def).unwrap();
+    assert_eq!(config.member, 0x0399);
+
+    let mut h = Harness::new("POD 2.0 (sibling)");
+    h.set_control("drive", 64);
+    h.assert_midi_out(&[MidiMessage::ControlChange { channel: 0, control: 70, value: 64 }]);
+
+    // same family/member id as the base config
+    let def = ConfigDefinition::from_toml(r#"
+        name = "POD 2.0 (clone)"
+        base = "POD 2.0"
+    "#).unwrap();
+    assert!(register_definition(def).is_err());
+}
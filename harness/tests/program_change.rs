@@ -0,0 +1,177 @@
+use std::sync::{Arc, Mutex};
+use pod_core::emulator::Pod2Emulator;
+use pod_core::event::*;
+use pod_core::midi::MidiMessage;
+use pod_harness::*;
+use pod_mod_xt::emulator::PodXtEmulator;
+
+fn harness(name: &str) -> Harness {
+    let mut h = Harness::new(name);
+    let config = h.ctx.config;
+    let emulator = Pod2Emulator::new(config, registry::handler(config));
+    h.connect(Arc::new(Mutex::new(emulator)));
+    h
+}
+
+/// PC message for a program number, manual mode or tuner PC value
+fn pc(h: &Harness, program: Program) -> MidiMessage {
+    let config = h.ctx.config;
+    let program = match program {
+        Program::ManualMode => config.pc_manual_mode.unwrap(),
+        Program::Tuner => config.pc_tuner.unwrap(),
+        Program::Program(p) => p as usize + config.pc_offset.unwrap_or_default()
+    };
+    MidiMessage::ProgramChange { channel: 0, program: program as u8 }
+}
+
+fn edit_buffer_dump(data: Vec<u8>) -> MidiMessage {
+    MidiMessage::ProgramEditBufferDump { ver: 0, data }
+}
+
+/// Select program 1 in the UI, modify it and switch to program 2
+fn modify_program_1(h: &mut Harness) {
+    h.select_program(Program::Program(1));
+    h.set_control("drive", 10);
+    h.select_program(Program::Program(2));
+    h.assert_modified(1, true);
+    h.clear_events();
+}
+
+#[test]
+fn ui_program_change_sends_pc() {
+    let mut h = harness("POD 2.0");
+    let data = test_program(h.ctx.config, 1);
+    h.set_program_data(1, &data);
+
+    h.select_program(Program::Program(1));
+
+    h.assert_program(Program::Program(1));
+    h.assert_midi_out(&[pc(&h, Program::Program(1))]);
+    h.assert_edit_data(&data);
+    assert!(!h.edit_modified());
+}
+
+#[test]
+fn ui_manual_mode_sends_pc() {
+    let mut h = harness("POD 2.0");
+    h.select_program(Program::Program(1));
+    h.clear_events();
+
+    h.select_program(Program::ManualMode);
+
+    h.assert_program(Program::ManualMode);
+    h.assert_midi_out(&[pc(&h, Program::ManualMode)]);
+}
+
+#[test]
+fn midi_program_change_does_not_echo() {
+    let mut h = harness("POD 2.0");
+    let data = test_program(h.ctx.config, 5);
+    h.set_program_data(5, &data);
+
+    h.midi_in(pc(&h, Program::Program(5)));
+
+    h.assert_program(Program::Program(5));
+    h.assert_midi_out(&[]);
+    h.assert_edit_data(&data);
+}
+
+#[test]
+fn control_change_modifies_edit_buffer() {
+    let mut h = harness("POD 2.0");
+    h.select_program(Program::Program(1));
+    h.clear_events();
+
+    h.set_control("drive", 10);
+
+    h.assert_control("drive", 10);
+    assert!(h.edit_modified());
+    h.assert_event("current buffer modified", |e| matches!(e,
+        AppEvent::Modified(ModifiedEvent { buffer: Buffer::Current, modified: true, .. })
+    ));
+    h.assert_event("CC out", |e| matches!(e,
+        AppEvent::MidiMsgOut(MidiMessage::ControlChange { control: 13, .. })
+    ));
+}
+
+#[test]
+fn bass_pod_control_change() {
+    let mut h = harness("Bass POD");
+    h.select_program(Program::Program(1));
+    h.clear_events();
+
+    // Bass POD treble is at the POD 2.0 presence CC and address
+    h.set_control("treble", 10);
+
+    h.assert_control("treble", 10);
+    assert_eq!(h.edit_data()[14], 10);
+    h.assert_event("CC out", |e| matches!(e,
+        AppEvent::MidiMsgOut(MidiMessage::ControlChange { control: 21, .. })
+    ));
+}
+
+#[test]
+fn floor_pod_pc_37_is_a_program() {
+    let mut h = harness("Floor POD Plus");
+
+    // POD 2.0 tuner PC is program 9A on a Floor POD Plus
+    h.midi_in(MidiMessage::ProgramChange { channel: 0, program: 37 });
+
+    h.assert_program(Program::Program(36));
+}
+
+#[test]
+fn modified_program_sends_edit_buffer() {
+    let mut h = harness("POD 2.0");
+    modify_program_1(&mut h);
+
+    h.select_program(Program::Program(1));
+
+    // POD 2.0 gets the modified buffer instead of a PC
+    h.assert_control("drive", 10);
+    let data = h.edit_data();
+    h.assert_midi_out(&[edit_buffer_dump(data)]);
+    assert!(h.edit_modified());
+}
+
+#[test]
+fn modified_program_sends_pc_and_edit_buffer() {
+    let mut h = harness("Pocket POD");
+    modify_program_1(&mut h);
+
+    h.select_program(Program::Program(1));
+
+    // Pocket POD gets a PC first, so that the modified buffer is
+    // loaded on top of the right program
+    h.assert_control("drive", 10);
+    let data = h.edit_data();
+    h.assert_midi_out(&[pc(&h, Program::Program(1)), edit_buffer_dump(data)]);
+}
+
+#[test]
+fn unmodified_program_sends_pc() {
+    let mut h = harness("Pocket POD");
+    modify_program_1(&mut h);
+
+    h.select_program(Program::Program(3));
+
+    h.assert_midi_out(&[pc(&h, Program::Program(3))]);
+    h.assert_modified(1, true);
+    h.assert_modified(3, false);
+}
+
+#[test]
+fn xt_program_change_sends_pc() {
+    let mut h = Harness::new("PODxt");
+    let config = h.ctx.config;
+    let emulator = Arc::new(Mutex::new(
+        PodXtEmulator::new(config, registry::handler(config), Some(0x0f))
+    ));
+    h.connect(emulator.clone());
+
+    h.select_program(Program::Program(7));
+
+    h.assert_program(Program::Program(7));
+    assert!(h.midi_out().contains(&pc(&h, Program::Program(7))));
+    assert_eq!(emulator.lock().unwrap().program(), 7);
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use maplit::*;
+use once_cell::sync::Lazy;
+use pod_core::builders::shorthand::*;
+use pod_core::model::*;
+use pod_mod_pod2::{short, fmt_percent};
+
+pub static BASS_POD_CONFIG: Lazy<Config> = Lazy::new(|| {
+    // Bass POD is of the same generation as POD 2.0, so assume the same
+    // program layout and effect engine, with a bass preamp instead of
+    // the guitar one
+    let pod2_config = pod_mod_pod2::configs()[0].clone();
+    let exclude = [
+        "drive2", "mid", "treble", "presence", "bright_switch_enable"
+    ];
+
+    let bass_pod_controls: HashMap<String, Control> = convert_args!(hashmap!(
+        "lo_mid" => RangeControl { cc: 15, addr: 12, config: short!(),
+            format: fmt_percent!() },
+        "hi_mid" => RangeControl { cc: 16, addr: 13, config: short!(),
+            format: fmt_percent!() },
+        "treble" => RangeControl { cc: 21, addr: 14, config: short!(),
+            format: fmt_percent!() },
+    ));
+    let controls = pod2_config.controls.into_iter()
+        .filter(|(k, _)| !exclude.contains(&k.as_str()))
+        .chain(bass_pod_controls)
+        .collect();
+
+    let init_controls = pod2_config.init_controls.into_iter()
+        .filter(|v| !exclude.contains(&v.as_str()))
+        .collect();
+
+    Config {
+        name: "Bass POD".to_string(),
+        // TODO: member ids of Bass POD and Bass POD Pro are not confirmed
+        //       by a device inquiry reply from a real device
+        family: 0x0000,
+        member: 0x0500,
+
+        amp_models: convert_args!(vec!(
+            amp("Tube Preamp"),
+            amp("Session"),
+            amp("California"),
+            amp("Jazz Tone"),
+            amp("Adam and Eve"),
+            amp("Eighties"),
+            amp("Stadium"),
+            amp("Amp 360"),
+            amp("Rock Classic"),
+            amp("Motor City"),
+            amp("Brit Class A100"),
+            amp("Brit Invader"),
+            amp("Silverface Bass"),
+            amp("Flip Top"),
+            amp("Sub Dub"),
+            amp("Hiway 200"),
+        )),
+        cab_models: convert_args!(vec!(
+            "1x12 Boutique",
+            "1x15 Flip Top",
+            "1x15 Jazz Tone",
+            "1x18 Session",
+            "1x18 Amp 360",
+            "1x18 California",
+            "1x18+12 Stadium",
+            "2x10 Modern UK",
+            "2x15 Class A",
+            "4x10 Adam Eve",
+            "4x10 Silvercone",
+            "4x10 Session",
+            "4x12 Hiway",
+            "4x15 Big Boy",
+            "8x10 Classic",
+            "No Cabinet",
+        )),
+
+        controls,
+        init_controls,
+
+        ..pod2_config
+    }
+});
+
+pub static BASS_POD_PRO_CONFIG: Lazy<Config> = Lazy::new(|| {
+    let bass_pod_config = BASS_POD_CONFIG.clone();
+
+    let pro_controls: HashMap<String, Control> = convert_args!(hashmap!(
+        "digiout_gain" => RangeControl { cc: 9, addr: 35,
+            config: short!(),
+            format: Format::Data(FormatData { k: 12.0/63.0, b: 0.0, format: "{val:1.2f} db".into()}),
+        }
+    ));
+    let controls = bass_pod_config.controls.into_iter()
+        .chain(pro_controls)
+        .collect();
+
+    Config {
+        name: "Bass POD Pro".to_string(),
+        family: 0x0000,
+        member: 0x0700,
+
+        controls,
+
+        ..bass_pod_config
+    }
+});
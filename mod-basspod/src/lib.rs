@@ -0,0 +1,26 @@
+mod config;
+#[cfg(feature = "gtk")]
+mod module;
+
+#[cfg(feature = "gtk")]
+pub use module::*;
+
+use pod_core::emulator::{EmulatedDevice, Pod2Emulator};
+use pod_core::handler::BoxedHandler;
+use pod_core::model::Config;
+use pod_mod_pod2::Pod2Handler;
+
+/// Device configs supported by this module
+pub fn configs() -> Vec<Config> {
+    vec![config::BASS_POD_CONFIG.clone(), config::BASS_POD_PRO_CONFIG.clone()]
+}
+
+/// Create a handler for a device with the given `config`
+pub fn handler(_config: &'static Config) -> BoxedHandler {
+    Box::new(Pod2Handler)
+}
+
+/// Create a software emulator of a device with the given `config`
+pub fn emulator(config: &'static Config) -> Box<dyn EmulatedDevice> {
+    Box::new(Pod2Emulator::new(config, handler(config)))
+}
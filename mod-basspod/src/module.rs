@@ -0,0 +1,103 @@
+use std::sync::{Arc, Mutex};
+use pod_core::edit::EditBuffer;
+use pod_core::model::Config;
+use pod_core::store::{Signal, StoreSetIm};
+use pod_core::store::Origin::MIDI;
+use pod_gtk::prelude::*;
+use gtk::{Builder, Widget};
+use pod_core::emulator::EmulatedDevice;
+use pod_core::handler::BoxedHandler;
+use pod_mod_pod2::wiring::*;
+
+use crate::config::*;
+
+pub struct BassPodModule;
+
+impl Module for BassPodModule {
+    fn config(&self) -> Box<[Config]> {
+        crate::configs().into_boxed_slice()
+    }
+
+    fn init(&self, config: &'static Config) -> Box<dyn Interface> {
+        Box::new(BassPodInterface::new(config))
+    }
+
+    fn handler(&self, config: &'static Config) -> BoxedHandler {
+        crate::handler(config)
+    }
+
+    fn emulator(&self, config: &'static Config) -> Option<Box<dyn EmulatedDevice>> {
+        Some(crate::emulator(config))
+    }
+}
+
+struct BassPodInterface {
+    config: &'static Config,
+    widget: Widget,
+    objects: ObjectList
+}
+
+impl BassPodInterface {
+    fn new(config: &'static Config) -> Self {
+        let builder = Builder::from_string(include_str!("bass-pod.glade"));
+        let objects = ObjectList::new(&builder);
+
+        let widow: gtk::Window = builder.object("app_win").unwrap();
+        let widget = widow.child().unwrap();
+        widow.remove(&widget);
+
+        Self { config, widget, objects }
+    }
+}
+
+impl Interface for BassPodInterface {
+    fn widget(&self) -> Widget {
+        self.widget.clone()
+    }
+
+    fn objects(&self) -> ObjectList {
+        self.objects.clone()
+    }
+
+    fn wire(&self, edit: Arc<Mutex<EditBuffer>>, callbacks: &mut Callbacks) -> anyhow::Result<()> {
+        let config = self.config;
+        let controller = edit.lock().unwrap().controller();
+        {
+            let controller = controller.lock().unwrap();
+
+            init_combo(&controller, &self.objects,
+                       "cab_select", &config.cab_models, |s| s.as_str() )?;
+            init_combo(&controller, &self.objects,
+                       "amp_select", &config.amp_models, |amp| amp.name.as_str() )?;
+            init_combo(&controller, &self.objects,
+                       "effect_select", &config.effects, |eff| eff.name.as_str() )?;
+        }
+
+        wire(controller.clone(), &self.objects, callbacks)?;
+
+        // bass amps have no presence or bright switch, so no `wire_amp_select`
+        wire_toggles("toggles", &config.toggles,
+                     controller.clone(), &self.objects, callbacks)?;
+        wire_14bit(controller.clone(), &self.objects, callbacks,
+                   "delay_time", "delay_time:msb", "delay_time:lsb",
+                   false)?;
+        wire_effect_select(config, controller, callbacks)?;
+        wire_name_change(edit, config, &self.objects, callbacks)?;
+
+        Ok(())
+    }
+
+    fn init(&self, edit: Arc<Mutex<EditBuffer>>) -> anyhow::Result<()> {
+        let controller = edit.lock().unwrap().controller();
+        controller.set_full("reverb_type", 0, MIDI, Signal::Force);
+
+        let digiout_enable = self.config.member == BASS_POD_PRO_CONFIG.member;
+        controller.set_full("digiout_show", digiout_enable as u16, MIDI, Signal::Force);
+
+        Ok(())
+    }
+}
+
+pub fn module() -> impl Module {
+    BassPodModule
+}
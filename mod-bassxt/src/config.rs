@@ -501,6 +501,16 @@ pub static BASS_PODXT_CONFIG: Lazy<Config> = Lazy::new(|| {
             "8x10 Classic",
         )),
         effects: vec![], // not used
+        select_labels: hashmap!(
+            "mic_select".into() => MIC_NAMES.to_vec(),
+            "stomp_select".into() => STOMP_CONFIG.iter().map(|c| c.name.clone()).collect(),
+            "mod_select".into() => MOD_CONFIG.iter().map(|c| c.name.clone()).collect(),
+            "mod_note_select".into() => NOTE_NAMES.to_vec(),
+            "delay_select".into() => DELAY_CONFIG.iter().map(|c| c.name.clone()).collect(),
+            "delay_note_select".into() => NOTE_NAMES.to_vec(),
+            "tweak_param_select".into() => TWEAK_PARAM_NAMES.to_vec(),
+            "pedal_assign_select".into() => PEDAL_ASSIGN_NAMES.to_vec(),
+        ),
 
         toggles: convert_args!(vec!(
             toggle("noise_gate_enable").non_moving(0),
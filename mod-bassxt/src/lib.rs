@@ -1,5 +1,33 @@
 mod config;
+#[cfg(feature = "gtk")]
 mod module;
+#[cfg(feature = "gtk")]
 mod wiring;
 
+#[cfg(feature = "gtk")]
 pub use module::*;
+
+use pod_core::emulator::EmulatedDevice;
+use pod_core::handler::BoxedHandler;
+use pod_core::model::Config;
+use pod_mod_xt::emulator::PodXtEmulator;
+use pod_mod_xt::handler::PodXtHandler;
+
+/// Device configs supported by this module
+pub fn configs() -> Vec<Config> {
+    vec![
+        config::BASS_PODXT_CONFIG.clone(),
+        config::BASS_PODXT_PRO_CONFIG.clone(),
+        config::BASS_PODXT_LIVE_CONFIG.clone(),
+    ]
+}
+
+/// Create a handler for a device with the given `config`
+pub fn handler(config: &'static Config) -> BoxedHandler {
+    Box::new(PodXtHandler::new(config, false))
+}
+
+/// Create a software emulator of a device with the given `config`
+pub fn emulator(config: &'static Config) -> Box<dyn EmulatedDevice> {
+    Box::new(PodXtEmulator::new(config, handler(config), None))
+}
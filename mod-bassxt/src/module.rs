@@ -4,10 +4,11 @@ use pod_core::model::Config;
 use pod_core::store::{Signal, StoreSetIm};
 use pod_gtk::prelude::*;
 use gtk::{Builder, Widget};
+use pod_core::emulator::EmulatedDevice;
 use pod_core::handler::BoxedHandler;
 use pod_core::store::Origin::MIDI;
 use pod_mod_pod2::wiring::*;
-use pod_mod_xt::handler::PodXtHandler;
+use pod_mod_xt::l6t::{import_l6t, L6T_EXT};
 use pod_mod_xt::widgets::Tuner;
 use pod_mod_xt::wiring::{*, init_combo};
 
@@ -18,11 +19,7 @@ struct BassPodXtModule;
 
 impl Module for BassPodXtModule {
     fn config(&self) -> Box<[Config]> {
-        vec![
-            config::BASS_PODXT_CONFIG.clone(),
-            config::BASS_PODXT_PRO_CONFIG.clone(),
-            config::BASS_PODXT_LIVE_CONFIG.clone(),
-        ].into_boxed_slice()
+        crate::configs().into_boxed_slice()
     }
 
     fn init(&self, config: &'static Config) -> Box<dyn Interface> {
@@ -30,7 +27,23 @@ impl Module for BassPodXtModule {
     }
 
     fn handler(&self, config: &'static Config) -> BoxedHandler {
-        Box::new(PodXtHandler::new(config, false))
+        crate::handler(config)
+    }
+
+    fn emulator(&self, config: &'static Config) -> Option<Box<dyn EmulatedDevice>> {
+        Some(crate::emulator(config))
+    }
+
+    fn import_extensions(&self) -> &[&str] {
+        &[L6T_EXT]
+    }
+
+    fn import(&self, config: &'static Config, ext: &str, bytes: &[u8], base: &[u8]) -> anyhow::Result<(Vec<u8>, Vec<String>)> {
+        if ext != L6T_EXT {
+            anyhow::bail!("Importing {:?} files is not supported", ext);
+        }
+        let handler = self.handler(config);
+        import_l6t(config, handler.as_ref(), bytes, base)
     }
 }
 
@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+use maplit::*;
+use once_cell::sync::Lazy;
+use pod_core::model::*;
+
+pub static FLOOR_POD_PLUS_CONFIG: Lazy<Config> = Lazy::new(|| {
+    // Floor POD Plus has the POD 2.0 amp and cab models and is handled
+    // much like a Pocket POD, so assume the same program layout
+    let pod2_config = pod_mod_pod2::configs()[0].clone();
+    let exclude = ["digiout_show"];
+
+    let floor_pod_controls: HashMap<String, Control> = convert_args!(hashmap!(
+        // the built-in pedal switches to wah with a toe switch, which is
+        // a MIDI-only control and is not present in the program data
+        "wah_enable" => MidiSwitchControl { cc: 43 },
+    ));
+    let controls = pod2_config.controls.into_iter()
+        .filter(|(k, _)| !exclude.contains(&k.as_str()))
+        .chain(floor_pod_controls)
+        .collect();
+
+    let floor_pod_init_controls = convert_args!(vec!(
+        "wah_enable"
+    ));
+    let init_controls = pod2_config.init_controls.into_iter()
+        .filter(|v| !exclude.contains(&v.as_str()))
+        .chain(floor_pod_init_controls)
+        .collect();
+
+    Config {
+        name: "Floor POD Plus".to_string(),
+        // TODO: member id is not confirmed by a device inquiry reply
+        //       from a real device
+        family: 0x0000,
+        member: 0x0800,
+
+        // 16 banks of 4 channels, 1A .. 16D
+        program_num: 64,
+        // PC 37 is a program, there is no tuner PC
+        pc_tuner: None,
+        flags: DeviceFlags::MODIFIED_BUFFER_PC_AND_EDIT_BUFFER,
+
+        controls,
+        init_controls,
+        toggles: vec![], // Floor POD Plus doesn't use dynamic toggle positioning
+
+        ..pod2_config
+    }
+});
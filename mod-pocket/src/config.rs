@@ -2,7 +2,6 @@ use std::collections::HashMap;
 use maplit::*;
 use once_cell::sync::Lazy;
 use pod_core::model::*;
-use pod_gtk::*;
 
 #[cfg(all(windows, not(feature = "winrt")))]
 const MIDI_QUIRKS: MidiQuirks = MidiQuirks::MIDI_CLOSE_QUIET_TIMEOUT;
@@ -11,7 +10,7 @@ const MIDI_QUIRKS: MidiQuirks = MidiQuirks::MIDI_CLOSE_QUIET_TIMEOUT;
 const MIDI_QUIRKS: MidiQuirks = MidiQuirks::empty();
 
 pub static CONFIG: Lazy<Config> = Lazy::new(|| {
-    let pod2_config = pod_mod_pod2::module().config()[0].clone();
+    let pod2_config = pod_mod_pod2::configs()[0].clone();
     let exclude = vec!["digiout_show", "vol_pedal_position"];
 
     let pocket_pod_controls: HashMap<String, Control> = convert_args!(hashmap!(
@@ -4,9 +4,9 @@ use pod_core::model::Config;
 use pod_core::store::{Signal, StoreSetIm};
 use pod_gtk::prelude::*;
 use gtk::{Builder, Widget};
+use pod_core::emulator::EmulatedDevice;
 use pod_core::handler::BoxedHandler;
 use pod_core::store::Origin::MIDI;
-use pod_mod_pod2::Pod2Handler;
 use pod_mod_pod2::wiring::*;
 
 use crate::config;
@@ -15,15 +15,19 @@ pub struct PocketPodModule;
 
 impl Module for PocketPodModule {
     fn config(&self) -> Box<[Config]> {
-        vec![config::CONFIG.clone()].into_boxed_slice()
+        crate::configs().into_boxed_slice()
     }
 
     fn init(&self, config: &'static Config) -> Box<dyn Interface> {
         Box::new(PocketPodInterface::new(config))
     }
 
-    fn handler(&self, _config: &'static Config) -> BoxedHandler {
-        Box::new(Pod2Handler)
+    fn handler(&self, config: &'static Config) -> BoxedHandler {
+        crate::handler(config)
+    }
+
+    fn emulator(&self, config: &'static Config) -> Option<Box<dyn EmulatedDevice>> {
+        Some(crate::emulator(config))
     }
 }
 
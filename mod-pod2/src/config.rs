@@ -211,6 +211,7 @@ pub static POD2_CONFIG: Lazy<Config> = Lazy::new(|| {
                // no delay!
            )
         ],
+        select_labels: HashMap::new(),
         controls: convert_args!(hashmap!(
            // switches
            "distortion_enable" => SwitchControl { cc: 25, addr: 0, ..def!() },
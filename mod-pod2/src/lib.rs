@@ -1,6 +1,29 @@
 mod config;
+#[cfg(feature = "gtk")]
 mod module;
+#[cfg(feature = "gtk")]
 pub mod wiring;
 pub mod handler;
 
+#[cfg(feature = "gtk")]
 pub use module::*;
+pub use handler::Pod2Handler;
+
+use pod_core::emulator::{EmulatedDevice, Pod2Emulator};
+use pod_core::handler::BoxedHandler;
+use pod_core::model::Config;
+
+/// Device configs supported by this module
+pub fn configs() -> Vec<Config> {
+    vec![config::POD2_CONFIG.clone(), config::PODPRO_CONFIG.clone(), config::POD_CONFIG.clone()]
+}
+
+/// Create a handler for a device with the given `config`
+pub fn handler(_config: &'static Config) -> BoxedHandler {
+    Box::new(Pod2Handler)
+}
+
+/// Create a software emulator of a device with the given `config`
+pub fn emulator(config: &'static Config) -> Box<dyn EmulatedDevice> {
+    Box::new(Pod2Emulator::new(config, handler(config)))
+}
@@ -5,25 +5,29 @@ use pod_core::store::{Signal, StoreSetIm};
 use pod_core::store::Origin::MIDI;
 use pod_gtk::prelude::*;
 use gtk::{Builder, Widget};
+use pod_core::emulator::EmulatedDevice;
 use pod_core::handler::BoxedHandler;
 
 use crate::wiring::*;
 use crate::config::*;
-pub use crate::handler::Pod2Handler;
 
 pub struct Pod2Module;
 
 impl Module for Pod2Module {
     fn config(&self) -> Box<[Config]> {
-        vec![POD2_CONFIG.clone(), PODPRO_CONFIG.clone(), POD_CONFIG.clone()].into_boxed_slice()
+        crate::configs().into_boxed_slice()
     }
 
     fn init(&self, config: &'static Config) -> Box<dyn Interface> {
         Box::new(Pod2Interface::new(config))
     }
 
-    fn handler(&self, _config: &'static Config) -> BoxedHandler {
-        Box::new(Pod2Handler)
+    fn handler(&self, config: &'static Config) -> BoxedHandler {
+        crate::handler(config)
+    }
+
+    fn emulator(&self, config: &'static Config) -> Option<Box<dyn EmulatedDevice>> {
+        Some(crate::emulator(config))
     }
 }
 
@@ -4,8 +4,7 @@ use once_cell::sync::Lazy;
 use pod_core::builders::shorthand::*;
 use pod_core::def;
 use pod_core::model::*;
-use pod_gtk::prelude::*;
-use glib::bitflags::bitflags;
+use bitflags::bitflags;
 
 use pod_mod_pod2::{short, long, steps, fmt_percent};
 use crate::model::*;
@@ -633,6 +632,18 @@ pub static PODXT_CONFIG: Lazy<Config> = Lazy::new(|| {
             "BX-8x10 Classic",
         )),
         effects: vec![], // not used
+        select_labels: hashmap!(
+            "mic_select".into() => MIC_NAMES.to_vec(),
+            "reverb_select".into() => REVERB_NAMES.to_vec(),
+            "stomp_select".into() => STOMP_CONFIG.iter().map(|c| c.name.clone()).collect(),
+            "mod_select".into() => MOD_CONFIG.iter().map(|c| c.name.clone()).collect(),
+            "mod_note_select".into() => NOTE_NAMES.to_vec(),
+            "delay_select".into() => DELAY_CONFIG.iter().map(|c| c.name.clone()).collect(),
+            "delay_note_select".into() => NOTE_NAMES.to_vec(),
+            "wah_select".into() => WAH_NAMES.to_vec(),
+            "tweak_param_select".into() => TWEAK_PARAM_NAMES.to_vec(),
+            "pedal_assign_select".into() => PEDAL_ASSIGN_NAMES.to_vec(),
+        ),
 
         toggles: convert_args!(vec!(
             toggle("noise_gate_enable").non_moving(0),
@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+use log::*;
+use pod_core::controller::*;
+use pod_core::emulator::{EmulatedDevice, pc_program};
+use pod_core::handler::BoxedHandler;
+use pod_core::midi::{Channel, MidiMessage};
+use pod_core::model::{AbstractControl, Config};
+use pod_core::program_id_string;
+
+/// Number of effect setups in the PODxt memory, requested as patches
+/// `0x0200 | n`
+const EFFECTS_NUM: usize = 64;
+
+/// Emulator of the PODxt family devices speaking the PODxt sysex
+/// protocol: PODxt, Bass PODxt and their Pro/Live variants
+pub struct PodXtEmulator {
+    config: &'static Config,
+    handler: BoxedHandler,
+    controller: Controller,
+    channel: u8,
+    /// Installed packs reported on request, `None` if the device
+    /// does not answer installed packs requests
+    packs: Option<u8>,
+    /// Reject all stores with a failed store status
+    store_failure: bool,
+    edit: Vec<u8>,
+    edited: bool,
+    program: usize,
+    programs: Vec<u8>,
+    effects: Vec<u8>,
+    /// Patch dumps received from the host, committed to the program
+    /// memory on `XtPatchDumpEnd`
+    pending: BTreeMap<u16, Vec<u8>>,
+    tuner_note: u16,
+    tuner_offset: u16,
+}
+
+fn named_buffers<F>(config: &Config, num: usize, name: F) -> Vec<u8>
+    where F: Fn(usize) -> String
+{
+    let mut data = vec![0u8; config.program_size * num];
+    for (i, buffer) in data.chunks_mut(config.program_size).enumerate() {
+        let name = format!("{:1$}", name(i), config.program_name_length);
+        let addr = config.program_name_addr;
+        buffer[addr .. addr + config.program_name_length]
+            .copy_from_slice(&name.as_bytes()[.. config.program_name_length]);
+    }
+    data
+}
+
+impl PodXtEmulator {
+    pub fn new(config: &'static Config, handler: BoxedHandler, packs: Option<u8>) -> Self {
+        let controller = Controller::new(config.controls.clone());
+        let programs = named_buffers(config, config.program_num,
+                                     |i| format!("Program {}", program_id_string(i)));
+        let effects = named_buffers(config, EFFECTS_NUM, |i| format!("Effect {}", i + 1));
+        let edit = programs[.. config.program_size].to_vec();
+
+        Self {
+            config, handler, controller, packs,
+            channel: Channel::num(0),
+            store_failure: false,
+            edit,
+            edited: false,
+            program: 0,
+            programs,
+            effects,
+            pending: BTreeMap::new(),
+            tuner_note: 9, // A
+            tuner_offset: 0
+        }
+    }
+
+    /// Make the device answer all following stores with a failed
+    /// store status, discarding the stored data
+    pub fn set_store_failure(&mut self, failure: bool) {
+        self.store_failure = failure;
+    }
+
+    pub fn set_installed_packs(&mut self, packs: Option<u8>) {
+        self.packs = packs;
+    }
+
+    /// Set the note and the offset reported by the tuner
+    pub fn set_tuner(&mut self, note: u16, offset: i16) {
+        self.tuner_note = note;
+        self.tuner_offset = offset as u16;
+    }
+
+    pub fn program(&self) -> usize {
+        self.program
+    }
+
+    pub fn edited(&self) -> bool {
+        self.edited
+    }
+
+    /// Data of the program or effect setup `patch` as addressed by
+    /// `XtPatchDumpRequest` and `XtPatchDump` messages
+    pub fn patch(&self, patch: u16) -> Option<&[u8]> {
+        let (is_effect, range) = self.location(patch);
+        let memory = if is_effect { &self.effects } else { &self.programs };
+        memory.get(range)
+    }
+
+    fn patch_mut(&mut self, patch: u16) -> Option<&mut [u8]> {
+        let (is_effect, range) = self.location(patch);
+        let memory = if is_effect { &mut self.effects } else { &mut self.programs };
+        memory.get_mut(range)
+    }
+
+    /// Whether `patch` is an effect setup and its location in memory
+    fn location(&self, patch: u16) -> (bool, Range<usize>) {
+        let size = self.config.program_size;
+        let (is_effect, n) = if patch & 0x0200 != 0 {
+            (true, (patch & 0xff) as usize)
+        } else {
+            (false, patch as usize)
+        };
+        (is_effect, n * size .. (n + 1) * size)
+    }
+
+    fn id(&self) -> u8 {
+        self.config.member as u8
+    }
+
+    fn is_our_channel(&self, channel: u8) -> bool {
+        channel == self.channel || channel == Channel::all()
+    }
+
+    fn control_change(&mut self, cc: u8, value: u8) {
+        let Some((name, control)) = self.config.cc_to_control(cc) else {
+            warn!("PODxt emulator: control for CC={} not defined!", cc);
+            return;
+        };
+        let value = control.value_from_midi(value);
+        self.controller.set(name, value, StoreOrigin::NONE);
+        self.handler.control_value_to_buffer(&self.controller, name, &mut self.edit);
+        if control.get_addr().is_some() {
+            self.edited = true;
+        }
+    }
+
+    fn program_change(&mut self, program: u8) {
+        let Some(program) = pc_program(self.config, program) else {
+            return;
+        };
+        if let Some(data) = self.patch(program as u16) {
+            self.edit = data.to_vec();
+            self.program = program;
+            self.edited = false;
+        }
+    }
+
+    fn store(&mut self) -> Vec<MidiMessage> {
+        if self.pending.is_empty() {
+            return vec![];
+        }
+        let pending = std::mem::take(&mut self.pending);
+        if self.store_failure {
+            return vec![MidiMessage::XtStoreStatus { success: false }];
+        }
+
+        for (patch, data) in pending {
+            match self.patch_mut(patch) {
+                Some(buffer) => buffer.copy_from_slice(&data),
+                None => {
+                    warn!("PODxt emulator: patch {:#06x} out of bounds", patch);
+                    continue;
+                }
+            }
+            if patch as usize == self.program {
+                self.edited = false;
+            }
+        }
+        vec![MidiMessage::XtStoreStatus { success: true }]
+    }
+}
+
+impl EmulatedDevice for PodXtEmulator {
+    fn config(&self) -> &'static Config {
+        self.config
+    }
+
+    fn message(&mut self, msg: MidiMessage) -> Vec<MidiMessage> {
+        let size = self.config.program_size;
+        match msg {
+            MidiMessage::UniversalDeviceInquiry { channel } if self.is_our_channel(channel) => {
+                // PODxt reports its version as binary digits
+                vec![MidiMessage::UniversalDeviceInquiryResponse {
+                    channel: self.channel,
+                    family: self.config.family,
+                    member: self.config.member,
+                    ver: "\u{0}\u{3}\u{0}\u{2}".to_string()
+                }]
+            }
+            MidiMessage::ControlChange { channel, control, value } if self.is_our_channel(channel) => {
+                self.control_change(control, value);
+                vec![]
+            }
+            MidiMessage::ProgramChange { channel, program } if self.is_our_channel(channel) => {
+                self.program_change(program);
+                vec![]
+            }
+            MidiMessage::XtInstalledPacksRequest => {
+                self.packs.map(|packs| MidiMessage::XtInstalledPacks { packs })
+                    .into_iter().collect()
+            }
+            MidiMessage::XtEditBufferDumpRequest => {
+                vec![MidiMessage::XtBufferDump { id: self.id(), data: self.edit.clone() }]
+            }
+            MidiMessage::XtPatchDumpRequest { patch } => {
+                // PODxt answers a patch dump request with a buffer dump
+                match self.patch(patch) {
+                    Some(data) => vec![
+                        MidiMessage::XtBufferDump { id: self.id(), data: data.to_vec() },
+                        MidiMessage::XtPatchDumpEnd
+                    ],
+                    None => {
+                        warn!("PODxt emulator: patch {:#06x} out of bounds", patch);
+                        vec![]
+                    }
+                }
+            }
+            MidiMessage::XtBufferDump { data, .. } => {
+                if data.len() == size {
+                    self.edit = data;
+                    self.edited = true;
+                } else {
+                    error!("PODxt emulator: buffer size mismatch: expected {}, got {}", size, data.len());
+                }
+                vec![]
+            }
+            MidiMessage::XtPatchDump { patch, data, .. } => {
+                if data.len() == size {
+                    self.pending.insert(patch, data);
+                } else {
+                    error!("PODxt emulator: patch size mismatch: expected {}, got {}", size, data.len());
+                }
+                vec![]
+            }
+            MidiMessage::XtPatchDumpEnd => {
+                self.store()
+            }
+            MidiMessage::XtTunerNoteRequest => {
+                vec![MidiMessage::XtTunerNote { note: self.tuner_note }]
+            }
+            MidiMessage::XtTunerOffsetRequest => {
+                vec![MidiMessage::XtTunerOffset { offset: self.tuner_offset }]
+            }
+            MidiMessage::XtProgramNumberRequest => {
+                vec![MidiMessage::XtProgramNumber { program: self.program as u16 }]
+            }
+            MidiMessage::XtProgramEditStateRequest => {
+                vec![MidiMessage::XtProgramEditState { edited: self.edited }]
+            }
+            msg => {
+                debug!("PODxt emulator: ignoring {:?}", msg);
+                vec![]
+            }
+        }
+    }
+}
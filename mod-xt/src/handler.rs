@@ -578,6 +578,18 @@ impl Handler for PodXtHandler {
         buffer[addr as usize] = value;
     }
 
+    // PODxt has different mics for the guitar and the BX bass cabs
+    fn select_labels(&self, config: &Config, name: &str, controller: &Controller) -> Option<Vec<String>> {
+        if !self.has_xt_packs || name != "mic_select" {
+            return config.select_labels(name);
+        }
+        let is_bx = controller.get("cab_select")
+            .and_then(|cab| config.cab_models.get(cab as usize))
+            .is_some_and(|cab_name| cab_name.starts_with("BX-"));
+        let mics = if is_bx { &crate::config::BX_MIC_NAMES } else { &crate::config::MIC_NAMES };
+        Some(mics.to_vec())
+    }
+
 
 }
 
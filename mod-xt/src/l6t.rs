@@ -0,0 +1,220 @@
+//! Line 6 Edit `.l6t` tone file import.
+//!
+//! A `.l6t` file is an IFF-style container (big-endian chunk sizes,
+//! chunks padded to an even length) with a top-level `FORM` of type
+//! `L6PA`. The tone data is stored in `MODL` chunks, one per processing
+//! block, each holding a `model`, `slot` and `enabled` header (`u32` each)
+//! followed by a list of `param` (`u32`), `value` (`f32`) pairs. The
+//! tone name is stored in an `INAM` chunk of the `INFO` list.
+//!
+//! Block slots are mapped onto model select and enable controls,
+//! parameters are PODxt parameter numbers, same as MIDI CC numbers,
+//! with the value as sent over MIDI.
+
+use anyhow::*;
+use log::*;
+use pod_core::edit::EditBuffer;
+use pod_core::handler::Handler;
+use pod_core::model::{AbstractControl, Config};
+use pod_core::store::*;
+
+pub const L6T_EXT: &str = "l6t";
+
+#[derive(Clone, Debug, Default)]
+pub struct L6tModel {
+    pub model: u32,
+    pub slot: u32,
+    pub enabled: bool,
+    pub params: Vec<(u32, f32)>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct L6tPatch {
+    pub name: Option<String>,
+    pub models: Vec<L6tModel>,
+    /// Chunks that were found in the file, but could not be interpreted
+    pub unknown_chunks: Vec<String>,
+}
+
+/// Block slots: slot id, model select control, enable control
+const SLOTS: &[(u32, Option<&str>, Option<&str>)] = &[
+    (0, Some("amp_select"), Some("amp_enable")),
+    (1, Some("cab_select"), None),
+    (2, Some("mic_select"), None),
+    (3, Some("stomp_select"), Some("stomp_enable")),
+    (4, Some("mod_select"), Some("mod_enable")),
+    (5, Some("delay_select"), Some("delay_enable")),
+    (6, Some("reverb_select"), Some("reverb_enable")),
+    (7, Some("wah_select"), Some("wah_enable")),
+    (8, None, Some("compressor_enable")),
+    (9, None, Some("eq_enable")),
+    (10, None, Some("noise_gate_enable")),
+    (11, Some("effect_select"), None),
+];
+
+fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    data.get(pos .. pos + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn fourcc(data: &[u8]) -> String {
+    data.iter().map(|b| if b.is_ascii_graphic() { *b as char } else { '?' }).collect()
+}
+
+fn parse_chunks(data: &[u8], patch: &mut L6tPatch) -> Result<()> {
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let id = &data[pos .. pos + 4];
+        let size = read_u32(data, pos + 4).unwrap() as usize;
+        let start = pos + 8;
+        let end = start.checked_add(size)
+            .filter(|end| *end <= data.len())
+            .with_context(|| format!("Chunk {:?} size {} out of bounds", fourcc(id), size))?;
+        let chunk = &data[start .. end];
+
+        match id {
+            b"FORM" | b"LIST" | b"CAT " => {
+                // container chunk: 4-byte type followed by other chunks
+                if chunk.len() < 4 {
+                    bail!("Container chunk {:?} too short", fourcc(id));
+                }
+                parse_chunks(&chunk[4..], patch)?;
+            }
+            b"MODL" => {
+                let (Some(model), Some(slot), Some(enabled)) =
+                    (read_u32(chunk, 0), read_u32(chunk, 4), read_u32(chunk, 8)) else {
+                    bail!("MODL chunk too short");
+                };
+                let params = chunk[12..].chunks_exact(8)
+                    .map(|p| {
+                        let param = read_u32(p, 0).unwrap();
+                        let value = f32::from_bits(read_u32(p, 4).unwrap());
+                        (param, value)
+                    })
+                    .collect();
+                patch.models.push(L6tModel { model, slot, enabled: enabled != 0, params });
+            }
+            b"INAM" => {
+                let name = chunk.split(|b| *b == 0).next().unwrap_or_default();
+                patch.name = Some(String::from_utf8_lossy(name).to_string());
+            }
+            b"HEAD" | b"IAUT" | b"IGST" | b"IBND" | b"ISNG" | b"INOT" | b"ISFT" | b"ICMT" => {
+                // informational chunks, nothing to import
+            }
+            _ => {
+                patch.unknown_chunks.push(fourcc(id));
+            }
+        }
+
+        pos = end + (size & 1);
+    }
+
+    Ok(())
+}
+
+impl L6tPatch {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 12 || &bytes[0..4] != b"FORM" || &bytes[8..12] != b"L6PA" {
+            bail!("Not a Line 6 Edit tone file");
+        }
+
+        let mut patch = L6tPatch::default();
+        parse_chunks(bytes, &mut patch)?;
+        if patch.models.is_empty() {
+            bail!("No tone data found");
+        }
+
+        Ok(patch)
+    }
+}
+
+fn midi_value(value: f32) -> Option<u8> {
+    if value.is_finite() && (0.0 ..= 127.0).contains(&value) {
+        Some(value.round() as u8)
+    } else {
+        None
+    }
+}
+
+/// Load a `.l6t` tone file on top of the `base` edit buffer data using
+/// `handler` to write control values into the buffer. Returns the new
+/// edit buffer data and a list of tone file entries that could not be
+/// mapped onto the controls of `config`.
+pub fn import_l6t(config: &Config, handler: &dyn Handler,
+                  bytes: &[u8], base: &[u8]) -> Result<(Vec<u8>, Vec<String>)> {
+    let patch = L6tPatch::from_bytes(bytes)?;
+    if base.len() != config.program_size {
+        bail!("Program size mismatch: expected {}, got {}", config.program_size, base.len());
+    }
+
+    let mut unmapped = patch.unknown_chunks.iter()
+        .map(|id| format!("Unknown chunk {:?}", id))
+        .collect::<Vec<_>>();
+    // control values, as stored in the `Controller`
+    let mut values: Vec<(String, u16)> = vec![];
+
+    let mut set = |name: &str, value: u16, what: String, unmapped: &mut Vec<String>| {
+        if config.controls.contains_key(name) {
+            values.push((name.to_string(), value));
+        } else {
+            unmapped.push(what);
+        }
+    };
+    let from_midi = |name: &str, value: u8| {
+        config.controls.get(name)
+            .map(|control| control.value_from_midi(value))
+            .unwrap_or_default()
+    };
+
+    for model in patch.models.iter() {
+        let Some((_, select, enable)) = SLOTS.iter().find(|(slot, _, _)| *slot == model.slot) else {
+            unmapped.push(format!("Block in unknown slot {} (model {})", model.slot, model.model));
+            continue;
+        };
+        if let Some(select) = select {
+            match u8::try_from(model.model).ok().filter(|v| *v < 0x80) {
+                Some(v) => set(select, from_midi(select, v), format!("Model {} for {:?}", model.model, select), &mut unmapped),
+                None => unmapped.push(format!("Model {} for {:?}", model.model, select))
+            }
+        }
+        if let Some(enable) = enable {
+            set(enable, model.enabled as u16, format!("Enable {:?}", enable), &mut unmapped);
+        }
+
+        for (param, value) in model.params.iter() {
+            let control = u8::try_from(*param).ok()
+                .and_then(|cc| config.cc_to_control(cc));
+            let Some((name, _)) = control else {
+                unmapped.push(format!("Parameter {} = {}", param, value));
+                continue;
+            };
+            match midi_value(*value) {
+                Some(v) => set(name, from_midi(name, v), format!("Parameter {} = {}", param, value), &mut unmapped),
+                None => unmapped.push(format!("Parameter {:?} = {}: value out of range", name, value))
+            }
+        }
+    }
+
+    let mut edit = EditBuffer::new(config);
+    edit.raw_locked().copy_from_slice(base);
+    {
+        let mut controller = edit.controller_locked();
+        for (name, value) in values.iter() {
+            controller.set(name, *value, Origin::NONE);
+        }
+        let mut raw = edit.raw_locked();
+        for (name, _) in values.iter() {
+            handler.control_value_to_buffer(&controller, name, &mut raw);
+        }
+    }
+    if let Some(name) = patch.name.as_ref() {
+        edit.set_name(name);
+    }
+
+    for msg in unmapped.iter() {
+        warn!("L6T import: {}", msg);
+    }
+
+    let data = edit.raw_locked().to_vec();
+    Ok((data, unmapped))
+}
@@ -1,10 +1,41 @@
 pub mod config;
+#[cfg(feature = "gtk")]
 mod module;
+#[cfg(feature = "gtk")]
 pub mod wiring;
 pub mod model;
 pub mod builders;
 pub mod handler;
+#[cfg(feature = "gtk")]
 pub mod widgets;
 pub mod tuner;
+pub mod l6t;
+pub mod emulator;
 
+#[cfg(feature = "gtk")]
 pub use module::*;
+
+use pod_core::emulator::EmulatedDevice;
+use pod_core::handler::BoxedHandler;
+use pod_core::model::Config;
+use crate::emulator::PodXtEmulator;
+use crate::handler::PodXtHandler;
+
+/// Device configs supported by this module
+pub fn configs() -> Vec<Config> {
+    vec![
+        config::PODXT_CONFIG.clone(),
+        config::PODXT_PRO_CONFIG.clone(),
+        config::PODXT_LIVE_CONFIG.clone(),
+    ]
+}
+
+/// Create a handler for a device with the given `config`
+pub fn handler(config: &'static Config) -> BoxedHandler {
+    Box::new(PodXtHandler::new(config, true))
+}
+
+/// Create a software emulator of a device with the given `config`
+pub fn emulator(config: &'static Config) -> Box<dyn EmulatedDevice> {
+    Box::new(PodXtEmulator::new(config, handler(config), Some(0x0f)))
+}
@@ -4,12 +4,13 @@ use pod_core::model::Config;
 use pod_core::store::{Signal, StoreSetIm};
 use pod_gtk::prelude::*;
 use gtk::{Builder, Widget};
+use pod_core::emulator::EmulatedDevice;
 use pod_core::handler::BoxedHandler;
 use pod_core::store::Origin::MIDI;
 use pod_mod_pod2::wiring::*;
+use crate::l6t::{import_l6t, L6T_EXT};
 
 use crate::config;
-use crate::handler::PodXtHandler;
 use crate::widgets::Tuner;
 use crate::wiring::{*, init_combo};
 
@@ -17,11 +18,7 @@ struct PodXtModule;
 
 impl Module for PodXtModule {
     fn config(&self) -> Box<[Config]> {
-        vec![
-            config::PODXT_CONFIG.clone(),
-            config::PODXT_PRO_CONFIG.clone(),
-            config::PODXT_LIVE_CONFIG.clone(),
-        ].into_boxed_slice()
+        crate::configs().into_boxed_slice()
     }
 
     fn init(&self, config: &'static Config) -> Box<dyn Interface> {
@@ -29,7 +26,23 @@ impl Module for PodXtModule {
     }
 
     fn handler(&self, config: &'static Config) -> BoxedHandler {
-        Box::new(PodXtHandler::new(config, true))
+        crate::handler(config)
+    }
+
+    fn emulator(&self, config: &'static Config) -> Option<Box<dyn EmulatedDevice>> {
+        Some(crate::emulator(config))
+    }
+
+    fn import_extensions(&self) -> &[&str] {
+        &[L6T_EXT]
+    }
+
+    fn import(&self, config: &'static Config, ext: &str, bytes: &[u8], base: &[u8]) -> anyhow::Result<(Vec<u8>, Vec<String>)> {
+        if ext != L6T_EXT {
+            anyhow::bail!("Importing {:?} files is not supported", ext);
+        }
+        let handler = self.handler(config);
+        import_l6t(config, handler.as_ref(), bytes, base)
     }
 }
 
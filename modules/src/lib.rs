@@ -0,0 +1,53 @@
+//! Registry of all device modules without the GTK user interface, shared
+//! by the headless front-ends, such as `pod-cli` and the test harness.
+//! The USB device access of the `usb` feature is shared by all front-ends.
+use std::sync::Once;
+use pod_core::config::{config_base, register_config};
+use pod_core::handler::BoxedHandler;
+use pod_core::model::Config;
+
+pub mod usb;
+
+/// A device module without the GTK user interface: device configs and
+/// a handler constructor
+struct Module {
+    configs: fn() -> Vec<Config>,
+    handler: fn(&'static Config) -> BoxedHandler,
+}
+
+const MODULES: &[Module] = &[
+    Module { configs: pod_mod_pod2::configs, handler: pod_mod_pod2::handler },
+    Module { configs: pod_mod_pocket::configs, handler: pod_mod_pocket::handler },
+    Module { configs: pod_mod_xt::configs, handler: pod_mod_xt::handler },
+    Module { configs: pod_mod_bassxt::configs, handler: pod_mod_bassxt::handler },
+    Module { configs: pod_mod_basspod::configs, handler: pod_mod_basspod::handler },
+    Module { configs: pod_mod_floorpod::configs, handler: pod_mod_floorpod::handler },
+];
+
+static REGISTER: Once = Once::new();
+
+/// Register the device configs of all modules. Only the first call
+/// registers anything, so that configs loaded from definition files
+/// later on are not replaced.
+pub fn register_modules() {
+    REGISTER.call_once(|| {
+        for module in MODULES.iter() {
+            for config in (module.configs)().iter() {
+                register_config(config);
+            }
+        }
+    });
+}
+
+/// Create the handler of the module providing `config`, or the config
+/// it is based on
+pub fn handler_for_config(config: &'static Config) -> Option<BoxedHandler> {
+    let module = MODULES.iter()
+        .find(|module| (module.configs)().contains(config))
+        .or_else(|| {
+            // configs loaded from definition files are handled by their base config's module
+            let base = config_base(config)?;
+            MODULES.iter().find(|module| (module.configs)().contains(base))
+        })?;
+    Some((module.handler)(config))
+}
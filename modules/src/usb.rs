@@ -1,3 +1,5 @@
+//! USB device access for the front-ends. Without the `usb` feature, no
+//! USB devices are listed and opening one fails.
 
 #[cfg(feature = "usb")]
 pub use imp::*;
@@ -66,6 +68,7 @@ mod imp {
     }
 }
 
+#[cfg(not(feature = "usb"))]
 mod nop {
     use anyhow::*;
     use pod_core::midi_io::{AutodetectResult, MidiInPort, MidiOutPort};
@@ -77,16 +80,16 @@ mod nop {
         vec![]
     }
 
-    pub fn usb_open_addr(_addr: &str) -> Result<(MidiInPort, MidiOutPort)> {
-        unimplemented!()
+    pub fn usb_open_addr(addr: &str) -> Result<(MidiInPort, MidiOutPort)> {
+        bail!("Cannot open USB device {:?}: built without USB support", addr)
     }
 
-    pub fn usb_open_name(_addr: &str) -> Result<(MidiInPort, MidiOutPort)> {
-        unimplemented!()
+    pub fn usb_open_name(name: &str) -> Result<(MidiInPort, MidiOutPort)> {
+        bail!("Cannot open USB device {:?}: built without USB support", name)
     }
 
     pub async fn autodetect() -> Result<AutodetectResult> {
-        unimplemented!()
+        bail!("Built without USB support")
     }
 
     pub const fn autodetect_supported() -> bool {
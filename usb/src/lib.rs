@@ -138,7 +138,9 @@ pub fn usb_device_for_address(dev_addr: &str) -> Result<(impl MidiIn, impl MidiO
     let port_id_re = Regex::new(r"\d+:\d+").unwrap();
 
     let found;
-    if port_id_re.is_match(dev_addr) {
+    if devices.values().any(|dev| dev.name == dev_addr) {
+        found = devices.values_mut().find(|dev| dev.name == dev_addr);
+    } else if port_id_re.is_match(dev_addr) {
         found = devices.get_mut(dev_addr);
     } else if port_n_re.is_match(dev_addr) {
         let n = usize::from_str(&dev_addr)